@@ -0,0 +1,137 @@
+//! Cost of generating the planet on the CPU, without a GPU. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use planet_placer::planet::{
+    self, icosphere, ElevationParams, GenerationParams, PlanetData, Shading,
+};
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use std::hint::black_box;
+
+const SUBDIVISIONS: std::ops::RangeInclusive<usize> = 3..=7;
+const PLATES: [usize; 3] = [10, 40, 160];
+/// Subdivisions the plate counts are compared at
+const PLATE_SUBDIVISIONS: usize = 5;
+const SEED: u64 = 0;
+
+/// Prints the size of the mesh at every subdivision level, the timings are per planet of
+/// that size
+fn report_sizes() {
+    for subdivisions in SUBDIVISIONS {
+        let (vertices, indices) = icosphere::subdivided(subdivisions);
+        println!(
+            "subdivisions {subdivisions}: {} vertices, {} triangles",
+            vertices.len(),
+            indices.len() / 3
+        );
+    }
+}
+
+fn create_regions(c: &mut Criterion) {
+    report_sizes();
+    let mut group = c.benchmark_group("create_regions");
+    group.sample_size(10);
+    for subdivisions in SUBDIVISIONS {
+        group.throughput(Throughput::Elements(20 * 4u64.pow(subdivisions as u32)));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subdivisions),
+            &subdivisions,
+            |b, &subdivisions| b.iter(|| planet::create_regions(black_box(subdivisions))),
+        );
+    }
+    group.finish();
+}
+
+fn cluster_regions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cluster_regions");
+    group.sample_size(10);
+    let params = GenerationParams::default();
+    for subdivisions in SUBDIVISIONS {
+        let (regions, adjacency, _) = planet::create_regions(subdivisions);
+        group.throughput(Throughput::Elements(regions.len() as u64));
+        for num_plates in PLATES {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{num_plates} plates"), subdivisions),
+                &num_plates,
+                |b, &num_plates| {
+                    b.iter(|| {
+                        let mut rng = Pcg32::seed_from_u64(SEED);
+                        planet::cluster_regions(
+                            &mut rng,
+                            &regions,
+                            &adjacency,
+                            num_plates,
+                            params.continental_fraction,
+                        )
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate");
+    group.sample_size(10);
+    let elevation_params = ElevationParams::default();
+    for subdivisions in SUBDIVISIONS {
+        let params = GenerationParams {
+            subdivisions,
+            ..Default::default()
+        };
+        group.throughput(Throughput::Elements(20 * 4u64.pow(subdivisions as u32)));
+        group.bench_with_input(
+            BenchmarkId::new("subdivisions", subdivisions),
+            &params,
+            |b, params| b.iter(|| PlanetData::generate(SEED, params, &elevation_params)),
+        );
+    }
+    group.throughput(Throughput::Elements(
+        20 * 4u64.pow(PLATE_SUBDIVISIONS as u32),
+    ));
+    for num_plates in PLATES {
+        let params = GenerationParams {
+            subdivisions: PLATE_SUBDIVISIONS,
+            num_plates,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::new("plates", num_plates),
+            &params,
+            |b, params| b.iter(|| PlanetData::generate(SEED, params, &elevation_params)),
+        );
+    }
+    group.finish();
+}
+
+/// Generation and the mesh
+fn build_planet(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_planet");
+    group.sample_size(10);
+    let elevation_params = ElevationParams::default();
+    for subdivisions in SUBDIVISIONS {
+        let params = GenerationParams {
+            subdivisions,
+            ..Default::default()
+        };
+        group.throughput(Throughput::Elements(20 * 4u64.pow(subdivisions as u32)));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subdivisions),
+            &params,
+            |b, params| {
+                b.iter(|| planet::build_planet(SEED, params, &elevation_params, Shading::default()))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    create_regions,
+    cluster_regions,
+    generate,
+    build_planet
+);
+criterion_main!(benches);
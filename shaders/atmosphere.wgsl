@@ -0,0 +1,96 @@
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    packed_position: vec4<u32>,
+    z_near: f32,
+    z_far: f32,
+    light_dir: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct AtmosphereUniform {
+    // center of the planet relative to the camera
+    center: vec3<f32>,
+    radius: f32,
+    glow_color: vec3<f32>,
+    planet_radius: f32,
+    sun_dir: vec3<f32>,
+    falloff: f32,
+    intensity: f32,
+};
+@group(1) @binding(0)
+var<uniform> atmosphere: AtmosphereUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) look_dir: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = camera.projection * camera.view * vec4<f32>(model.position, 1.0);
+    out.look_dir = model.position;
+
+    return out;
+}
+
+// Fragment shader
+
+// distances along the ray from the camera to where it enters and leaves the sphere,
+// both negative if the ray misses
+fn sphere_intersection(dir: vec3<f32>, center: vec3<f32>, radius: f32) -> vec2<f32> {
+    let b = dot(dir, center);
+    let c = dot(center, center) - radius * radius;
+    let discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return vec2<f32>(-1.0);
+    }
+    let root = sqrt(discriminant);
+    return vec2<f32>(b - root, b + root);
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+    let dir = normalize(in.look_dir);
+
+    let shell = sphere_intersection(dir, atmosphere.center, atmosphere.radius);
+    if (shell.y < 0.0) {
+        out.color = vec4<f32>(0.0);
+        return out;
+    }
+
+    // the opaque planet ends the ray
+    let start = max(shell.x, 0.0);
+    var end = shell.y;
+    let planet = sphere_intersection(dir, atmosphere.center, atmosphere.planet_radius);
+    if (planet.y > 0.0) {
+        end = min(end, max(planet.x, 0.0));
+    }
+
+    // the longest path through the shell is the one grazing the planet
+    let r = atmosphere.radius;
+    let rp = atmosphere.planet_radius;
+    let max_path = 2.0 * sqrt(r * r - rp * rp);
+    let depth = clamp((end - start) / max_path, 0.0, 1.0);
+
+    let midpoint = dir * (start + end) * 0.5 - atmosphere.center;
+    let daylight = smoothstep(-0.3, 0.3, dot(normalize(midpoint), atmosphere.sun_dir));
+
+    let glow = pow(depth, atmosphere.falloff) * atmosphere.intensity * mix(0.1, 1.0, daylight);
+    out.color = vec4<f32>(atmosphere.glow_color * glow, 1.0);
+    return out;
+}
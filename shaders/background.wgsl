@@ -4,10 +4,18 @@ struct CameraUniform {
     packed_position: vec4<u32>,
     z_near: f32,
     z_far: f32,
+    light_dir: vec3<f32>,
 };
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct BackgroundUniform {
+    star_density: f32,
+    seed: f32,
+};
+@group(1) @binding(0)
+var<uniform> background: BackgroundUniform;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
 };
@@ -31,8 +39,12 @@ fn vs_main(
 
 // Fragment shader
 
-const DENSITY: f32 = 0.005;
 const STAR_SIZE: f32 = 0.1;
+/// cosine of the angle from the sun at which the stars start fading in its glare, so they only
+/// show in the night sky
+const SUN_GLARE: f32 = 0.0;
+const WARM_STAR: vec3<f32> = vec3<f32>(1.0, 0.85, 0.7);
+const COOL_STAR: vec3<f32> = vec3<f32>(0.75, 0.85, 1.0);
 
 fn hash33(p: vec3<f32>) -> vec3<f32> {
     var p3: vec3<f32> = fract(p * vec3<f32>(443.897, 441.423, 437.195));
@@ -82,15 +94,15 @@ fn fractal_noise(p: vec3<f32>, octaves: i32) -> f32 {
     return value;
 }
 
-fn calculate_stars(dir: vec3<f32>) -> f32 {
-    let grid_scale = 150.0;
+// dir is the normalized view ray, cells of the grid contain at most one star
+fn calculate_stars(dir: vec3<f32>, grid_scale: f32) -> vec3<f32> {
     let base_density = 0.9995;
 
     let cell = floor(dir * grid_scale);
-    let rand = hash33(cell);
+    let rand = hash33(cell + vec3<f32>(background.seed));
 
-    if (rand.x > base_density * DENSITY) {
-        return 0.0;
+    if (rand.x > base_density * background.star_density) {
+        return vec3<f32>(0.0);
     }
 
     let star_pos = (cell + 0.5 + rand.yzx) / grid_scale;
@@ -99,7 +111,7 @@ fn calculate_stars(dir: vec3<f32>) -> f32 {
     let size = mix(0.001, 0.01, rand.y) * STAR_SIZE;
     let star = 1.0 - smoothstep(size * 0.8, size, dist);
 
-    return star;
+    return star * mix(WARM_STAR, COOL_STAR, rand.z);
 }
 
 fn calculate_nebula(dir: vec3<f32>) -> vec3<f32> {
@@ -128,12 +140,12 @@ fn fs_main(in: VertexOutput) -> FragmentOutput {
     var out: FragmentOutput;
     let dir = normalize(in.look_dir);
 
-    var stars: f32 = calculate_stars(dir);
-    stars += calculate_stars(dir*1.5) * 0.5;
-    // stars += calculate_stars(dir*4.0) * 0.25;
+    var stars = calculate_stars(dir, 150.0);
+    stars += calculate_stars(dir, 225.0) * 0.5;
+    stars *= 1.0 - smoothstep(SUN_GLARE, 1.0, dot(dir, camera.light_dir));
 
     let nebula = calculate_nebula(dir);
 
-    out.color = vec4<f32>(nebula + vec3<f32>(stars), 1.0);
+    out.color = vec4<f32>(nebula + stars, 1.0);
     return out;
 }
@@ -0,0 +1,71 @@
+struct BloomUniform {
+    /// brightness above which the scene blooms
+    threshold: f32,
+};
+@group(0) @binding(0)
+var<uniform> bloom: BloomUniform;
+/// the previous level, or the bright scene for the first level
+@group(0) @binding(1)
+var source: texture_2d<f32>;
+@group(0) @binding(2)
+var source_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+/// One triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+/// Keeps the part of the scene brighter than the threshold, at half the resolution
+@fragment
+fn fs_threshold(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let bright = max(brightness - bloom.threshold, 0.0) / max(brightness, 1e-4);
+    return vec4<f32>(color * bright, 1.0);
+}
+
+/// Halves the resolution, the linear sampler averages four texels
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureSample(source, source_sampler, in.uv).rgb, 1.0);
+}
+
+/// 9 tap gaussian in 5 linear samples
+const OFFSETS: array<f32, 3> = array<f32, 3>(0.0, 1.3846153846, 3.2307692308);
+const WEIGHTS: array<f32, 3> = array<f32, 3>(0.2270270270, 0.3162162162, 0.0702702703);
+
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let texel = direction / vec2<f32>(textureDimensions(source));
+    var color = textureSample(source, source_sampler, uv).rgb * WEIGHTS[0];
+    for (var i = 1; i < 3; i++) {
+        color += textureSample(source, source_sampler, uv + texel * OFFSETS[i]).rgb * WEIGHTS[i];
+        color += textureSample(source, source_sampler, uv - texel * OFFSETS[i]).rgb * WEIGHTS[i];
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+/// Adds the smaller level onto the larger one, the pipeline blends additively
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureSample(source, source_sampler, in.uv).rgb, 1.0);
+}
@@ -0,0 +1,161 @@
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    packed_position: vec4<u32>,
+    z_near: f32,
+    z_far: f32,
+    reverse_z: u32,
+    light_dir: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct CloudUniform {
+    /// seconds since the start
+    time: f32,
+    /// fraction of the sky covered by clouds
+    coverage: f32,
+    /// angular speed of the clouds around the z axis in radians per second
+    speed: f32,
+    opacity: f32,
+};
+@group(1) @binding(0)
+var<uniform> clouds: CloudUniform;
+
+struct VertexInput {
+    @location(0) position: vec4<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    /// direction from the center of the planet
+    @location(0) up: vec3<f32>,
+};
+
+fn extract_int(position: vec4<u32>) -> vec3<i32> {
+    let x_int = bitcast<i32>(position.w) >> 3; // 29 bit signed integer
+    let y_int = bitcast<i32>((((position.z & ((1 << 21) - 1)) << 8) | (position.y >> 24)) << 3) >> 3; // 29 bit signed integer
+    let z_int = bitcast<i32>((((position.y & ((1 << 10) - 1)) << 18) | (position.x >> 14)) << 4) >> 4; // 28 bit signed integer
+    return vec3<i32>(x_int, y_int, z_int);
+}
+
+fn extract_dec(position: vec4<u32>) -> vec3<f32> {
+    let x_dec = f32(((position.w & ((1 << 3) - 1)) << 11) | (position.z >> 21)); // 14 bit unsigned integer as float
+    let y_dec = f32((position.y >> 10) & ((1 << 14) - 1)); // 14 bit unsigned integer as float
+    let z_dec = f32(position.x & ((1 << 14) - 1)); // 14 bit unsigned integer as float
+    return vec3<f32>(x_dec, y_dec, z_dec);
+}
+
+fn unpack_position(position: vec4<u32>) -> vec3<f32> {
+    const SCALE: f32 = 1.0 / 16384.0;
+
+    let cam_int = extract_int(camera.packed_position);
+    let pos_int = extract_int(position);
+    let cam_dec = extract_dec(camera.packed_position);
+    let pos_dec = extract_dec(position);
+
+    let rel_x = f32(pos_int.x - cam_int.x) + (pos_dec.x - cam_dec.x) * SCALE;
+    let rel_y = f32(pos_int.y - cam_int.y) + (pos_dec.y - cam_dec.y) * SCALE;
+    let rel_z = f32(pos_int.z - cam_int.z) + (pos_dec.z - cam_dec.z) * SCALE;
+
+    return vec3<f32>(rel_x, rel_y, rel_z);
+}
+
+/// Position in world space, only precise enough for directions
+fn world_position(position: vec4<u32>) -> vec3<f32> {
+    const SCALE: f32 = 1.0 / 16384.0;
+    return vec3<f32>(extract_int(position)) + extract_dec(position) * SCALE;
+}
+
+/// Same log depth as the planet, so the clouds are depth tested against it
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.up = normalize(world_position(model.position));
+
+    let view_pos = camera.view * vec4<f32>(unpack_position(model.position), 1.0);
+    let z_view = -view_pos.z;
+    var log_depth = (log(z_view) - log(camera.z_near)) / (log(camera.z_far) - log(camera.z_near));
+    if camera.reverse_z != 0u {
+        log_depth = 1.0 - log_depth;
+    }
+
+    out.clip_position = camera.projection * view_pos;
+    out.clip_position.z = log_depth * out.clip_position.w;
+    return out;
+}
+
+// Fragment shader
+
+fn hash33(p: vec3<f32>) -> vec3<f32> {
+    var p3: vec3<f32> = fract(p * vec3<f32>(443.897, 441.423, 437.195));
+    p3 = p3 + dot(p3, p3.yxz + vec3<f32>(19.19));
+    return fract((p3.xxy + p3.yxx) * p3.zyx);
+}
+
+fn gradient_noise(p: vec3<f32>) -> f32 {
+    let i = floor(p);
+    let fp = fract(p);
+    let u = fp * fp * (3.0 - 2.0 * fp);
+
+    let a = hash33(i + vec3<f32>(0.0, 0.0, 0.0)).x;
+    let b = hash33(i + vec3<f32>(1.0, 0.0, 0.0)).x;
+    let c = hash33(i + vec3<f32>(0.0, 1.0, 0.0)).x;
+    let d = hash33(i + vec3<f32>(1.0, 1.0, 0.0)).x;
+    let e = hash33(i + vec3<f32>(0.0, 0.0, 1.0)).x;
+    let f = hash33(i + vec3<f32>(1.0, 0.0, 1.0)).x;
+    let g = hash33(i + vec3<f32>(0.0, 1.0, 1.0)).x;
+    let h = hash33(i + vec3<f32>(1.0, 1.0, 1.0)).x;
+
+    let y1 = mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+    let y2 = mix(mix(e, f, u.x), mix(g, h, u.x), u.y);
+    return mix(y1, y2, u.z);
+}
+
+fn fractal_noise(p: vec3<f32>, octaves: i32) -> f32 {
+    var value: f32 = 0.0;
+    var amplitude: f32 = 0.5;
+    var frequency: f32 = 1.0;
+
+    for (var i: i32 = 0; i < octaves; i = i + 1) {
+        value += amplitude * gradient_noise(p * frequency);
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    return value;
+}
+
+const NOISE_SCALE: f32 = 4.0;
+/// how fast the cloud shapes change while they move
+const EVOLUTION_SPEED: f32 = 0.02;
+/// width of the transition from clear sky to cloud, in noise values
+const EDGE_SOFTNESS: f32 = 0.08;
+const AMBIENT: f32 = 0.05;
+const NIGHT_AMBIENT: f32 = 0.01;
+const TERMINATOR_WIDTH: f32 = 0.1;
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+    let up = normalize(in.up);
+
+    // the clouds drift around the z axis
+    let angle = -clouds.time * clouds.speed;
+    let rotated = vec3<f32>(cos(angle) * up.x - sin(angle) * up.y, sin(angle) * up.x + cos(angle) * up.y, up.z);
+    let noise = fractal_noise(rotated * NOISE_SCALE + vec3<f32>(clouds.time * EVOLUTION_SPEED), 5);
+    // the noise is mostly between 0.25 and 0.75
+    let threshold = 0.75 - 0.5 * clouds.coverage;
+    let density = smoothstep(threshold - EDGE_SOFTNESS, threshold + EDGE_SOFTNESS, noise);
+
+    let day = smoothstep(-TERMINATOR_WIDTH, TERMINATOR_WIDTH, dot(up, camera.light_dir));
+    let diffuse = max(dot(up, camera.light_dir), 0.0) * day;
+    let light = mix(NIGHT_AMBIENT, AMBIENT, day) + (1.0 - AMBIENT) * diffuse;
+
+    out.color = vec4<f32>(vec3<f32>(light), density * clouds.opacity);
+    return out;
+}
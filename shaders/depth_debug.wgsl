@@ -0,0 +1,57 @@
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    packed_position: vec4<u32>,
+    z_near: f32,
+    z_far: f32,
+    reverse_z: u32,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+/// the camera depth, only one of them is bound depending on the sample count. They are
+/// read as unfilterable floats, the GL backend can't load from depth textures.
+@group(1) @binding(0)
+var depth_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var depth_texture_multisampled: texture_multisampled_2d<f32>;
+
+/// view distance drawn white, everything further away is clamped to it
+const DEPTH_RANGE: f32 = 6.0;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+/// One triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+/// Inverts the logarithmic depth written by the planet shader, giving the view distance
+fn linearize(depth: f32) -> f32 {
+    var log_depth = depth;
+    if camera.reverse_z != 0u {
+        log_depth = 1.0 - log_depth;
+    }
+    return exp(mix(log(camera.z_near), log(camera.z_far), log_depth));
+}
+
+fn grayscale(depth: f32) -> vec4<f32> {
+    let distance = linearize(depth) - camera.z_near;
+    return vec4<f32>(vec3<f32>(clamp(distance / DEPTH_RANGE, 0.0, 1.0)), 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return grayscale(textureLoad(depth_texture, vec2<i32>(in.clip_position.xy), 0).r);
+}
+
+/// Shows the first sample of every pixel
+@fragment
+fn fs_multisampled(in: VertexOutput) -> @location(0) vec4<f32> {
+    return grayscale(textureLoad(depth_texture_multisampled, vec2<i32>(in.clip_position.xy), 0).r);
+}
@@ -0,0 +1,152 @@
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    packed_position: vec4<u32>,
+    z_near: f32,
+    z_far: f32,
+    reverse_z: u32,
+    light_dir: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct MoonUniform {
+    /// center of the moon, packed like the vertex positions of the planet
+    center: vec4<u32>,
+    color: vec3<f32>,
+    radius: f32,
+};
+@group(1) @binding(0)
+var<uniform> moon: MoonUniform;
+
+struct ShadowUniform {
+    /// from world space into the clip space of the shadow map
+    view_projection: mat4x4<f32>,
+    /// distance receivers are moved along their normal before the lookup, in world units
+    normal_offset: f32,
+    /// size of a shadow map texel in uv coordinates
+    texel_size: f32,
+};
+@group(2) @binding(0)
+var<uniform> shadow: ShadowUniform;
+/// depth seen from the sun, not bound while it is drawn by `vs_shadow`
+@group(2) @binding(1)
+var shadow_map: texture_depth_2d;
+@group(2) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+struct VertexInput {
+    /// direction from the center of the moon
+    @location(0) direction: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    /// direction from the center of the moon
+    @location(0) normal: vec3<f32>,
+    /// position in the clip space of the shadow map
+    @location(1) shadow_position: vec3<f32>,
+};
+
+fn extract_int(position: vec4<u32>) -> vec3<i32> {
+    let x_int = bitcast<i32>(position.w) >> 3; // 29 bit signed integer
+    let y_int = bitcast<i32>((((position.z & ((1 << 21) - 1)) << 8) | (position.y >> 24)) << 3) >> 3; // 29 bit signed integer
+    let z_int = bitcast<i32>((((position.y & ((1 << 10) - 1)) << 18) | (position.x >> 14)) << 4) >> 4; // 28 bit signed integer
+    return vec3<i32>(x_int, y_int, z_int);
+}
+
+fn extract_dec(position: vec4<u32>) -> vec3<f32> {
+    let x_dec = f32(((position.w & ((1 << 3) - 1)) << 11) | (position.z >> 21)); // 14 bit unsigned integer as float
+    let y_dec = f32((position.y >> 10) & ((1 << 14) - 1)); // 14 bit unsigned integer as float
+    let z_dec = f32(position.x & ((1 << 14) - 1)); // 14 bit unsigned integer as float
+    return vec3<f32>(x_dec, y_dec, z_dec);
+}
+
+fn unpack_position(position: vec4<u32>) -> vec3<f32> {
+    const SCALE: f32 = 1.0 / 16384.0;
+
+    let cam_int = extract_int(camera.packed_position);
+    let pos_int = extract_int(position);
+    let cam_dec = extract_dec(camera.packed_position);
+    let pos_dec = extract_dec(position);
+
+    let rel_x = f32(pos_int.x - cam_int.x) + (pos_dec.x - cam_dec.x) * SCALE;
+    let rel_y = f32(pos_int.y - cam_int.y) + (pos_dec.y - cam_dec.y) * SCALE;
+    let rel_z = f32(pos_int.z - cam_int.z) + (pos_dec.z - cam_dec.z) * SCALE;
+
+    return vec3<f32>(rel_x, rel_y, rel_z);
+}
+
+/// Position in world space, only precise enough for directions
+fn world_position(position: vec4<u32>) -> vec3<f32> {
+    const SCALE: f32 = 1.0 / 16384.0;
+    return vec3<f32>(extract_int(position)) + extract_dec(position) * SCALE;
+}
+
+/// Same log depth as the planet, so the moon is depth tested against it
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.normal = model.direction;
+
+    let relative_position = unpack_position(moon.center) + moon.radius * model.direction;
+    let view_pos = camera.view * vec4<f32>(relative_position, 1.0);
+    let z_view = -view_pos.z;
+    var log_depth = (log(z_view) - log(camera.z_near)) / (log(camera.z_far) - log(camera.z_near));
+    if camera.reverse_z != 0u {
+        log_depth = 1.0 - log_depth;
+    }
+
+    out.clip_position = camera.projection * view_pos;
+    out.clip_position.z = log_depth * out.clip_position.w;
+
+    let world = world_position(moon.center) + moon.radius * model.direction;
+    out.shadow_position = (shadow.view_projection * vec4<f32>(world + model.direction * shadow.normal_offset, 1.0)).xyz;
+    return out;
+}
+
+/// Depth of the moon seen from the sun, into the shadow map
+@vertex
+fn vs_shadow(model: VertexInput) -> @builtin(position) vec4<f32> {
+    let world = world_position(moon.center) + moon.radius * model.direction;
+    return shadow.view_projection * vec4<f32>(world, 1.0);
+}
+
+// Fragment shader
+
+const AMBIENT: f32 = 0.05;
+const NIGHT_AMBIENT: f32 = 0.01;
+const TERMINATOR_WIDTH: f32 = 0.1;
+
+/// Fraction of the sun reaching `shadow_position`, filtered over 3x3 texels of the shadow map.
+/// Outside the shadow map everything is lit.
+fn sunlight(shadow_position: vec3<f32>) -> f32 {
+    let uv = shadow_position.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || shadow_position.z > 1.0 {
+        return 1.0;
+    }
+    var light = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+            light += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, shadow_position.z);
+        }
+    }
+    return light / 9.0;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+    let normal = normalize(in.normal);
+    let day = smoothstep(-TERMINATOR_WIDTH, TERMINATOR_WIDTH, dot(normal, camera.light_dir));
+    // in the shadow of the planet during an eclipse
+    let diffuse = max(dot(normal, camera.light_dir), 0.0) * day * sunlight(in.shadow_position);
+    let light = mix(NIGHT_AMBIENT, AMBIENT, day) + (1.0 - AMBIENT) * diffuse;
+    out.color = vec4<f32>(moon.color * light, 1.0);
+    return out;
+}
@@ -4,18 +4,74 @@ struct CameraUniform {
     packed_position: vec4<u32>,
     z_near: f32,
     z_far: f32,
+    reverse_z: u32,
+    light_dir: vec3<f32>,
+    specular_exponent: f32,
+    specular_intensity: f32,
 };
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
+/// color of every region, only bound when vertex shaders support storage buffers
+@group(0) @binding(1)
+var<storage, read> region_colors: array<vec4<f32>>;
+/// equirectangular, repeats in u for the regions on the seam
+@group(0) @binding(2)
+var planet_texture: texture_2d<f32>;
+@group(0) @binding(3)
+var planet_sampler: sampler;
+
+struct ShadowUniform {
+    /// from world space into the clip space of the shadow map
+    view_projection: mat4x4<f32>,
+    /// distance receivers are moved along their normal before the lookup, in world units
+    normal_offset: f32,
+    /// size of a shadow map texel in uv coordinates
+    texel_size: f32,
+};
+@group(1) @binding(0)
+var<uniform> shadow: ShadowUniform;
+/// depth seen from the sun, not bound while it is drawn by `vs_shadow`
+@group(1) @binding(1)
+var shadow_map: texture_depth_2d;
+@group(1) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+/// Whether the color target takes linear colors, see `setup::linear_target`. The vertex and
+/// region colors are sRGB, they are converted to linear for these targets. Other targets get
+/// them as they are.
+override LINEAR_TARGET: bool = true;
 
 struct VertexInput {
     @location(0) position: vec4<u32>,
-    @location(1) color: vec4<f32>,
+    /// RGBA8, see `pack_color`
+    @location(1) color: u32,
+    @location(2) region: u32,
+    @location(3) normal: vec3<f32>,
+    @location(4) material: u32,
+    @location(5) uv: vec2<f32>,
+};
+
+/// Placement of a copy of the planet, see `Transform`
+struct InstanceInput {
+    /// center of the copy, packed like the vertex positions
+    @location(6) position: vec4<u32>,
+    @location(7) scale: f32,
 };
 
 struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) color: vec3<f32>,
+    @builtin(position) @invariant clip_position: vec4<f32>,
+    /// the color of the region, from the first vertex of the triangle
+    @location(0) @interpolate(flat) color: vec3<f32>,
+    /// position relative to the camera
+    @location(1) relative_position: vec3<f32>,
+    /// direction from the center of the planet, smooth across the regions
+    @location(2) up: vec3<f32>,
+    @location(3) @interpolate(flat) material: u32,
+    /// zero for flat shading
+    @location(4) normal: vec3<f32>,
+    @location(5) uv: vec2<f32>,
+    /// position in the clip space of the shadow map
+    @location(6) shadow_position: vec3<f32>,
 };
 
 fn extract_int(position: vec4<u32>) -> vec3<i32> {
@@ -32,6 +88,12 @@ fn extract_dec(position: vec4<u32>) -> vec3<f32> {
     return vec3<f32>(x_dec, y_dec, z_dec);
 }
 
+/// Position in world space, only precise enough for directions
+fn world_position(position: vec4<u32>) -> vec3<f32> {
+    const SCALE: f32 = 1.0 / 16384.0;
+    return vec3<f32>(extract_int(position)) + extract_dec(position) * SCALE;
+}
+
 fn unpack_position(position: vec4<u32>) -> vec3<f32> {
     const SCALE: f32 = 1.0 / 16384.0;
 
@@ -47,33 +109,230 @@ fn unpack_position(position: vec4<u32>) -> vec3<f32> {
     return vec3<f32>(rel_x, rel_y, rel_z);
 }
 
+const AMBIENT: f32 = 0.05;
+/// ambient light on the night side
+const NIGHT_AMBIENT: f32 = 0.01;
+/// width of the soft day/night terminator, in cosine of the sun angle
+const TERMINATOR_WIDTH: f32 = 0.1;
+/// `Material` of the vertex
+const MATERIAL_LAND: u32 = 0u;
+const MATERIAL_WATER: u32 = 1u;
+
 @vertex
 fn vs_main(
     model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    return vertex(model, instance, region_colors[model.region].rgb);
+}
+
+/// Uses the vertex colors in place of `region_colors`
+@vertex
+fn vs_vertex_color(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    return vertex(model, instance, unpack4x8unorm(model.color).rgb);
+}
+
+/// Moves the lines of the wireframe overlay towards the camera in log depth, so they sit on
+/// top of the filled surface. Depth bias isn't applied to lines on every backend.
+const LINE_DEPTH_OFFSET: f32 = 1e-5;
+
+@vertex
+fn vs_line(
+    model: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
+    var out = vertex(model, instance, unpack4x8unorm(model.color).rgb);
+    out.clip_position = offset_line(out.clip_position);
+    return out;
+}
+
+/// Color of the latitude and longitude lines, it is blended over the planet with the alpha
+const GRATICULE_COLOR: vec4<f32> = vec4<f32>(0.8, 0.85, 0.9, 0.25);
+
+/// A vertex of the graticule, a direction from the center of the planet scaled to the height of
+/// the lines
+struct GraticuleInput {
+    @location(0) position: vec3<f32>,
+};
+
+@vertex
+fn vs_graticule(
+    model: GraticuleInput,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let relative_position = unpack_position(instance.position) + instance.scale * model.position;
+    return offset_line(clip_position(relative_position));
+}
+
+@fragment
+fn fs_graticule() -> FragmentOutput {
+    var out: FragmentOutput;
+    let color = GRATICULE_COLOR.rgb;
+    out.color = vec4<f32>(select(color, srgb_to_linear(color), LINEAR_TARGET), GRATICULE_COLOR.a);
+    return out;
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+fn vertex(model: VertexInput, instance: InstanceInput, color: vec3<f32>) -> VertexOutput {
     var out: VertexOutput;
-    out.color = model.color.xyz;
+    out.color = select(color, srgb_to_linear(color), LINEAR_TARGET);
+    out.up = normalize(world_position(model.position));
+    out.material = model.material;
+    out.normal = model.normal;
+    out.uv = model.uv;
 
-    let view_pos = camera.view * (vec4<f32>(unpack_position(model.position), 1.0));
+    // the center of the instance relative to the camera is exact, the vertices are small
+    // offsets from it
+    let relative_position = unpack_position(instance.position) + instance.scale * world_position(model.position);
+    let world = world_position(instance.position) + instance.scale * world_position(model.position);
+    out.shadow_position = (shadow.view_projection * vec4<f32>(world + out.up * shadow.normal_offset, 1.0)).xyz;
+    out.relative_position = relative_position;
+    out.clip_position = clip_position(relative_position);
+    return out;
+}
+
+/// Clip space position of a point relative to the camera, with the log depth in z
+fn clip_position(relative_position: vec3<f32>) -> vec4<f32> {
+    let view_pos = camera.view * (vec4<f32>(relative_position, 1.0));
     let z_view = -view_pos.z;
-    let log_depth = (log(z_view) - log(camera.z_near)) / (log(camera.z_far) - log(camera.z_near));
+    var log_depth = (log(z_view) - log(camera.z_near)) / (log(camera.z_far) - log(camera.z_near));
+    if camera.reverse_z != 0u {
+        log_depth = 1.0 - log_depth;
+    }
+
+    var clip_position = camera.projection * view_pos;
+    clip_position.z = log_depth * clip_position.w;
+    return clip_position;
+}
+
+/// Moves a line towards the camera by `LINE_DEPTH_OFFSET`
+fn offset_line(clip_position: vec4<f32>) -> vec4<f32> {
+    var offset = LINE_DEPTH_OFFSET * clip_position.w;
+    if camera.reverse_z == 0u {
+        offset = -offset;
+    }
+    return clip_position + vec4<f32>(0.0, 0.0, offset, 0.0);
+}
 
-    out.clip_position = camera.projection * view_pos;
-    out.clip_position.z = log_depth * out.clip_position.w;
+/// Half the size of a point of the point cloud, in normalized device coordinates of the
+/// screen height
+const POINT_SIZE: f32 = 0.004;
 
-    // out.clip_position.z = log_depth * out.clip_position.w;
+/// Corners of the quad a point is expanded into, two triangles at six vertices per point
+const POINT_CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, 1.0),
+);
 
+/// Expands the six copies of every point of the point cloud into a quad of constant size on
+/// screen, point primitives are always a single pixel
+@vertex
+fn vs_point(
+    model: VertexInput,
+    instance: InstanceInput,
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    var out = vertex(model, instance, unpack4x8unorm(model.color).rgb);
+    var corners = POINT_CORNERS;
+    let corner = corners[vertex_index % 6u];
+    // `projection[0][0] / projection[1][1]` is one over the aspect ratio, the quads stay square
+    let scale = vec2<f32>(camera.projection[0][0] / camera.projection[1][1], 1.0);
+    out.clip_position = vec4<f32>(out.clip_position.xy + corner * scale * POINT_SIZE * out.clip_position.w, out.clip_position.zw);
     return out;
 }
 
+/// Depth of the planet seen from the sun, into the shadow map
+@vertex
+fn vs_shadow(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let world = world_position(instance.position) + instance.scale * world_position(model.position);
+    return shadow.view_projection * vec4<f32>(world, 1.0);
+}
+
 // Fragment shader
 
 struct FragmentOutput {
     @location(0) color: vec4<f32>,
 }
 
+/// Fraction of the sun reaching `shadow_position`, filtered over 3x3 texels of the shadow map.
+/// Outside the shadow map everything is lit.
+fn sunlight(shadow_position: vec3<f32>) -> f32 {
+    let uv = shadow_position.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || shadow_position.z > 1.0 {
+        return 1.0;
+    }
+    var light = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+            light += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, shadow_position.z);
+        }
+    }
+    return light / 9.0;
+}
+
+/// Lights a surface of `base_color` and `material` with the sun
+fn shade(in: VertexOutput, base_color: vec3<f32>, material: u32) -> vec3<f32> {
+    // vertices are shared between regions, so the flat normal of the region comes from the
+    // screen space derivatives, facing the camera
+    var normal = normalize(cross(dpdx(in.relative_position), dpdy(in.relative_position)));
+    if dot(normal, in.relative_position) > 0.0 {
+        normal = -normal;
+    }
+    // smooth normals are interpolated from the vertices
+    if dot(in.normal, in.normal) > 0.0 {
+        normal = normalize(in.normal);
+    }
+    // the terminator follows the sphere rather than the faces, so it doesn't step region by region
+    let day = smoothstep(-TERMINATOR_WIDTH, TERMINATOR_WIDTH, dot(normalize(in.up), camera.light_dir));
+    // shadows of the mountains and the moon
+    let sun = day * sunlight(in.shadow_position);
+    let diffuse = max(dot(normal, camera.light_dir), 0.0) * sun;
+    let ambient = mix(NIGHT_AMBIENT, AMBIENT, day);
+    var color = base_color * (ambient + (1.0 - AMBIENT) * diffuse);
+    if material == MATERIAL_WATER {
+        let halfway = normalize(camera.light_dir - normalize(in.relative_position));
+        let specular = pow(max(dot(normal, halfway), 0.0), camera.specular_exponent);
+        color += vec3<f32>(camera.specular_intensity * specular * sun);
+    }
+    return color;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+    out.color = vec4<f32>(shade(in, in.color, in.material), 1.0);
+    return out;
+}
+
+/// The planet texture instead of the region colors. The oceans of the texture don't match the
+/// plates, so it is matte everywhere.
+@fragment
+fn fs_textured(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+    let base_color = textureSample(planet_texture, planet_sampler, in.uv).rgb;
+    out.color = vec4<f32>(shade(in, base_color, MATERIAL_LAND), 1.0);
+    return out;
+}
+
+/// Unlit, lines have no normal
+@fragment
+fn fs_line(in: VertexOutput) -> FragmentOutput {
     var out: FragmentOutput;
     out.color = vec4<f32>(in.color, 1.0);
     return out;
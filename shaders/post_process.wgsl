@@ -0,0 +1,59 @@
+struct PostProcessUniform {
+    /// scales the scene before the tonemapping, 1 keeps it as it is
+    exposure: f32,
+    /// how much of the bloom is added to the scene
+    bloom_intensity: f32,
+};
+@group(0) @binding(0)
+var<uniform> post_process: PostProcessUniform;
+/// the linear HDR scene, the same size as the output
+@group(0) @binding(1)
+var scene: texture_2d<f32>;
+/// the blurred bright parts of the scene at half resolution, see `bloom.wgsl`
+@group(0) @binding(2)
+var bloom: texture_2d<f32>;
+@group(0) @binding(3)
+var bloom_sampler: sampler;
+
+/// Whether the output takes linear colors, otherwise they are encoded to sRGB here
+override LINEAR_TARGET: bool = true;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+/// One triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+/// Narkowicz's fit of the ACES filmic curve, maps 0..inf to 0..1 with a soft shoulder
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let bloom_color = textureSample(bloom, bloom_sampler, in.uv).rgb;
+    let hdr = textureLoad(scene, vec2<i32>(in.clip_position.xy), 0).rgb
+        + post_process.bloom_intensity * bloom_color;
+    let color = aces(hdr * post_process.exposure);
+    return vec4<f32>(select(linear_to_srgb(color), color, LINEAR_TARGET), 1.0);
+}
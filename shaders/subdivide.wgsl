@@ -0,0 +1,117 @@
+// One level of icosphere subdivision. `allocate_midpoints` gives every edge a new vertex and
+// stores it in a hash table of edges, `split_triangles` then replaces every triangle by four.
+// The two entry points run as separate dispatches, so all midpoints exist before they are read.
+// Only atomic adds are used, compare exchange isn't supported by every backend.
+
+struct Params {
+    triangle_count: u32,
+    table_size: u32,
+    _padding: vec2<u32>,
+};
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(0) @binding(1)
+var<storage, read_write> vertices: array<vec4<f32>>;
+@group(0) @binding(2)
+var<storage, read> indices: array<u32>;
+@group(0) @binding(3)
+var<storage, read_write> new_indices: array<u32>;
+/// non-zero for the slots of `table` that are taken
+@group(0) @binding(4)
+var<storage, read_write> occupied: array<atomic<u32>>;
+@group(0) @binding(5)
+var<storage, read_write> table: array<Entry>;
+/// number of vertices, starts at the vertex count of the previous level
+@group(0) @binding(6)
+var<storage, read_write> vertex_count: atomic<u32>;
+
+/// The midpoint of the edge from vertex `a` to vertex `b`, with `a < b`
+struct Entry {
+    a: u32,
+    b: u32,
+    midpoint: u32,
+    _padding: u32,
+};
+
+fn first_slot(a: u32, b: u32) -> u32 {
+    return ((a * 2654435761u) ^ (b * 2246822519u)) % params.table_size;
+}
+
+/// The triangles are wound consistently, so every edge goes from the lower to the higher
+/// vertex in exactly one of its two triangles. That triangle creates the midpoint, so every
+/// edge is only inserted once.
+fn insert_midpoint(a: u32, b: u32) {
+    if a > b {
+        return;
+    }
+    let index = atomicAdd(&vertex_count, 1u);
+    vertices[index] = vec4<f32>(normalize((vertices[a].xyz + vertices[b].xyz) * 0.5), 0.0);
+
+    var slot = first_slot(a, b);
+    // the table has more slots than edges, so this finds a free slot
+    for (var probe = 0u; probe < params.table_size; probe++) {
+        if atomicAdd(&occupied[slot], 1u) == 0u {
+            table[slot] = Entry(a, b, index, 0u);
+            return;
+        }
+        slot = (slot + 1u) % params.table_size;
+    }
+}
+
+fn midpoint(a: u32, b: u32) -> u32 {
+    let low = min(a, b);
+    let high = max(a, b);
+    var slot = first_slot(low, high);
+    // every edge was inserted, so this finds it before going around the whole table
+    for (var probe = 0u; probe < params.table_size; probe++) {
+        let entry = table[slot];
+        if entry.a == low && entry.b == high {
+            return entry.midpoint;
+        }
+        slot = (slot + 1u) % params.table_size;
+    }
+    return 0u;
+}
+
+@compute @workgroup_size(64)
+fn allocate_midpoints(@builtin(global_invocation_id) id: vec3<u32>) {
+    let triangle = id.x;
+    if triangle >= params.triangle_count {
+        return;
+    }
+    let a = indices[3u * triangle];
+    let b = indices[3u * triangle + 1u];
+    let c = indices[3u * triangle + 2u];
+    insert_midpoint(a, b);
+    insert_midpoint(b, c);
+    insert_midpoint(c, a);
+}
+
+@compute @workgroup_size(64)
+fn split_triangles(@builtin(global_invocation_id) id: vec3<u32>) {
+    let triangle = id.x;
+    if triangle >= params.triangle_count {
+        return;
+    }
+    let a = indices[3u * triangle];
+    let b = indices[3u * triangle + 1u];
+    let c = indices[3u * triangle + 2u];
+    let m1 = midpoint(a, b);
+    let m2 = midpoint(b, c);
+    let m3 = midpoint(c, a);
+
+    // same order as the CPU subdivision, so the regions come out in the same order
+    let out = 12u * triangle;
+    new_indices[out] = a;
+    new_indices[out + 1u] = m1;
+    new_indices[out + 2u] = m3;
+    new_indices[out + 3u] = m1;
+    new_indices[out + 4u] = b;
+    new_indices[out + 5u] = m2;
+    new_indices[out + 6u] = m3;
+    new_indices[out + 7u] = m2;
+    new_indices[out + 8u] = c;
+    new_indices[out + 9u] = m1;
+    new_indices[out + 10u] = m2;
+    new_indices[out + 11u] = m3;
+}
@@ -0,0 +1,110 @@
+// Moves the plates of the planet mesh on the GPU. Every vertex is rotated around the motion
+// axis of the plate of its region, starting from the mesh as it was uploaded, so the rounding
+// of the packed positions doesn't add up over many steps. See `Vertex` for the layout, the
+// vertices are read as plain words because `vec3` fields are aligned differently in WGSL.
+
+struct Params {
+    /// radians per unit of motion axis length since the mesh was uploaded
+    angle: f32,
+    vertex_count: u32,
+    _padding: vec2<u32>,
+};
+@group(0) @binding(0)
+var<uniform> params: Params;
+/// the vertices as they were uploaded
+@group(0) @binding(1)
+var<storage, read> rest_vertices: array<u32>;
+@group(0) @binding(2)
+var<storage, read_write> vertices: array<u32>;
+/// plate of every region, `0xffffffff` for none
+@group(0) @binding(3)
+var<storage, read> plate_ids: array<u32>;
+/// motion axis of every plate, its length is the angular speed
+@group(0) @binding(4)
+var<storage, read> motion_axes: array<vec4<f32>>;
+
+/// words per `Vertex`
+const VERTEX_WORDS: u32 = 12u;
+const POSITION_WORD: u32 = 0u;
+const REGION_WORD: u32 = 5u;
+const NORMAL_WORD: u32 = 6u;
+const SCALE: f32 = 16384.0;
+
+fn extract_int(position: vec4<u32>) -> vec3<i32> {
+    let x_int = bitcast<i32>(position.w) >> 3; // 29 bit signed integer
+    let y_int = bitcast<i32>((((position.z & ((1 << 21) - 1)) << 8) | (position.y >> 24)) << 3) >> 3; // 29 bit signed integer
+    let z_int = bitcast<i32>((((position.y & ((1 << 10) - 1)) << 18) | (position.x >> 14)) << 4) >> 4; // 28 bit signed integer
+    return vec3<i32>(x_int, y_int, z_int);
+}
+
+fn extract_dec(position: vec4<u32>) -> vec3<f32> {
+    let x_dec = f32(((position.w & ((1 << 3) - 1)) << 11) | (position.z >> 21)); // 14 bit unsigned integer as float
+    let y_dec = f32((position.y >> 10) & ((1 << 14) - 1)); // 14 bit unsigned integer as float
+    let z_dec = f32(position.x & ((1 << 14) - 1)); // 14 bit unsigned integer as float
+    return vec3<f32>(x_dec, y_dec, z_dec);
+}
+
+fn unpack_position(position: vec4<u32>) -> vec3<f32> {
+    return vec3<f32>(extract_int(position)) + extract_dec(position) / SCALE;
+}
+
+/// The inverse of `unpack_position`, rounding down like `PackedVec3::from`
+fn pack_position(position: vec3<f32>) -> vec4<u32> {
+    let ints = floor(position);
+    // two's complement, the bits above the width of each coordinate are cut off
+    let i = bitcast<vec3<u32>>(vec3<i32>(ints));
+    let d = min(vec3<u32>((position - ints) * SCALE), vec3<u32>((1u << 14) - 1));
+    let x = d.z | (i.z << 14);
+    let y = ((i.z >> 18) & ((1u << 10) - 1)) | (d.y << 10) | (i.y << 24);
+    let z = ((i.y >> 8) & ((1u << 21) - 1)) | (d.x << 21);
+    let w = (d.x >> 11) | (i.x << 3);
+    return vec4<u32>(x, y, z, w);
+}
+
+/// Rotates `v` by `angle` radians around the unit `axis`
+fn rotate(v: vec3<f32>, axis: vec3<f32>, angle: f32) -> vec3<f32> {
+    return v * cos(angle) + cross(axis, v) * sin(angle) + axis * dot(axis, v) * (1.0 - cos(angle));
+}
+
+@compute @workgroup_size(64)
+fn move_plates(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.vertex_count {
+        return;
+    }
+    // the other words are the same in both buffers, only the position and the normal move
+    let base = id.x * VERTEX_WORDS;
+    let plate = plate_ids[rest_vertices[base + REGION_WORD]];
+    if plate >= arrayLength(&motion_axes) {
+        return;
+    }
+    let motion_axis = motion_axes[plate].xyz;
+    let speed = length(motion_axis);
+    if speed == 0.0 {
+        return;
+    }
+    let axis = motion_axis / speed;
+    let angle = speed * params.angle;
+
+    let p = base + POSITION_WORD;
+    let position = rotate(
+        unpack_position(vec4<u32>(rest_vertices[p], rest_vertices[p + 1], rest_vertices[p + 2], rest_vertices[p + 3])),
+        axis,
+        angle,
+    );
+    let packed = pack_position(position);
+    for (var k = 0u; k < 4u; k++) {
+        vertices[p + k] = packed[k];
+    }
+
+    // flat shaded vertices have no normal, it stays zero
+    let n = base + NORMAL_WORD;
+    let normal = rotate(
+        bitcast<vec3<f32>>(vec3<u32>(rest_vertices[n], rest_vertices[n + 1], rest_vertices[n + 2])),
+        axis,
+        angle,
+    );
+    let normal_words = bitcast<vec3<u32>>(normal);
+    for (var k = 0u; k < 3u; k++) {
+        vertices[n + k] = normal_words[k];
+    }
+}
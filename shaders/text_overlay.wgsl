@@ -0,0 +1,40 @@
+struct OverlayUniform {
+    screen_size: vec2<f32>,
+};
+@group(0) @binding(0)
+var<uniform> overlay: OverlayUniform;
+/// one row of glyphs, a texel is 1 where the glyph is drawn
+@group(0) @binding(1)
+var atlas: texture_2d<f32>;
+
+struct VertexInput {
+    /// in pixels from the top left corner of the screen
+    @location(0) position: vec2<f32>,
+    /// in texels of the atlas
+    @location(1) texel: vec2<f32>,
+    @location(2) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) texel: vec2<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let ndc = model.position / overlay.screen_size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.texel = model.texel;
+    out.color = model.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if textureLoad(atlas, vec2<i32>(floor(in.texel)), 0).r < 0.5 {
+        discard;
+    }
+    return vec4<f32>(in.color, 1.0);
+}
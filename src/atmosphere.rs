@@ -0,0 +1,239 @@
+use crate::background::{
+    build_near_field_quad, create_near_field_quad_index_buffer,
+    create_near_field_quad_vertex_buffer, vec3_vertex_desc,
+};
+use crate::{setup, utils::*, RADIUS};
+use anyhow::Result;
+use camera::{Camera, CameraUniform};
+
+pub const SHADER: &str = "shaders/atmosphere.wgsl";
+
+pub struct AtmosphereParams {
+    /// outer radius of the atmosphere shell
+    pub radius: f64,
+    pub glow_color: Vec3,
+    /// exponent applied to the path length through the shell, higher values concentrate the
+    /// glow at the limb
+    pub falloff: f32,
+    pub intensity: f32,
+}
+
+impl Default for AtmosphereParams {
+    fn default() -> Self {
+        AtmosphereParams {
+            radius: 1.05 * RADIUS,
+            glow_color: vec3(0.3, 0.55, 1.0),
+            falloff: 3.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct AtmosphereUniform {
+    /// center of the planet relative to the camera
+    center: Vec3,
+    radius: f32,
+    glow_color: Vec3,
+    planet_radius: f32,
+    sun_dir: Vec3,
+    falloff: f32,
+    intensity: f32,
+    _padding: [f32; 3],
+}
+
+pub fn create_atmosphere_uniform_buffer(device: &wgpu::Device) -> Buffer<AtmosphereUniform> {
+    device.create_typed_buffer(&TypedBufferDescriptor {
+        label: Some("atmosphere_uniform_buffer"),
+        len: 1,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Atmosphere Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[vec3_vertex_desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                // the glow is added on top of the background and planet
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::Zero,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        // drawn in the planet pass, the shader handles occlusion by the planet itself
+        depth_stencil: Some(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            ..camera::depth_stencil_state()
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+pub struct Atmosphere {
+    pub params: AtmosphereParams,
+    vertex_buffer: Buffer<Vec3>,
+    index_buffer: Buffer<u16>,
+    uniform_buffer: Buffer<AtmosphereUniform>,
+    camera_bind_group: wgpu::BindGroup,
+    bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Atmosphere {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        camera_uniform: &Buffer<CameraUniform>,
+    ) -> Result<Self> {
+        let vertex_buffer = create_near_field_quad_vertex_buffer(device);
+        let index_buffer = create_near_field_quad_index_buffer(device);
+        let uniform_buffer = create_atmosphere_uniform_buffer(device);
+
+        let shader = setup::shader(device, SHADER)?;
+
+        let camera_bind_group_layout = camera::bind_group_layout(device);
+        let camera_bind_group =
+            camera::bind_group(device, &camera_bind_group_layout, camera_uniform);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("atmosphere_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("atmosphere_bind_group"),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Atmosphere Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let format = config.format;
+        let render_pipeline = render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+        );
+
+        Ok(Atmosphere {
+            params: AtmosphereParams::default(),
+            vertex_buffer,
+            index_buffer,
+            uniform_buffer,
+            camera_bind_group,
+            bind_group,
+            render_pipeline_layout,
+            format,
+            sample_count,
+            render_pipeline,
+        })
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.render_pipeline = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok(render_pipeline(
+                device,
+                &self.render_pipeline_layout,
+                &shader,
+                self.format,
+                self.sample_count,
+            ))
+        })?;
+        Ok(())
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, sun_dir: Vec3) {
+        let uniform = AtmosphereUniform {
+            center: (-camera.position).as_vec3(),
+            radius: self.params.radius as f32,
+            glow_color: self.params.glow_color,
+            planet_radius: RADIUS as f32,
+            sun_dir: sun_dir.normalize(),
+            falloff: self.params.falloff,
+            intensity: self.params.intensity,
+            _padding: [0.; 3],
+        };
+        queue.write_typed_buffer(&self.uniform_buffer, 0, &[uniform]);
+        queue.write_typed_buffer(&self.vertex_buffer, 0, &build_near_field_quad(camera));
+    }
+}
+
+pub fn render(render_pass: &mut wgpu::RenderPass, atmosphere: &Atmosphere) {
+    render_pass.set_pipeline(&atmosphere.render_pipeline);
+    render_pass.set_bind_group(0, &atmosphere.camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &atmosphere.bind_group, &[]);
+    render_pass.set_typed_vertex_buffer(0, &atmosphere.vertex_buffer);
+    render_pass.set_typed_index_buffer(&atmosphere.index_buffer);
+    render_pass.draw_indexed(0..atmosphere.index_buffer.len as _, 0, 0..1);
+}
@@ -1,6 +1,8 @@
 use crate::{setup, utils::*};
 use anyhow::Result;
-use camera::{Camera, CameraUniform};
+use camera::{Camera, CameraUniform, Projection};
+
+pub const SHADER: &str = "shaders/background.wgsl";
 
 pub fn vec3_vertex_desc() -> wgpu::VertexBufferLayout<'static> {
     use std::mem;
@@ -14,16 +16,30 @@ pub fn vec3_vertex_desc() -> wgpu::VertexBufferLayout<'static> {
     }
 }
 
+/// Field of view the background pretends to have with an orthographic projection
+const ORTHOGRAPHIC_FOV_Y: f32 = std::f32::consts::FRAC_PI_4;
+
 pub fn build_near_field_quad(camera: &Camera) -> [Vec3; 4] {
     let look_dir = camera.look_dir;
     assert!(look_dir.is_normalized(), "look_dir not normalized");
 
-    let znear = camera.z_near * 1.01;
-    let half_height = (camera.fov_y * 0.5).tan() * znear;
+    let (distance, half_height) = match camera.projection {
+        Projection::Perspective { fov_y } => {
+            let znear = camera.z_near * 1.01;
+            (znear, (fov_y * 0.5).tan() * znear)
+        }
+        // the quad still covers the screen, but is placed so that its corners point in the
+        // directions of a perspective view, otherwise the stars would be smeared out
+        Projection::Orthographic { height } => (
+            0.5 * height / (ORTHOGRAPHIC_FOV_Y * 0.5).tan(),
+            0.5 * height,
+        ),
+    };
     let half_width = half_height * camera.aspect_ratio();
 
-    let near_center = look_dir * znear;
-    let right = look_dir.cross(camera.up).normalize();
+    let near_center = look_dir * distance;
+    // the rolled up of the view matrix, so the stars turn with the planet
+    let right = look_dir.cross(camera.view_up()).normalize();
     let up = right.cross(look_dir);
 
     let top_left = near_center + up * half_height - right * half_width;
@@ -51,10 +67,95 @@ pub fn create_near_field_quad_index_buffer(device: &wgpu::Device) -> Buffer<u16>
     })
 }
 
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Background Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[vec3_vertex_desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BackgroundUniform {
+    /// fraction of the sky cells that contain a star
+    star_density: f32,
+    /// offsets the star hash, only the low bits of the seed are used to keep the hash precise
+    seed: f32,
+    _padding: [f32; 2],
+}
+
+impl BackgroundUniform {
+    pub fn new(star_density: f32, seed: u32) -> Self {
+        BackgroundUniform {
+            star_density,
+            seed: (seed % 1024) as f32,
+            _padding: [0.; 2],
+        }
+    }
+}
+
+impl Default for BackgroundUniform {
+    fn default() -> Self {
+        BackgroundUniform::new(0.005, 0)
+    }
+}
+
+pub fn create_background_uniform_buffer(device: &wgpu::Device) -> Buffer<BackgroundUniform> {
+    device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+        label: Some("background_uniform_buffer"),
+        contents: &[BackgroundUniform::default()],
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
 pub struct Background {
     vertex_buffer: Buffer<Vec3>,
     index_buffer: Buffer<u16>,
+    uniform_buffer: Buffer<BackgroundUniform>,
+    camera_bind_group: wgpu::BindGroup,
     bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
     render_pipeline: wgpu::RenderPipeline,
 }
 
@@ -62,17 +163,23 @@ impl Background {
     pub fn new(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
         camera_uniform: &Buffer<CameraUniform>,
     ) -> Result<Self> {
         let vertex_buffer = create_near_field_quad_vertex_buffer(device);
         let index_buffer = create_near_field_quad_index_buffer(device);
+        let uniform_buffer = create_background_uniform_buffer(device);
+
+        let shader = setup::shader(device, SHADER)?;
 
-        let shader = setup::shader(device, "shaders/background.wgsl")?;
+        let camera_bind_group_layout = camera::bind_group_layout(device);
+        let camera_bind_group =
+            camera::bind_group(device, &camera_bind_group_layout, camera_uniform);
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -80,71 +187,67 @@ impl Background {
                 },
                 count: None,
             }],
-            label: Some("camera_bind_group_layout"),
+            label: Some("background_bind_group_layout"),
         });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: camera_uniform.as_entire_binding(),
+                resource: uniform_buffer.as_entire_binding(),
             }],
-            label: Some("camera_bind_group"),
+            label: Some("background_bind_group"),
         });
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Background Render Pipeline Layout"),
-                bind_group_layouts: &[&bind_group_layout],
+                bind_group_layouts: &[&camera_bind_group_layout, &bind_group_layout],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Background Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[vec3_vertex_desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        let format = config.format;
+        let render_pipeline = render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+        );
 
         Ok(Background {
             vertex_buffer,
             index_buffer,
+            uniform_buffer,
+            camera_bind_group,
             bind_group,
+            render_pipeline_layout,
+            format,
+            sample_count,
             render_pipeline,
         })
     }
 
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.render_pipeline = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok(render_pipeline(
+                device,
+                &self.render_pipeline_layout,
+                &shader,
+                self.format,
+                self.sample_count,
+            ))
+        })?;
+        Ok(())
+    }
+
+    pub fn set_stars(&self, queue: &wgpu::Queue, star_density: f32, seed: u32) {
+        let uniform = BackgroundUniform::new(star_density, seed);
+        queue.write_typed_buffer(&self.uniform_buffer, 0, &[uniform]);
+    }
+
     pub fn update_screen_quad(&self, queue: &wgpu::Queue, camera: &Camera) {
         queue.write_typed_buffer(&self.vertex_buffer, 0, &build_near_field_quad(camera));
     }
@@ -152,8 +255,27 @@ impl Background {
 
 pub fn render(render_pass: &mut wgpu::RenderPass, background: &Background) {
     render_pass.set_pipeline(&background.render_pipeline);
-    render_pass.set_bind_group(0, &background.bind_group, &[]);
+    render_pass.set_bind_group(0, &background.camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &background.bind_group, &[]);
     render_pass.set_typed_vertex_buffer(0, &background.vertex_buffer);
     render_pass.set_typed_index_buffer(&background.index_buffer);
     render_pass.draw_indexed(0..background.index_buffer.len as _, 0, 0..1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_quad_scales_with_the_field_of_view() {
+        let mut camera = Camera::new(&setup::offscreen_config(1600, 900), DVec3::X, -Vec3::X);
+        let ratios = [20f32, 45., 100.].map(|fov_y| {
+            camera.set_fov_y(fov_y.to_radians());
+            let [top_left, top_right, ..] = build_near_field_quad(&camera);
+            top_left.distance(top_right) / 2. / (fov_y.to_radians() / 2.).tan()
+        });
+        for ratio in ratios {
+            assert!((ratio / ratios[0] - 1.).abs() < 1e-5, "{ratios:?}");
+        }
+    }
+}
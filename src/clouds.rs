@@ -0,0 +1,240 @@
+use crate::planet::icosphere;
+use crate::{setup, utils::*, RADIUS};
+use anyhow::Result;
+use camera::CameraUniform;
+
+pub const SHADER: &str = "shaders/clouds.wgsl";
+
+/// Subdivisions of the cloud shell, it is smooth so it needs fewer triangles than the planet
+const SUBDIVISIONS: usize = 4;
+
+pub struct CloudParams {
+    /// radius of the cloud shell, above the highest mountains
+    pub radius: f64,
+    /// fraction of the sky covered by clouds, from 0 to 1
+    pub coverage: f32,
+    /// angular speed of the clouds around the z axis in radians per second
+    pub speed: f32,
+    pub opacity: f32,
+}
+
+impl Default for CloudParams {
+    fn default() -> Self {
+        CloudParams {
+            radius: 1.02 * RADIUS,
+            coverage: 0.45,
+            speed: 0.02,
+            opacity: 0.85,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CloudUniform {
+    time: f32,
+    coverage: f32,
+    speed: f32,
+    opacity: f32,
+}
+
+fn vertex_desc() -> wgpu::VertexBufferLayout<'static> {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Uint32x4];
+
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<PackedVec3>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBS,
+    }
+}
+
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Cloud Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[vertex_desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // only the near side of the shell, the far side is behind the planet
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        // hidden behind the planet, but doesn't hide what is drawn after it
+        depth_stencil: Some(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            ..camera::depth_stencil_state()
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Translucent shell around the planet with drifting procedural clouds
+pub struct Clouds {
+    pub params: CloudParams,
+    vertex_buffer: Buffer<PackedVec3>,
+    index_buffer: Buffer<u32>,
+    uniform_buffer: Buffer<CloudUniform>,
+    camera_bind_group: wgpu::BindGroup,
+    bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Clouds {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        camera_uniform: &Buffer<CameraUniform>,
+        params: CloudParams,
+    ) -> Result<Self> {
+        let (vertices, indices) = icosphere::subdivided(SUBDIVISIONS);
+        let vertices = vertices
+            .into_iter()
+            .map(|vertex| PackedVec3::from(vertex * params.radius))
+            .collect::<Vec<_>>();
+        let vertex_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("cloud_vertex_buffer"),
+            contents: &vertices,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("cloud_index_buffer"),
+            contents: &indices,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let uniform_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("cloud_uniform_buffer"),
+            len: 1,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = setup::shader(device, SHADER)?;
+
+        let camera_bind_group_layout = camera::bind_group_layout(device);
+        let camera_bind_group =
+            camera::bind_group(device, &camera_bind_group_layout, camera_uniform);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("cloud_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("cloud_bind_group"),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Cloud Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let format = config.format;
+        let render_pipeline = render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+        );
+
+        Ok(Clouds {
+            params,
+            vertex_buffer,
+            index_buffer,
+            uniform_buffer,
+            camera_bind_group,
+            bind_group,
+            render_pipeline_layout,
+            format,
+            sample_count,
+            render_pipeline,
+        })
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.render_pipeline = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok(render_pipeline(
+                device,
+                &self.render_pipeline_layout,
+                &shader,
+                self.format,
+                self.sample_count,
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// Moves the clouds to where they are after `time` seconds
+    pub fn update(&self, queue: &wgpu::Queue, time: f32) {
+        let uniform = CloudUniform {
+            time,
+            coverage: self.params.coverage,
+            speed: self.params.speed,
+            opacity: self.params.opacity,
+        };
+        queue.write_typed_buffer(&self.uniform_buffer, 0, &[uniform]);
+    }
+}
+
+/// Draws the clouds, after the opaque planet so they blend over it
+pub fn render(render_pass: &mut wgpu::RenderPass, clouds: &Clouds) {
+    render_pass.set_pipeline(&clouds.render_pipeline);
+    render_pass.set_bind_group(0, &clouds.camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &clouds.bind_group, &[]);
+    render_pass.set_typed_vertex_buffer(0, &clouds.vertex_buffer);
+    render_pass.set_typed_index_buffer(&clouds.index_buffer);
+    render_pass.draw_indexed(0..clouds.index_buffer.len as _, 0, 0..1);
+}
@@ -0,0 +1,396 @@
+use crate::clouds::CloudParams;
+use crate::moon::Satellite;
+use crate::planet::{self, ElevationParams, GenerationParams, NoiseParams};
+use crate::post_process::BloomParams;
+use crate::utils::camera::{self, Specular};
+use crate::utils::*;
+use crate::{setup, RADIUS};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// File the config is loaded from, in the working directory
+pub const PATH: &str = "planet-placer.toml";
+
+/// Settings of a scene. Missing fields in the config file keep their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub seed: u64,
+    pub subdivisions: usize,
+    pub plates: usize,
+    /// raindrops eroding the land after the tectonic elevation
+    pub erosion_iterations: usize,
+    /// largest height of the noise relief on the plates, in planet radii. 0 keeps the plates
+    /// flat between their boundaries.
+    pub noise_height: f64,
+    /// noise features per planet radius in the coarsest octave
+    pub noise_frequency: f64,
+    /// layers of noise, each finer than the one before
+    pub noise_octaves: usize,
+    /// factor the frequency grows by from one octave to the next
+    pub noise_lacunarity: f64,
+    /// factor the amplitude shrinks by from one octave to the next
+    pub noise_persistence: f64,
+    /// elevation of the water surface, in planet radii. Continents below it are flooded, Home
+    /// and End move it.
+    pub sea_level: f64,
+    /// distance of the orbiting camera from the axis of its orbit, in planet radii
+    pub orbit_distance: f64,
+    /// angular speed of the orbiting camera in radians per second
+    pub orbit_speed: f64,
+    /// axis the orbiting camera circles around, through the center of the planet
+    pub orbit_axis: [f64; 3],
+    /// angle the orbiting camera looks down onto the plane of its orbit from, in degrees
+    pub orbit_inclination: f64,
+    /// vertical field of view of the camera in degrees
+    pub fov_y: f64,
+    /// angular speed of the sun around the planet in radians per second
+    pub sun_speed: f64,
+    /// distance of the moon from the center of the planet, in planet radii
+    pub moon_orbit_radius: f64,
+    /// seconds the moon takes for an orbit
+    pub moon_orbit_period: f64,
+    /// radius of the moon, in planet radii
+    pub moon_radius: f64,
+    /// fraction of the planet covered by clouds, from 0 to 1
+    pub cloud_coverage: f64,
+    /// angular speed of the clouds around the planet in radians per second
+    pub cloud_speed: f64,
+    /// sharpness of the sun's highlight on water
+    pub specular_exponent: f64,
+    /// brightness of the sun's highlight on water, 0 turns it off
+    pub specular_intensity: f64,
+    /// brightness of the scene before the tonemapping, 1 keeps it as it is
+    pub exposure: f64,
+    /// brightness above which the scene blooms
+    pub bloom_threshold: f64,
+    /// how much of the bloom is added to the scene, 0 turns it off
+    pub bloom_intensity: f64,
+    /// halved and blurred copies of the bright parts, more spread the glow further
+    pub bloom_levels: usize,
+    /// sRGB color behind the stars, showing through the gaps between the plates
+    pub clear_color: [f64; 3],
+    /// steps per second of the plate motion and the animations, independent of the frame rate
+    pub simulation_rate: f64,
+    /// size of the window or the headless image in pixels
+    pub width: u32,
+    pub height: u32,
+    /// copies of the planet drawn instead of the one at the origin, as `[[instances]]` tables
+    pub instances: Vec<InstanceConfig>,
+}
+
+/// Placement of a copy of the planet, see `planet::Transform`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstanceConfig {
+    /// position of the center, in planet radii
+    pub translation: [f64; 3],
+    /// size relative to the planet
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        let generation = GenerationParams::default();
+        let noise = generation.noise;
+        let clouds = CloudParams::default();
+        let specular = Specular::default();
+        let bloom = BloomParams::default();
+        let satellite = Satellite::default();
+        let orbit = camera::OrbitParams::default();
+        AppConfig {
+            seed: 1,
+            subdivisions: generation.subdivisions,
+            plates: generation.num_plates,
+            erosion_iterations: generation.erosion_iterations,
+            noise_height: noise.height / RADIUS,
+            noise_frequency: noise.frequency * RADIUS,
+            noise_octaves: noise.octaves,
+            noise_lacunarity: noise.lacunarity,
+            noise_persistence: noise.persistence,
+            sea_level: ElevationParams::default().sea_level / RADIUS,
+            orbit_distance: orbit.radius / RADIUS,
+            orbit_speed: orbit.speed,
+            orbit_axis: orbit.axis.to_array(),
+            orbit_inclination: orbit.inclination.to_degrees(),
+            fov_y: 45.,
+            sun_speed: 0.05,
+            moon_orbit_radius: satellite.orbit_radius / RADIUS,
+            moon_orbit_period: satellite.orbit_period,
+            moon_radius: satellite.radius / RADIUS,
+            cloud_coverage: clouds.coverage as f64,
+            cloud_speed: clouds.speed as f64,
+            specular_exponent: specular.exponent as f64,
+            specular_intensity: specular.intensity as f64,
+            exposure: 1.,
+            bloom_threshold: bloom.threshold as f64,
+            bloom_intensity: bloom.intensity as f64,
+            bloom_levels: bloom.levels,
+            clear_color: [0.1; 3],
+            simulation_rate: 60.,
+            width: 1280,
+            height: 720,
+            instances: Vec::new(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Reads the config from `path`, or the defaults if the file doesn't exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Checks that the planet can be generated and the values are in range
+    pub fn validate(&self) -> Result<()> {
+        anyhow::ensure!(
+            self.subdivisions <= planet::MAX_SUBDIVISIONS,
+            "{} subdivisions are more than the supported {}",
+            self.subdivisions,
+            planet::MAX_SUBDIVISIONS,
+        );
+        let regions = planet::region_count(self.subdivisions);
+        anyhow::ensure!(
+            (1..=regions).contains(&self.plates),
+            "{} plates don't fit on the {regions} regions of {} subdivisions",
+            self.plates,
+            self.subdivisions,
+        );
+        anyhow::ensure!(
+            self.noise_height >= 0. && self.noise_frequency > 0. && self.noise_lacunarity > 0.,
+            "noise height {} is negative, or frequency {} or lacunarity {} is not positive",
+            self.noise_height,
+            self.noise_frequency,
+            self.noise_lacunarity,
+        );
+        anyhow::ensure!(
+            (0. ..=1.).contains(&self.noise_persistence),
+            "noise persistence {} is not between 0 and 1",
+            self.noise_persistence,
+        );
+        anyhow::ensure!(
+            (0. ..=1.).contains(&self.cloud_coverage),
+            "cloud coverage {} is not between 0 and 1",
+            self.cloud_coverage,
+        );
+        let (min_fov_y, max_fov_y) = (
+            camera::MIN_FOV_Y.to_degrees() as f64,
+            camera::MAX_FOV_Y.to_degrees() as f64,
+        );
+        anyhow::ensure!(
+            (min_fov_y..=max_fov_y).contains(&self.fov_y),
+            "field of view {}° is not between {min_fov_y:.0}° and {max_fov_y:.0}°",
+            self.fov_y,
+        );
+        anyhow::ensure!(
+            self.moon_radius > 0. && self.moon_orbit_period > 0.,
+            "moon radius {} and orbit period {} are not positive",
+            self.moon_radius,
+            self.moon_orbit_period,
+        );
+        anyhow::ensure!(
+            self.moon_orbit_radius > 1. + self.moon_radius,
+            "moon orbit radius {} is too small, the moon would touch the planet",
+            self.moon_orbit_radius,
+        );
+        anyhow::ensure!(
+            DVec3::from(self.orbit_axis).length() > 0.,
+            "the orbit axis is zero",
+        );
+        anyhow::ensure!(
+            self.orbit_inclination.abs() < 90.,
+            "orbit inclination {}° is not between -90° and 90°",
+            self.orbit_inclination,
+        );
+        anyhow::ensure!(
+            self.exposure > 0.,
+            "exposure {} is not positive",
+            self.exposure,
+        );
+        anyhow::ensure!(
+            self.simulation_rate > 0.,
+            "simulation rate {} is not positive",
+            self.simulation_rate,
+        );
+        for instance in &self.instances {
+            anyhow::ensure!(
+                instance.scale > 0.,
+                "instance scale {} is not positive",
+                instance.scale,
+            );
+        }
+        anyhow::ensure!(self.bloom_levels > 0, "bloom needs at least one level");
+        anyhow::ensure!(
+            self.clear_color.iter().all(|c| (0. ..=1.).contains(c)),
+            "clear color {:?} is not between 0 and 1",
+            self.clear_color,
+        );
+        Ok(())
+    }
+
+    pub fn generation_params(&self) -> GenerationParams {
+        GenerationParams {
+            subdivisions: self.subdivisions,
+            num_plates: self.plates,
+            erosion_iterations: self.erosion_iterations,
+            noise: NoiseParams {
+                height: self.noise_height * RADIUS,
+                frequency: self.noise_frequency / RADIUS,
+                octaves: self.noise_octaves,
+                lacunarity: self.noise_lacunarity,
+                persistence: self.noise_persistence,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn elevation_params(&self) -> ElevationParams {
+        ElevationParams {
+            sea_level: self.sea_level * RADIUS,
+            ..Default::default()
+        }
+    }
+
+    /// Transforms of the planet copies, none when the config doesn't list any
+    pub fn instances(&self) -> Vec<planet::Transform> {
+        self.instances
+            .iter()
+            .map(|instance| planet::Transform {
+                translation: DVec3::from(instance.translation) * RADIUS,
+                scale: instance.scale,
+            })
+            .collect()
+    }
+
+    pub fn orbit_params(&self) -> camera::OrbitParams {
+        camera::OrbitParams {
+            axis: DVec3::from(self.orbit_axis).normalize(),
+            radius: self.orbit_distance * RADIUS,
+            speed: self.orbit_speed,
+            inclination: self.orbit_inclination.to_radians(),
+        }
+    }
+
+    pub fn satellite(&self) -> Satellite {
+        Satellite {
+            orbit_radius: self.moon_orbit_radius * RADIUS,
+            orbit_period: self.moon_orbit_period,
+            radius: self.moon_radius * RADIUS,
+        }
+    }
+
+    pub fn cloud_params(&self) -> CloudParams {
+        CloudParams {
+            coverage: self.cloud_coverage as f32,
+            speed: self.cloud_speed as f32,
+            ..Default::default()
+        }
+    }
+
+    pub fn bloom_params(&self) -> BloomParams {
+        BloomParams {
+            threshold: self.bloom_threshold as f32,
+            intensity: self.bloom_intensity as f32,
+            levels: self.bloom_levels,
+        }
+    }
+
+    pub fn specular(&self) -> Specular {
+        Specular {
+            exponent: self.specular_exponent as f32,
+            intensity: self.specular_intensity as f32,
+        }
+    }
+
+    /// The clear color for a target of `format`, converted to linear for targets taking linear
+    /// colors
+    pub fn clear_color(&self, format: wgpu::TextureFormat) -> wgpu::Color {
+        let [r, g, b] = match setup::linear_target(format) {
+            true => self.clear_color.map(srgb_to_linear),
+            false => self.clear_color,
+        };
+        wgpu::Color { r, g, b, a: 1.0 }
+    }
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Command line arguments, overriding the config file
+#[derive(clap::Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    #[arg(long)]
+    pub seed: Option<u64>,
+    #[arg(long)]
+    pub subdivisions: Option<usize>,
+    #[arg(long)]
+    pub plates: Option<usize>,
+    #[arg(long)]
+    pub width: Option<u32>,
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// render a single frame into this PNG file instead of opening a window
+    #[arg(long, value_name = "OUTPUT")]
+    pub headless: Option<PathBuf>,
+    /// compare the headless render with this PNG and fail if they differ
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    pub reference: Option<PathBuf>,
+    /// difference of a color channel from the reference that is still tolerated, absorbs the
+    /// rounding differences between GPUs
+    #[arg(long, default_value_t = 4)]
+    pub tolerance: u8,
+    /// camera position for the headless render, by default on the orbit
+    #[arg(long, num_args = 3, value_names = ["X", "Y", "Z"], allow_negative_numbers = true)]
+    pub camera: Option<Vec<f64>>,
+    /// start with the camera saved in this file instead of orbiting, see F5
+    #[arg(long, value_name = "FILE")]
+    pub camera_pose: Option<PathBuf>,
+    /// load the planet from this JSON file instead of generating it from the seed
+    #[arg(long, value_name = "FILE")]
+    pub load: Option<PathBuf>,
+    /// write the generated planet to this JSON file
+    #[arg(long, value_name = "FILE")]
+    pub save: Option<PathBuf>,
+    /// wrap this equirectangular PNG or JPEG image around the planet
+    #[arg(long, value_name = "FILE")]
+    pub texture: Option<PathBuf>,
+    /// sRGB color behind the stars, from 0 to 1
+    #[arg(long, num_args = 3, value_names = ["R", "G", "B"])]
+    pub clear_color: Option<Vec<f64>>,
+}
+
+impl Args {
+    pub fn apply(&self, config: &mut AppConfig) {
+        config.seed = self.seed.unwrap_or(config.seed);
+        config.subdivisions = self.subdivisions.unwrap_or(config.subdivisions);
+        config.plates = self.plates.unwrap_or(config.plates);
+        config.width = self.width.unwrap_or(config.width);
+        config.height = self.height.unwrap_or(config.height);
+        if let Some(&[r, g, b]) = self.clear_color.as_deref() {
+            config.clear_color = [r, g, b];
+        }
+    }
+
+    pub fn camera_position(&self, config: &AppConfig) -> DVec3 {
+        match self.camera.as_deref() {
+            Some(&[x, y, z]) => dvec3(x, y, z),
+            _ => config.orbit_params().position(0.),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{setup, targets::Targets, utils::*};
+use anyhow::Result;
+use camera::CameraUniform;
+
+pub const SHADER: &str = "shaders/depth_debug.wgsl";
+
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    depth_sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let fragment_entry_point = match depth_sample_count {
+        1 => "fs_main",
+        _ => "fs_multisampled",
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Depth Debug Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment_entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        // the depth texture is sampled, it can't be the attachment at the same time
+        depth_stencil: None,
+        // drawn straight into the output, after the tonemapping
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Binding of the depth texture in the shader, multisampled textures have their own type
+fn depth_binding(sample_count: u32) -> u32 {
+    match sample_count {
+        1 => 0,
+        _ => 1,
+    }
+}
+
+fn bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    targets: &Targets,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: depth_binding(targets.sample_count()),
+            resource: wgpu::BindingResource::TextureView(targets.depth_view()),
+        }],
+        label: Some("depth_debug_bind_group"),
+    })
+}
+
+/// Fullscreen view of the camera depth as grayscale, from black at the near plane to white
+/// at `DEPTH_RANGE` in the shader
+pub struct DepthDebug {
+    pub visible: bool,
+    camera_bind_group: wgpu::BindGroup,
+    /// kept to rebuild the bind group when the depth texture is recreated
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    /// of the depth texture, the pipeline itself doesn't multisample
+    sample_count: u32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl DepthDebug {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &Targets,
+        camera_uniform: &Buffer<CameraUniform>,
+    ) -> Result<Self> {
+        let shader = setup::shader(device, SHADER)?;
+        let sample_count = targets.sample_count();
+
+        let camera_bind_group_layout = camera::bind_group_layout(device);
+        let camera_bind_group =
+            camera::bind_group(device, &camera_bind_group_layout, camera_uniform);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: depth_binding(sample_count),
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: sample_count > 1,
+                },
+                count: None,
+            }],
+            label: Some("depth_debug_bind_group_layout"),
+        });
+        let bind_group = bind_group(device, &bind_group_layout, targets);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Depth Debug Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let format = config.format;
+        let render_pipeline = render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+        );
+
+        Ok(DepthDebug {
+            visible: false,
+            camera_bind_group,
+            bind_group_layout,
+            bind_group,
+            render_pipeline_layout,
+            format,
+            sample_count,
+            render_pipeline,
+        })
+    }
+
+    /// Binds the new depth texture after the targets were recreated
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &Targets) {
+        self.bind_group = bind_group(device, &self.bind_group_layout, targets);
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.render_pipeline = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok(render_pipeline(
+                device,
+                &self.render_pipeline_layout,
+                &shader,
+                self.format,
+                self.sample_count,
+            ))
+        })?;
+        Ok(())
+    }
+}
+
+/// Draws the depth over the whole output, in a pass without MSAA or the depth attachment
+pub fn render(render_pass: &mut wgpu::RenderPass, depth_debug: &DepthDebug) {
+    render_pass.set_pipeline(&depth_debug.render_pipeline);
+    render_pass.set_bind_group(0, &depth_debug.camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &depth_debug.bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
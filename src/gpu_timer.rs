@@ -0,0 +1,141 @@
+use crate::utils::*;
+use std::sync::mpsc;
+
+/// Passes that are timed, in the order of their timestamps in the query set
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pass {
+    Background,
+    Planet,
+}
+const PASSES: [Pass; 2] = [Pass::Background, Pass::Planet];
+/// Number of frames the logged GPU times are averaged over
+const WINDOW: u32 = 120;
+
+/// Measures the GPU time of the render passes with timestamp queries. The results are read back
+/// without blocking, a frame is skipped while the previous readback is still in flight.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: Buffer<u64>,
+    readback_buffer: Buffer<u64>,
+    /// nanoseconds per timestamp tick
+    period: f64,
+    /// set when the queries were resolved into the readback buffer in the current frame
+    resolved: bool,
+    pending: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+    /// summed time of every pass over the current window, in milliseconds
+    totals: [f64; PASSES.len()],
+    frames: u32,
+}
+
+impl GpuTimer {
+    /// None when the device doesn't support `TIMESTAMP_QUERY`
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let count = 2 * PASSES.len();
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu_timer_query_set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: count as u32,
+        });
+        let resolve_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("gpu_timer_resolve_buffer"),
+            len: count,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("gpu_timer_readback_buffer"),
+            len: count,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Some(GpuTimer {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period() as f64,
+            resolved: false,
+            pending: None,
+            totals: [0.; PASSES.len()],
+            frames: 0,
+        })
+    }
+
+    /// Writes a timestamp at the beginning and the end of the render pass
+    pub fn timestamp_writes(&self, pass: Pass) -> wgpu::RenderPassTimestampWrites<'_> {
+        let index = PASSES.iter().position(|&p| p == pass).unwrap() as u32;
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(2 * index),
+            end_of_pass_write_index: Some(2 * index + 1),
+        }
+    }
+
+    /// Copies the timestamps of the frame into the readback buffer, unless it is still mapped
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.pending.is_some() {
+            return;
+        }
+        let count = self.resolve_buffer.len;
+        encoder.resolve_query_set(&self.query_set, 0..count as u32, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            (count * std::mem::size_of::<u64>()) as u64,
+        );
+        self.resolved = true;
+    }
+
+    /// Drops the readback in flight, unmapping the buffer calls its map callback with an error
+    /// instead of leaving it pending
+    pub fn cancel(&mut self) {
+        if self.pending.take().is_some() {
+            self.readback_buffer.unmap();
+        }
+        self.resolved = false;
+    }
+
+    /// Starts reading back the timestamps resolved this frame and collects the ones that
+    /// arrived, logging the average pass times once per window. Call after submitting.
+    pub fn read(&mut self, device: &wgpu::Device) {
+        if std::mem::take(&mut self.resolved) {
+            let (sender, receiver) = mpsc::channel();
+            self.readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            self.pending = Some(receiver);
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+        let Some(Ok(result)) = self.pending.as_ref().map(|pending| pending.try_recv()) else {
+            return;
+        };
+        self.pending = None;
+        if result.is_ok() {
+            let timestamps: Vec<u64> =
+                bytemuck::pod_collect_to_vec(&self.readback_buffer.slice(..).get_mapped_range());
+            for (total, pass) in self.totals.iter_mut().zip(timestamps.chunks_exact(2)) {
+                *total += pass[1].saturating_sub(pass[0]) as f64 * self.period * 1e-6;
+            }
+            self.readback_buffer.unmap();
+            self.frames += 1;
+        }
+
+        if self.frames == WINDOW {
+            let times = PASSES
+                .iter()
+                .zip(self.totals)
+                .map(|(pass, total)| format!("{pass:?} {:.3} ms", total / WINDOW as f64))
+                .collect::<Vec<_>>();
+            log::info!("GPU time: {}", times.join(", "));
+            self.totals = [0.; PASSES.len()];
+            self.frames = 0;
+        }
+    }
+}
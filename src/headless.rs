@@ -0,0 +1,160 @@
+use crate::config::AppConfig;
+use crate::{
+    atmosphere, background, clouds, moon, planet, post_process, screenshot, setup, shadow_map,
+    targets, utils::*, LIGHT_DIR,
+};
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Renders a single frame into a PNG without opening a window
+pub struct HeadlessOptions {
+    pub width: u32,
+    pub height: u32,
+    /// the camera looks from here towards the center of the planet
+    pub camera_position: DVec3,
+    /// camera saved with `Camera::save`, used instead of `camera_position`
+    pub camera_pose: Option<PathBuf>,
+    /// equirectangular image wrapped around the planet, see `Planet::with_texture`
+    pub texture: Option<PathBuf>,
+    pub output: PathBuf,
+    /// image the render has to match, see `screenshot::compare`
+    pub reference: Option<PathBuf>,
+    pub tolerance: u8,
+    /// samples per pixel, `None` for MSAA if the adapter supports it
+    pub sample_count: Option<u32>,
+    /// render on a GL adapter if there is no PRIMARY one, see `setup::offscreen_adapter`
+    pub fallback_adapter: bool,
+}
+
+impl HeadlessOptions {
+    /// Renders the planet and the scene described by `app_config` from the camera of the options
+    pub fn render(&self, app_config: &AppConfig, planet_data: planet::PlanetData) -> Result<()> {
+        let adapter = setup::offscreen_adapter(self.fallback_adapter)?;
+        let (device, queue) = setup::device_queue(&adapter)?;
+
+        let config = setup::offscreen_config(self.width, self.height);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless Target"),
+            size: wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let scene_config = post_process::scene_config(&config);
+        let sample_count = self
+            .sample_count
+            .unwrap_or_else(|| setup::sample_count(&adapter, &scene_config));
+        let targets = targets::Targets::new(&device, &config, sample_count);
+        let camera = match &self.camera_pose {
+            Some(path) => camera::Camera::load(path, &config)?,
+            None => {
+                let mut camera = camera::Camera::new(
+                    &config,
+                    self.camera_position,
+                    -self.camera_position.normalize().as_vec3(),
+                );
+                camera.set_fov_y((app_config.fov_y as f32).to_radians());
+                camera
+            }
+        };
+        let camera_uniform = camera::uniform_buffer(&device);
+
+        let background =
+            background::Background::new(&device, &scene_config, sample_count, &camera_uniform)?;
+        let shadow_map = shadow_map::ShadowMap::new(&device);
+        let mut planet = planet::Planet::from_data(
+            &device,
+            &queue,
+            &scene_config,
+            sample_count,
+            setup::vertex_storage(&adapter),
+            &camera_uniform,
+            &shadow_map,
+            planet_data,
+            &app_config.elevation_params(),
+        )?;
+        if let Some(path) = &self.texture {
+            planet = planet.with_texture(&device, &queue, path)?;
+        }
+        let instances = app_config.instances();
+        if !instances.is_empty() {
+            planet.set_instances(&device, &queue, &instances);
+        }
+        let moon = moon::Moon::new(
+            &device,
+            &scene_config,
+            sample_count,
+            &camera_uniform,
+            &shadow_map,
+            app_config.satellite(),
+        )?;
+        let clouds = clouds::Clouds::new(
+            &device,
+            &scene_config,
+            sample_count,
+            &camera_uniform,
+            app_config.cloud_params(),
+        )?;
+        let atmosphere =
+            atmosphere::Atmosphere::new(&device, &scene_config, sample_count, &camera_uniform)?;
+        let post_process = post_process::PostProcess::new(
+            &device,
+            &config,
+            &targets,
+            app_config.exposure as f32,
+            app_config.bloom_params(),
+        )?;
+
+        camera::write_view_projection(
+            &queue,
+            &camera,
+            LIGHT_DIR,
+            app_config.specular(),
+            &camera_uniform,
+        );
+        background.update_screen_quad(&queue, &camera);
+        moon.update(&queue, 0.);
+        shadow_map.update(&queue, LIGHT_DIR, moon.satellite.reach());
+        clouds.update(&queue, 0.);
+        atmosphere.update(&queue, &camera, LIGHT_DIR);
+        post_process.update(&queue);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Encoder"),
+        });
+        crate::draw(
+            &mut encoder,
+            &view,
+            &targets,
+            &shadow_map,
+            &background,
+            &planet,
+            &moon,
+            &clouds,
+            &atmosphere,
+            &post_process,
+            app_config.clear_color(post_process::HDR_FORMAT),
+            None,
+        );
+        let readback = screenshot::copy_texture(&device, &mut encoder, &texture);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        readback.save_png(&device, &self.output)?;
+        log::info!("Saved render to {}", self.output.display());
+
+        if let Some(reference) = &self.reference {
+            screenshot::compare(&self.output, reference, self.tolerance)?;
+            log::info!("Render matches {}", reference.display());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use notify::Watcher;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+/// Watches the shader directory for changes
+pub struct ShaderReloader {
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+impl ShaderReloader {
+    pub fn new(directory: impl AsRef<Path>) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(directory.as_ref(), notify::RecursiveMode::NonRecursive)?;
+        Ok(ShaderReloader {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// The files that were created or modified since the last call
+    pub fn changed(&self) -> HashSet<PathBuf> {
+        let mut changed = HashSet::new();
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) if event.kind.is_create() || event.kind.is_modify() => {
+                    changed.extend(event.paths)
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!("Shader watcher error: {err}"),
+            }
+        }
+        changed
+    }
+}
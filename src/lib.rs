@@ -0,0 +1,121 @@
+//! Generation and rendering of the planet, shared by the viewer, the benchmarks and the tests
+
+pub mod atmosphere;
+pub mod background;
+pub mod clouds;
+pub mod config;
+pub mod depth_debug;
+pub mod gpu_timer;
+pub mod headless;
+pub mod hot_reload;
+pub mod measure;
+pub mod moon;
+pub mod planet;
+pub mod post_process;
+pub mod screenshot;
+pub mod setup;
+pub mod shadow_map;
+pub mod targets;
+pub mod text_overlay;
+pub mod utils;
+
+/// Radius of the planet, the unit generation and rendering are measured in
+pub const RADIUS: f64 = 1.0;
+/// Direction towards the sun at the start, it then orbits around the z axis
+pub const LIGHT_DIR: utils::Vec3 = utils::Vec3::new(1.0, 0.5, 0.5);
+
+/// Records the render passes of one frame into `view`, shared by the window and headless
+/// rendering. The scene is drawn into the HDR target of `targets` and then tonemapped into
+/// `view`.
+#[allow(clippy::too_many_arguments)]
+pub fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    targets: &targets::Targets,
+    shadow_map: &shadow_map::ShadowMap,
+    background: &background::Background,
+    planet: &planet::Planet,
+    moon: &moon::Moon,
+    clouds: &clouds::Clouds,
+    atmosphere: &atmosphere::Atmosphere,
+    post_process: &post_process::PostProcess,
+    clear_color: wgpu::Color,
+    gpu_timer: Option<&gpu_timer::GpuTimer>,
+) {
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(shadow_map.depth_stencil_attachment()),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        planet::render_shadow(&mut render_pass, planet);
+        moon::render_shadow(&mut render_pass, moon);
+    }
+
+    let depth_prepass = planet.depth_prepass();
+    if depth_prepass {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Prepass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(targets.depth_stencil_attachment(false)),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        planet::render_depth(&mut render_pass, planet);
+    }
+
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(
+                targets.color_attachment(wgpu::LoadOp::Clear(clear_color)),
+            )],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: gpu_timer
+                .map(|gpu_timer| gpu_timer.timestamp_writes(gpu_timer::Pass::Background)),
+        });
+
+        background::render(&mut render_pass, background);
+    }
+
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(targets.color_attachment(wgpu::LoadOp::Load))],
+            depth_stencil_attachment: Some(targets.depth_stencil_attachment(depth_prepass)),
+            occlusion_query_set: None,
+            timestamp_writes: gpu_timer
+                .map(|gpu_timer| gpu_timer.timestamp_writes(gpu_timer::Pass::Planet)),
+        });
+
+        planet::render(&mut render_pass, planet);
+        moon::render(&mut render_pass, moon);
+        clouds::render(&mut render_pass, clouds);
+        atmosphere::render(&mut render_pass, atmosphere);
+    }
+
+    post_process::bloom(encoder, post_process);
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Process Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        post_process::render(&mut render_pass, post_process);
+    }
+}
@@ -1,43 +1,199 @@
+use planet_placer::{
+    atmosphere, background, clouds, config, depth_debug, draw, gpu_timer, headless, hot_reload,
+    measure, moon, planet, post_process, screenshot, setup, shadow_map, targets, text_overlay,
+    utils, LIGHT_DIR, RADIUS,
+};
 use utils::*;
 use winit::{
     event::*,
     event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
 };
 
-mod background;
-mod planet;
-mod setup;
-mod utils;
-
-const RADIUS: f64 = 1.0;
+/// Width of the exported heightmap, it is half as high
+const HEIGHTMAP_WIDTH: u32 = 2048;
+/// File the camera is saved to with F5 and loaded from with F9, in the working directory
+const CAMERA_PATH: &str = "camera.json";
+/// Seconds the camera takes to fly to a picked region
+const FLIGHT_DURATION: f64 = 1.5;
+/// Roll per press of Q or E, holding them rolls with the key repeat
+const ROLL_STEP: f32 = 5f32.to_radians();
+/// Turn of the sun per press of an arrow key, holding them turns it with the key repeat
+const SUN_STEP: f32 = 5f32.to_radians();
+/// Highest elevation of the sun above the equator, it stays off the poles where the azimuth
+/// is undefined
+const MAX_SUN_ELEVATION: f32 = 89f32.to_radians();
+/// Factor the exposure changes by per key press
+const EXPOSURE_STEP: f32 = 1.25;
+/// Change of the sea level per press of Home or End, holding them moves it with the key repeat
+const SEA_LEVEL_STEP: f64 = 0.0005 * RADIUS;
+/// Slowest and fastest simulation speed, the brackets halve and double it
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 8.;
+/// Change of the field of view per line scrolled with Ctrl held
+const FOV_STEP: f32 = 2f32.to_radians();
+/// Pixels of touchpad scrolling that count as one line
+const PIXELS_PER_LINE: f32 = 20.;
+/// Time after the last subdivision change before the planet is rebuilt
+const REBUILD_DELAY: std::time::Duration = std::time::Duration::from_millis(300);
 
 pub fn main() -> anyhow::Result<()> {
     env_logger::init();
 
+    let args = <config::Args as clap::Parser>::parse();
+    let mut app_config = config::AppConfig::load(config::PATH)?;
+    args.apply(&mut app_config);
+    app_config.validate()?;
+    log::debug!("Effective config:\n{}", toml::to_string(&app_config)?);
+    let mut generation_params = app_config.generation_params();
+
+    let mut elevation_params = app_config.elevation_params();
+    // the window generates the planet on the GPU once there is a device, saving and headless
+    // rendering need it right away
+    let generate =
+        || planet::PlanetData::generate(app_config.seed, &generation_params, &elevation_params);
+    let mut planet_data = match &args.load {
+        Some(path) => Some(planet::PlanetData::load(
+            path,
+            &generation_params,
+            &elevation_params,
+        )?),
+        None => None,
+    };
+    if let Some(path) = &args.save {
+        planet_data.get_or_insert_with(generate).save(path)?;
+        log::info!("Saved planet to {}", path.display());
+    }
+
+    if let Some(output) = &args.headless {
+        let options = headless::HeadlessOptions {
+            width: app_config.width,
+            height: app_config.height,
+            camera_position: args.camera_position(&app_config),
+            camera_pose: args.camera_pose.clone(),
+            texture: args.texture.clone(),
+            output: output.clone(),
+            reference: args.reference.clone(),
+            tolerance: args.tolerance,
+            sample_count: None,
+            fallback_adapter: false,
+        };
+        return options.render(&app_config, planet_data.unwrap_or_else(generate));
+    }
+
     let event_loop = EventLoop::new().unwrap();
-    let window = setup::window(&event_loop)?;
+    let window = setup::window(
+        &event_loop,
+        &window_title(app_config.seed, generation_params.subdivisions, None),
+        app_config.width,
+        app_config.height,
+    )?;
     let instance = setup::instance();
     let surface = unsafe { setup::surface(&instance, &window) }?;
-    let adapter = setup::adapter(&instance, &surface).unwrap();
+    let adapter = setup::adapter(&instance, Some(&surface))?;
     let (device, queue) = setup::device_queue(&adapter)?;
-    let mut config = setup::surface_config(&surface, &adapter);
+    let mut config =
+        setup::surface_config(&surface, &adapter, setup::PresentPreference::from_env());
     let mut surface_configured =
         setup::configure_surface(&surface, &device, &mut config, window.inner_size());
 
+    // the scene is rendered into an HDR target and tonemapped to the surface
+    let scene_config = post_process::scene_config(&config);
+    let sample_count = setup::sample_count(&adapter, &scene_config);
+    let mut targets = targets::Targets::new(&device, &config, sample_count);
     let mut camera = camera::Camera::new(
-        &device,
         &config,
         dvec3(0., 0., RADIUS),
         vec3(0., -1., -2.).normalize(),
     );
+    camera.set_fov_y((app_config.fov_y as f32).to_radians());
+    let mut camera_controller = camera::CameraController::new(RADIUS, 0.002);
+    if let Some(path) = &args.camera_pose {
+        camera = camera::Camera::load(path, &config)?;
+        camera_controller.mode = camera::CameraMode::Fixed;
+    }
     let camera_uniform = camera::uniform_buffer(&device);
 
-    let background = background::Background::new(&device, &config, &camera_uniform)?;
-    let planet = planet::Planet::new(&device, &config, &camera_uniform)?;
+    let mut background =
+        background::Background::new(&device, &scene_config, sample_count, &camera_uniform)?;
+    let shadow_map = shadow_map::ShadowMap::new(&device);
+    let mut planet = planet::Planet::from_data(
+        &device,
+        &queue,
+        &scene_config,
+        sample_count,
+        setup::vertex_storage(&adapter),
+        &camera_uniform,
+        &shadow_map,
+        planet_data.unwrap_or_else(|| {
+            planet::PlanetData::generate_on_gpu(
+                &device,
+                &queue,
+                app_config.seed,
+                &generation_params,
+                &elevation_params,
+            )
+        }),
+        &elevation_params,
+    )?;
+    if let Some(path) = &args.texture {
+        planet = planet.with_texture(&device, &queue, path)?;
+    }
+    let instances = app_config.instances();
+    if !instances.is_empty() {
+        planet.set_instances(&device, &queue, &instances);
+    }
+    let mut moon = moon::Moon::new(
+        &device,
+        &scene_config,
+        sample_count,
+        &camera_uniform,
+        &shadow_map,
+        app_config.satellite(),
+    )?;
+    let mut clouds = clouds::Clouds::new(
+        &device,
+        &scene_config,
+        sample_count,
+        &camera_uniform,
+        app_config.cloud_params(),
+    )?;
+    let mut atmosphere =
+        atmosphere::Atmosphere::new(&device, &scene_config, sample_count, &camera_uniform)?;
+    let mut post_process = post_process::PostProcess::new(
+        &device,
+        &config,
+        &targets,
+        app_config.exposure as f32,
+        app_config.bloom_params(),
+    )?;
+    let mut depth_debug =
+        depth_debug::DepthDebug::new(&device, &config, &targets, &camera_uniform)?;
+    let mut text_overlay = text_overlay::TextOverlay::new(&device, &queue, &config)?;
+    let mut frame_stats = text_overlay::FrameStats::new();
+    let mut gpu_timer = gpu_timer::GpuTimer::new(&device, &queue);
 
-    let start = std::time::Instant::now();
+    let shader_reloader = hot_reload::ShaderReloader::new("shaders")
+        .inspect_err(|err| log::warn!("Shader hot reloading is disabled: {err}"))
+        .ok();
+    let mut screenshot_requested = false;
+    let mut cursor = Vec2::ZERO;
+    let mut measurement = measure::Measurement::default();
+    let mut modifiers = ModifiersState::empty();
+    // the planet is rebuilt once no +/- was pressed for a moment
+    let mut subdivisions = generation_params.subdivisions;
+    let mut subdivisions_changed = None;
+
+    let mut clock = clock::SimulationClock::new(app_config.simulation_rate);
+    let mut sim_state = clock::SimState::default();
+    let mut sun = Sun::new(LIGHT_DIR);
+    let orbit = app_config.orbit_params();
+    let mut last_frame = std::time::Instant::now();
     event_loop.run(move |event, control_flow| match event {
+        Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } => camera_controller.process_mouse_motion(delta),
         Event::WindowEvent {
             ref event,
             window_id,
@@ -45,7 +201,14 @@ pub fn main() -> anyhow::Result<()> {
             WindowEvent::Resized(new_size) => {
                 surface_configured =
                     setup::configure_surface(&surface, &device, &mut config, *new_size);
-                camera.resize(&device, &config);
+                resize(
+                    &device,
+                    &config,
+                    &mut camera,
+                    &mut targets,
+                    &mut post_process,
+                    &mut depth_debug,
+                );
             }
             WindowEvent::RedrawRequested => {
                 window.request_redraw();
@@ -54,11 +217,116 @@ pub fn main() -> anyhow::Result<()> {
                     return;
                 }
 
-                update(start.elapsed().as_secs_f64(), &mut camera);
-                camera::write_view_projection(&queue, &camera, &camera_uniform);
+                if let Some(reloader) = &shader_reloader {
+                    reload_shaders(
+                        reloader,
+                        &device,
+                        &mut background,
+                        &mut planet,
+                        &mut moon,
+                        &mut clouds,
+                        &mut atmosphere,
+                        &mut post_process,
+                        &mut depth_debug,
+                        &mut text_overlay,
+                    );
+                }
+
+                let now = std::time::Instant::now();
+                let dt = (now - last_frame).as_secs_f64();
+                last_frame = now;
+                // the averages are updated twice per second, often enough for the title
+                if frame_stats.tick(dt) {
+                    window.set_title(&window_title(
+                        app_config.seed,
+                        generation_params.subdivisions,
+                        Some(frame_stats.fps),
+                    ));
+                }
+
+                if subdivisions_changed.is_some_and(|changed| now - changed >= REBUILD_DELAY) {
+                    subdivisions_changed = None;
+                    if subdivisions != generation_params.subdivisions {
+                        generation_params.subdivisions = subdivisions;
+                        let data = planet::PlanetData::generate_on_gpu(
+                            &device,
+                            &queue,
+                            app_config.seed,
+                            &generation_params,
+                            &elevation_params,
+                        );
+                        planet.set_data(&device, &queue, data);
+                        log::info!(
+                            "Rebuilt the planet with {subdivisions} subdivisions: \
+                             {} vertices, {} triangles",
+                            planet.vertex_count(),
+                            planet.data.regions.len()
+                        );
+                    }
+                }
+
+                // the simulation advances in fixed steps, the orbits, sun and clouds are
+                // rendered at the time between the last two of them
+                let steps = clock.advance(dt, &mut sim_state);
+                let t = clock.interpolated_time();
+                match camera_controller.mode {
+                    camera::CameraMode::Orbit => update(t, &mut camera, &orbit),
+                    camera::CameraMode::Fixed => camera.update_flight(t),
+                    camera::CameraMode::Fly | camera::CameraMode::Arcball => {
+                        camera_controller.update(&mut camera, dt)
+                    }
+                }
+                if planet.animate_plates && steps > 0 {
+                    planet.step(&device, &queue, clock.step(), steps);
+                }
+                planet.cull(&device, &queue, &camera);
+                let sun_dir = sun.direction(t, &app_config);
+                camera::write_view_projection(
+                    &queue,
+                    &camera,
+                    sun_dir,
+                    app_config.specular(),
+                    &camera_uniform,
+                );
                 background.update_screen_quad(&queue, &camera);
+                moon.update(&queue, t);
+                shadow_map.update(&queue, sun_dir, moon.satellite.reach());
+                clouds.update(&queue, t as f32);
+                atmosphere.update(&queue, &camera, sun_dir);
+                post_process.update(&queue);
+                if text_overlay.visible {
+                    let mut text = format!(
+                        "FPS {:.1} ({:.2} MS)\nTRIANGLES {}\nSEED {}",
+                        frame_stats.fps,
+                        frame_stats.frame_time,
+                        planet.triangle_count(),
+                        app_config.seed
+                    );
+                    if let Some(distance) = measurement.distance() {
+                        text += &format!("\nDISTANCE {distance:.4}");
+                    }
+                    text_overlay.update(&queue, &config, &text);
+                }
 
-                match render(&surface, &device, &queue, &camera, &background, &planet) {
+                let screenshot = std::mem::take(&mut screenshot_requested);
+                match render(
+                    &surface,
+                    &device,
+                    &queue,
+                    &targets,
+                    &shadow_map,
+                    &background,
+                    &planet,
+                    &moon,
+                    &clouds,
+                    &atmosphere,
+                    &post_process,
+                    &depth_debug,
+                    &text_overlay,
+                    app_config.clear_color(post_process::HDR_FORMAT),
+                    gpu_timer.as_mut(),
+                    screenshot,
+                ) {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         surface_configured = setup::configure_surface(
@@ -67,19 +335,91 @@ pub fn main() -> anyhow::Result<()> {
                             &mut config,
                             window.inner_size(),
                         );
-                        return;
+                        resize(
+                            &device,
+                            &config,
+                            &mut camera,
+                            &mut targets,
+                            &mut post_process,
+                            &mut depth_debug,
+                        );
                     }
                     Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
                         log::error!("OutOfMemory");
                         control_flow.exit();
-                        return;
                     }
                     Err(wgpu::SurfaceError::Timeout) => {
                         log::warn!("Surface timeout");
-                        return;
                     }
                 };
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                cursor = vec2(position.x as f32, position.y as f32);
+                let size = vec2(config.width as f32, config.height as f32);
+                camera_controller.process_cursor(cursor, size);
+            }
+            WindowEvent::ModifiersChanged(new_modifiers) => modifiers = new_modifiers.state(),
+            WindowEvent::MouseWheel { delta, .. } if modifiers.control_key() => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                // scrolling up narrows the view and zooms in
+                if let Some(fov_y) = camera.fov_y() {
+                    camera.set_fov_y(fov_y - lines * FOV_STEP);
+                    let fov_y = camera.fov_y().unwrap_or(fov_y);
+                    log::info!("Field of view {:.0}°", fov_y.to_degrees());
+                }
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => {
+                if *state == ElementState::Pressed && measurement.endpoints().is_some() {
+                    measurement.clear();
+                    planet.set_measurement(&device, &queue, &[]);
+                }
+                // dragging takes over from a flight
+                camera.cancel_flight();
+                camera_controller.process_drag(*state)
+            }
+            // Shift+click picks the points to measure between instead of flying
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if modifiers.shift_key() => {
+                let size = vec2(config.width as f32, config.height as f32);
+                let (origin, dir) = camera.screen_ray(cursor, size);
+                if let Some(hit) = planet::pick_point(origin, dir.as_dvec3()) {
+                    measurement.add(hit);
+                    planet.set_measurement(&device, &queue, &measurement.arc());
+                    if let Some(distance) = measurement.distance() {
+                        log::info!("Great circle distance {distance:.4}");
+                    }
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let size = vec2(config.width as f32, config.height as f32);
+                let (origin, dir) = camera.screen_ray(cursor, size);
+                if let Some(region) = planet::pick(&planet.data, origin, dir.as_dvec3()) {
+                    let classification = planet.data.plate_of(region).map(|p| p.classification);
+                    log::info!("Picked region {region} ({classification:?})");
+                    // the flight holds the camera in place, the orbit would move it away
+                    camera.focus_on(
+                        planet.data.regions[region].centroid(),
+                        app_config.orbit_distance * RADIUS,
+                        clock.interpolated_time(),
+                        FLIGHT_DURATION,
+                    );
+                    camera_controller.mode = camera::CameraMode::Fixed;
+                }
+            }
             WindowEvent::CloseRequested
             | WindowEvent::KeyboardInput {
                 event:
@@ -90,28 +430,578 @@ pub fn main() -> anyhow::Result<()> {
                     },
                 ..
             } => control_flow.exit(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::Tab),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                camera.cancel_flight();
+                camera_controller.toggle_mode(&mut camera)
+            }
+            // in fly mode W moves the camera forward
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyW),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } if camera_controller.mode == camera::CameraMode::Orbit => {
+                planet.toggle_render_mode()
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyP),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => camera.toggle_projection(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyM),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.animate_plates = !planet.animate_plates,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyN),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.toggle_shading(&device, &queue),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyU),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.show_texture = !planet.show_texture,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyY),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.toggle_coloring(&device, &queue),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyZ),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => depth_debug.visible = !depth_debug.visible,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key:
+                            PhysicalKey::Code(key @ (KeyCode::PageDown | KeyCode::PageUp)),
+                        ..
+                    },
+                ..
+            } => {
+                post_process.exposure *= match key {
+                    KeyCode::PageUp => EXPOSURE_STEP,
+                    _ => 1. / EXPOSURE_STEP,
+                };
+                log::info!("Exposure {:.2}", post_process.exposure);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::End | KeyCode::Home)),
+                        ..
+                    },
+                ..
+            } => {
+                elevation_params.sea_level += match key {
+                    KeyCode::Home => SEA_LEVEL_STEP,
+                    _ => -SEA_LEVEL_STEP,
+                };
+                planet.set_sea_level(&device, &queue, elevation_params.sea_level);
+                let stats = planet::Stats::compute(
+                    &planet.data.regions,
+                    &planet.data.plates,
+                    &planet.data.ocean,
+                );
+                log::info!(
+                    "Sea level {:.4}, {:.1}% ocean",
+                    elevation_params.sea_level / RADIUS,
+                    100. * stats.ocean_fraction
+                );
+            }
+            // in fly mode Space moves the camera up
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::Space),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } if camera_controller.mode != camera::CameraMode::Fly => {
+                sim_state.paused = !sim_state.paused;
+                log::info!("Simulation {}", if sim_state.paused { "paused" } else { "resumed" });
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::Period),
+                        ..
+                    },
+                ..
+            } => {
+                sim_state.paused = true;
+                sim_state.step_once = true;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key:
+                            PhysicalKey::Code(
+                                key @ (KeyCode::BracketLeft | KeyCode::BracketRight),
+                            ),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                sim_state.speed = match key {
+                    KeyCode::BracketRight => 2. * sim_state.speed,
+                    _ => 0.5 * sim_state.speed,
+                }
+                .clamp(MIN_SPEED, MAX_SPEED);
+                log::info!("Simulation speed {}x", sim_state.speed);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyC),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.frustum_culling = !planet.frustum_culling,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyH),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.hemisphere_culling = !planet.hemisphere_culling,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyL),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.wireframe_overlay = !planet.wireframe_overlay,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyB),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.show_boundaries = !planet.show_boundaries,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.show_graticule = !planet.show_graticule,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyV),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => planet.show_motion_arrows = !planet.show_motion_arrows,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F12),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => screenshot_requested = true,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyI),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => match planet.toggle_depth_prepass(&device) {
+                Ok(on) => log::info!("Depth prepass {}", if on { "on" } else { "off" }),
+                Err(err) => log::error!("Failed to toggle the depth prepass: {err}"),
+            },
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key:
+                            PhysicalKey::Code(
+                                key @ (KeyCode::Equal
+                                | KeyCode::NumpadAdd
+                                | KeyCode::Minus
+                                | KeyCode::NumpadSubtract),
+                            ),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                subdivisions = match key {
+                    KeyCode::Equal | KeyCode::NumpadAdd => {
+                        (subdivisions + 1).min(planet::MAX_SUBDIVISIONS)
+                    }
+                    // the plates need a region each
+                    _ => subdivisions
+                        .saturating_sub(1)
+                        .max(planet::min_subdivisions(generation_params.num_plates)),
+                };
+                subdivisions_changed = Some(std::time::Instant::now());
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::KeyQ | KeyCode::KeyE)),
+                        ..
+                    },
+                ..
+            } => camera.roll_by(match key {
+                KeyCode::KeyE => ROLL_STEP,
+                _ => -ROLL_STEP,
+            }),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key:
+                            PhysicalKey::Code(
+                                key @ (KeyCode::ArrowLeft
+                                | KeyCode::ArrowRight
+                                | KeyCode::ArrowUp
+                                | KeyCode::ArrowDown),
+                            ),
+                        ..
+                    },
+                ..
+            } if camera_controller.mode != camera::CameraMode::Fly => match key {
+                KeyCode::ArrowLeft => sun.turn(-SUN_STEP, 0.),
+                KeyCode::ArrowRight => sun.turn(SUN_STEP, 0.),
+                KeyCode::ArrowUp => sun.turn(0., SUN_STEP),
+                _ => sun.turn(0., -SUN_STEP),
+            },
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => camera.level(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F5),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => match camera.save(std::path::Path::new(CAMERA_PATH)) {
+                Ok(()) => log::info!("Saved camera to {CAMERA_PATH}"),
+                Err(err) => log::error!("Failed to save camera: {err}"),
+            },
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F9),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => match camera::Camera::load(std::path::Path::new(CAMERA_PATH), &config) {
+                Ok(loaded) => {
+                    camera = loaded;
+                    camera_controller.mode = camera::CameraMode::Fixed;
+                    log::info!("Loaded camera from {CAMERA_PATH}");
+                }
+                Err(err) => log::error!("Failed to load camera: {err}"),
+            },
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F3),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => text_overlay.visible = !text_overlay.visible,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key:
+                            PhysicalKey::Code(key @ (KeyCode::KeyO | KeyCode::KeyG | KeyCode::KeyK)),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let (path, result) = match key {
+                    KeyCode::KeyO => (
+                        "planet.obj",
+                        planet::export_obj(
+                            "planet.obj".as_ref(),
+                            app_config.seed,
+                            &generation_params,
+                        ),
+                    ),
+                    KeyCode::KeyG => (
+                        "planet.glb",
+                        planet::export_gltf(
+                            "planet.glb".as_ref(),
+                            app_config.seed,
+                            &generation_params,
+                        ),
+                    ),
+                    _ => (
+                        "heightmap.png",
+                        planet::export_heightmap(
+                            "heightmap.png".as_ref(),
+                            &planet.data,
+                            HEIGHTMAP_WIDTH,
+                            HEIGHTMAP_WIDTH / 2,
+                        ),
+                    ),
+                };
+                match result {
+                    Ok(()) => log::info!("Exported planet to {path}"),
+                    Err(err) => log::error!("Failed to export planet to {path}: {err}"),
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state,
+                        physical_key: PhysicalKey::Code(key),
+                        ..
+                    },
+                ..
+            } => {
+                camera_controller.process_keyboard(*key, *state);
+            }
             _ => {}
         },
+        // every exit of the loop, Escape and closing the window included
+        Event::LoopExiting => shutdown(&device, gpu_timer.as_mut()),
         _ => {}
     })?;
 
     Ok(())
 }
 
-fn update(t: f64, camera: &mut camera::Camera) {
-    let (x, y) = (0.1 * t).sin_cos();
-    camera.position.x = 4. * RADIUS * x;
-    camera.position.y = 4. * RADIUS * y;
+#[allow(clippy::too_many_arguments)]
+fn reload_shaders(
+    reloader: &hot_reload::ShaderReloader,
+    device: &wgpu::Device,
+    background: &mut background::Background,
+    planet: &mut planet::Planet,
+    moon: &mut moon::Moon,
+    clouds: &mut clouds::Clouds,
+    atmosphere: &mut atmosphere::Atmosphere,
+    post_process: &mut post_process::PostProcess,
+    depth_debug: &mut depth_debug::DepthDebug,
+    text_overlay: &mut text_overlay::TextOverlay,
+) {
+    for path in reloader.changed() {
+        let result = if path.ends_with(background::SHADER) {
+            background.reload_shader(device)
+        } else if path.ends_with(planet::SHADER) {
+            planet.reload_shader(device)
+        } else if path.ends_with(planet::TECTONICS_SHADER) {
+            planet.reload_tectonics_shader(device)
+        } else if path.ends_with(moon::SHADER) {
+            moon.reload_shader(device)
+        } else if path.ends_with(clouds::SHADER) {
+            clouds.reload_shader(device)
+        } else if path.ends_with(atmosphere::SHADER) {
+            atmosphere.reload_shader(device)
+        } else if path.ends_with(post_process::SHADER) {
+            post_process.reload_shader(device)
+        } else if path.ends_with(post_process::BLOOM_SHADER) {
+            post_process.reload_bloom_shader(device)
+        } else if path.ends_with(depth_debug::SHADER) {
+            depth_debug.reload_shader(device)
+        } else if path.ends_with(text_overlay::SHADER) {
+            text_overlay.reload_shader(device)
+        } else {
+            continue;
+        };
+        match result {
+            Ok(()) => log::info!("Reloaded {}", path.display()),
+            Err(err) => log::error!("Failed to reload {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Title of the window with the seed and subdivisions of the planet, and the frame rate once
+/// it was measured
+fn window_title(seed: u64, subdivisions: usize, fps: Option<f64>) -> String {
+    let title = format!("Planet Placer - seed {seed}, {subdivisions} subdivisions");
+    match fps {
+        Some(fps) => format!("{title}, {fps:.0} FPS"),
+        None => title,
+    }
+}
+
+/// Cancels the readbacks still in flight and waits for the submitted work to finish, so
+/// nothing is dropped while the GPU still uses it
+fn shutdown(device: &wgpu::Device, gpu_timer: Option<&mut gpu_timer::GpuTimer>) {
+    if let Some(gpu_timer) = gpu_timer {
+        gpu_timer.cancel();
+    }
+    device.poll(wgpu::Maintain::Wait);
+    log::info!("Exited cleanly");
+}
+
+/// Moves the camera along its orbit, looking at the center of the planet
+fn update(t: f64, camera: &mut camera::Camera, orbit: &camera::OrbitParams) {
+    camera.position = orbit.position(t);
     camera.look_dir = -camera.position.normalize().as_vec3()
 }
 
+/// Direction towards the sun at the start as azimuth around the z axis and elevation above the
+/// equator, moved with the arrow keys. The sun animation turns it further around the z axis.
+struct Sun {
+    azimuth: f32,
+    elevation: f32,
+}
+
+impl Sun {
+    fn new(direction: Vec3) -> Self {
+        let direction = direction.normalize();
+        Sun {
+            azimuth: direction.y.atan2(direction.x),
+            elevation: direction
+                .z
+                .asin()
+                .clamp(-MAX_SUN_ELEVATION, MAX_SUN_ELEVATION),
+        }
+    }
+
+    /// Moves the sun by the angles in radians, it doesn't go past `MAX_SUN_ELEVATION`
+    fn turn(&mut self, azimuth: f32, elevation: f32) {
+        self.azimuth = (self.azimuth + azimuth).rem_euclid(std::f32::consts::TAU);
+        self.elevation = (self.elevation + elevation).clamp(-MAX_SUN_ELEVATION, MAX_SUN_ELEVATION);
+    }
+
+    /// Direction towards the sun after `t` seconds
+    fn direction(&self, t: f64, app_config: &config::AppConfig) -> Vec3 {
+        let azimuth = self.azimuth + (app_config.sun_speed * t) as f32;
+        let (sin_elevation, cos_elevation) = self.elevation.sin_cos();
+        let (sin_azimuth, cos_azimuth) = azimuth.sin_cos();
+        vec3(
+            cos_elevation * cos_azimuth,
+            cos_elevation * sin_azimuth,
+            sin_elevation,
+        )
+    }
+}
+
+/// Follows a new surface size. A minimized window keeps the previous size in `config`, so the
+/// targets stay as they are until it is restored.
+fn resize(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    camera: &mut camera::Camera,
+    targets: &mut targets::Targets,
+    post_process: &mut post_process::PostProcess,
+    depth_debug: &mut depth_debug::DepthDebug,
+) {
+    camera.resize(config);
+    if targets.resize(device, config) {
+        post_process.resize(device, config, targets);
+        depth_debug.resize(device, targets);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn render(
     surface: &wgpu::Surface,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-    camera: &camera::Camera,
+    targets: &targets::Targets,
+    shadow_map: &shadow_map::ShadowMap,
     background: &background::Background,
     planet: &planet::Planet,
+    moon: &moon::Moon,
+    clouds: &clouds::Clouds,
+    atmosphere: &atmosphere::Atmosphere,
+    post_process: &post_process::PostProcess,
+    depth_debug: &depth_debug::DepthDebug,
+    text_overlay: &text_overlay::TextOverlay,
+    clear_color: wgpu::Color,
+    mut gpu_timer: Option<&mut gpu_timer::GpuTimer>,
+    screenshot: bool,
 ) -> Result<(), wgpu::SurfaceError> {
     let output = surface.get_current_texture()?;
 
@@ -122,19 +1012,28 @@ fn render(
         label: Some("Render Encoder"),
     });
 
-    {
+    draw(
+        &mut encoder,
+        &view,
+        targets,
+        shadow_map,
+        background,
+        planet,
+        moon,
+        clouds,
+        atmosphere,
+        post_process,
+        clear_color,
+        gpu_timer.as_deref(),
+    );
+    if depth_debug.visible {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
+            label: Some("Depth Debug Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.01,
-                        g: 0.01,
-                        b: 0.01,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Load,
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -143,29 +1042,62 @@ fn render(
             timestamp_writes: None,
         });
 
-        background::render(&mut render_pass, background);
+        depth_debug::render(&mut render_pass, depth_debug);
     }
+    if text_overlay.visible {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Text Overlay Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
 
-    // {
-    //     let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-    //         label: Some("Render Pass"),
-    //         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-    //             view: &view,
-    //             resolve_target: None,
-    //             ops: wgpu::Operations {
-    //                 load: wgpu::LoadOp::Load,
-    //                 store: wgpu::StoreOp::Store,
-    //             },
-    //         })],
-    //         depth_stencil_attachment: Some(camera.depth_stencil_attachment()),
-    //         occlusion_query_set: None,
-    //         timestamp_writes: None,
-    //     });
+        text_overlay::render(&mut render_pass, text_overlay);
+    }
+    if let Some(gpu_timer) = &mut gpu_timer {
+        gpu_timer.resolve(&mut encoder);
+    }
 
-    //     planet::render(&mut render_pass, planet);
-    // }
+    let readback = match screenshot {
+        true if output
+            .texture
+            .usage()
+            .contains(wgpu::TextureUsages::COPY_SRC) =>
+        {
+            Some(screenshot::copy_texture(
+                device,
+                &mut encoder,
+                &output.texture,
+            ))
+        }
+        true => {
+            log::warn!("Surface doesn't support copying, can't take a screenshot");
+            None
+        }
+        false => None,
+    };
 
     queue.submit(std::iter::once(encoder.finish()));
+    if let Some(gpu_timer) = gpu_timer {
+        gpu_timer.read(device);
+    }
+
+    if let Some(readback) = readback {
+        let path = screenshot::path();
+        match readback.save_png(device, &path) {
+            Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+            Err(err) => log::error!("Failed to save screenshot: {err}"),
+        }
+    }
+
     output.present();
 
     Ok(())
@@ -0,0 +1,56 @@
+use crate::{planet, utils::*};
+
+/// Longest segment of a drawn arc in radians, short enough to follow the curve of the planet
+const ARC_STEP: f64 = 0.01;
+
+/// Unit directions along the shorter great circle from `a` to `b`, including both ends
+pub fn arc(a: DVec3, b: DVec3) -> Vec<DVec3> {
+    let (a, b) = (a.normalize(), b.normalize());
+    let angle = a.angle_between(b);
+    let segments = ((angle / ARC_STEP).ceil() as usize).max(1);
+    // the points coincide or are opposite, any great circle through them is the shortest
+    let axis = a
+        .cross(b)
+        .try_normalize()
+        .unwrap_or_else(|| a.any_orthonormal_vector());
+    (0..=segments)
+        .map(|i| DQuat::from_axis_angle(axis, angle * i as f64 / segments as f64) * a)
+        .collect()
+}
+
+/// Two points picked on the surface and the great circle between them
+#[derive(Default)]
+pub struct Measurement {
+    /// directions from the center of the planet, at most two
+    points: Vec<DVec3>,
+}
+
+impl Measurement {
+    /// Adds a picked point, a third one starts a new measurement
+    pub fn add(&mut self, point: DVec3) {
+        if self.points.len() == 2 {
+            self.points.clear();
+        }
+        self.points.push(point.normalize());
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn endpoints(&self) -> Option<[DVec3; 2]> {
+        match self.points[..] {
+            [a, b] => Some([a, b]),
+            _ => None,
+        }
+    }
+
+    pub fn distance(&self) -> Option<f64> {
+        self.endpoints().map(|[a, b]| planet::arc_distance(a, b))
+    }
+
+    /// Directions along the arc between the points, empty until both are picked
+    pub fn arc(&self) -> Vec<DVec3> {
+        self.endpoints().map_or_else(Vec::new, |[a, b]| arc(a, b))
+    }
+}
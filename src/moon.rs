@@ -0,0 +1,323 @@
+use crate::planet::icosphere;
+use crate::shadow_map::{self, ShadowMap};
+use crate::{setup, utils::*, RADIUS};
+use anyhow::Result;
+use camera::CameraUniform;
+
+pub const SHADER: &str = "shaders/moon.wgsl";
+
+/// Subdivisions of the moon, it is small on screen so it needs fewer triangles than the planet
+const SUBDIVISIONS: usize = 3;
+
+/// Linear color of the moon's surface, it is only drawn into the HDR target
+const COLOR: Vec3 = Vec3::splat(0.25);
+
+/// A body on a circular orbit around the center of the planet, in the plane of the equator
+#[derive(Clone, Copy, Debug)]
+pub struct Satellite {
+    /// distance of the center from the center of the planet
+    pub orbit_radius: f64,
+    /// seconds per orbit
+    pub orbit_period: f64,
+    pub radius: f64,
+}
+
+impl Default for Satellite {
+    fn default() -> Self {
+        Satellite {
+            orbit_radius: 3. * RADIUS,
+            orbit_period: 120.,
+            radius: 0.27 * RADIUS,
+        }
+    }
+}
+
+impl Satellite {
+    /// Distance from the center of the planet the satellite reaches
+    pub fn reach(&self) -> f64 {
+        self.orbit_radius + self.radius
+    }
+
+    /// Center of the satellite after `time` seconds, it starts on the x axis
+    pub fn position(&self, time: f64) -> DVec3 {
+        let angle = std::f64::consts::TAU * time / self.orbit_period;
+        let (y, x) = angle.sin_cos();
+        DVec3::new(x, y, 0.) * self.orbit_radius
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MoonUniform {
+    /// packed like the vertex positions of the planet, so the shader can subtract the camera
+    /// position from it exactly
+    center: PackedVec3,
+    color: Vec3,
+    radius: f32,
+}
+
+fn vertex_desc() -> wgpu::VertexBufferLayout<'static> {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vec3>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBS,
+    }
+}
+
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Moon Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[vertex_desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        // the same log depth as the planet, so either hides the other
+        depth_stencil: Some(camera::depth_stencil_state()),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Pipeline drawing the depth of the moon into the shadow map
+fn shadow_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Moon Shadow Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_shadow"),
+            buffers: &[vertex_desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(shadow_map::depth_stencil_state()),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Gray sphere orbiting the planet, it casts a shadow on the planet and receives one
+pub struct Moon {
+    pub satellite: Satellite,
+    vertex_buffer: Buffer<Vec3>,
+    index_buffer: Buffer<u32>,
+    uniform_buffer: Buffer<MoonUniform>,
+    camera_bind_group: wgpu::BindGroup,
+    bind_group: wgpu::BindGroup,
+    /// the shadow map for the render pipeline and its light matrix for the shadow pipeline
+    shadow_bind_group: wgpu::BindGroup,
+    light_bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    shadow_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    render_pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
+}
+
+impl Moon {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        camera_uniform: &Buffer<CameraUniform>,
+        shadow_map: &ShadowMap,
+        satellite: Satellite,
+    ) -> Result<Self> {
+        // unit directions, the shader scales them by the radius
+        let (vertices, indices) = icosphere::subdivided(SUBDIVISIONS);
+        let vertices = vertices
+            .into_iter()
+            .map(|vertex| vertex.as_vec3())
+            .collect::<Vec<_>>();
+        let vertex_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("moon_vertex_buffer"),
+            contents: &vertices,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("moon_index_buffer"),
+            contents: &indices,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let uniform_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("moon_uniform_buffer"),
+            len: 1,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = setup::shader(device, SHADER)?;
+
+        let camera_bind_group_layout = camera::bind_group_layout(device);
+        let camera_bind_group =
+            camera::bind_group(device, &camera_bind_group_layout, camera_uniform);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("moon_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("moon_bind_group"),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Moon Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &bind_group_layout,
+                    shadow_map.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+        let shadow_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Moon Shadow Pipeline Layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &bind_group_layout,
+                    shadow_map.light_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let format = config.format;
+        let render_pipeline = render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+        );
+        let shadow_pipeline = shadow_pipeline(device, &shadow_pipeline_layout, &shader);
+
+        Ok(Moon {
+            satellite,
+            vertex_buffer,
+            index_buffer,
+            uniform_buffer,
+            camera_bind_group,
+            bind_group,
+            shadow_bind_group: shadow_map.bind_group().clone(),
+            light_bind_group: shadow_map.light_bind_group().clone(),
+            render_pipeline_layout,
+            shadow_pipeline_layout,
+            format,
+            sample_count,
+            render_pipeline,
+            shadow_pipeline,
+        })
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        let (render, shadow) = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok((
+                render_pipeline(
+                    device,
+                    &self.render_pipeline_layout,
+                    &shader,
+                    self.format,
+                    self.sample_count,
+                ),
+                shadow_pipeline(device, &self.shadow_pipeline_layout, &shader),
+            ))
+        })?;
+        self.render_pipeline = render;
+        self.shadow_pipeline = shadow;
+        Ok(())
+    }
+
+    /// Moves the moon to where it is on its orbit after `time` seconds
+    pub fn update(&self, queue: &wgpu::Queue, time: f64) {
+        let uniform = MoonUniform {
+            center: self.satellite.position(time).into(),
+            color: COLOR,
+            radius: self.satellite.radius as f32,
+        };
+        queue.write_typed_buffer(&self.uniform_buffer, 0, &[uniform]);
+    }
+}
+
+/// Draws the moon, in the same pass as the planet so they are depth tested against each other
+pub fn render(render_pass: &mut wgpu::RenderPass, moon: &Moon) {
+    render_pass.set_pipeline(&moon.render_pipeline);
+    render_pass.set_bind_group(0, &moon.camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &moon.bind_group, &[]);
+    render_pass.set_bind_group(2, &moon.shadow_bind_group, &[]);
+    render_pass.set_typed_vertex_buffer(0, &moon.vertex_buffer);
+    render_pass.set_typed_index_buffer(&moon.index_buffer);
+    render_pass.draw_indexed(0..moon.index_buffer.len as _, 0, 0..1);
+}
+
+/// Draws the depth of the moon seen from the sun, in the pass into the shadow map
+pub fn render_shadow(render_pass: &mut wgpu::RenderPass, moon: &Moon) {
+    render_pass.set_pipeline(&moon.shadow_pipeline);
+    render_pass.set_bind_group(0, &moon.camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &moon.bind_group, &[]);
+    render_pass.set_bind_group(2, &moon.light_bind_group, &[]);
+    render_pass.set_typed_vertex_buffer(0, &moon.vertex_buffer);
+    render_pass.set_typed_index_buffer(&moon.index_buffer);
+    render_pass.draw_indexed(0..moon.index_buffer.len as _, 0, 0..1);
+}
@@ -1,28 +1,80 @@
-use std::collections::HashSet;
-
-use crate::setup;
+use crate::shadow_map::{self, ShadowMap};
 use crate::utils::*;
-use anyhow::Result;
-use rand::{seq::SliceRandom, Rng, SeedableRng};
+use crate::{setup, RADIUS};
+use anyhow::{Context, Result};
+use camera::Camera;
+use rand::SeedableRng;
 use rand_pcg::Pcg32;
-use tectonic_plates::TectonicPlateClassification;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tectonic_plates::{BoundaryType, PlateBoundary, TectonicPlate, TectonicPlateClassification};
+use wgpu::util::DeviceExt;
 
-use crate::RADIUS;
+mod climate;
+pub use climate::ClimateParams;
+mod elevation;
+pub use elevation::ElevationParams;
+mod erosion;
+pub use erosion::{erode, ErosionParams};
+mod export;
+pub use export::{export_gltf, export_heightmap, export_obj};
+mod noise;
+pub use noise::{base_elevation, NoiseParams};
 mod regions;
-use regions::Region;
+pub use regions::{arc_distance, create_regions, great_circle_distance, icosphere};
+use regions::{Adjacency, Region, RegionIndex};
+mod rivers;
+pub use rivers::trace_rivers;
+mod save;
+mod stats;
+pub use stats::Stats;
 mod tectonic_plates;
+pub use tectonic_plates::{cluster_regions, cluster_regions_voronoi, compute_seafloor_age};
+mod tectonic_sim;
+pub use tectonic_sim::{TectonicSim, SHADER as TECTONICS_SHADER};
+
+pub const SHADER: &str = "shaders/planet.wgsl";
+
+/// Most subdivisions a planet is generated with, its mesh has 1.3 million triangles. The 32 bit
+/// indices of the mesh would address the vertices of up to 13 subdivisions.
+pub const MAX_SUBDIVISIONS: usize = 8;
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     position: PackedVec3,
-    color: Vec3,
-    _padding: f32,
+    /// sRGB like all colors on the CPU, packed by `pack_color`. The shader converts it to
+    /// linear for linear targets. Colors beyond 8 bits per channel go through `region_colors`.
+    color: u32,
+    /// index of the region in `region_colors`
+    region: u32,
+    normal: Vec3,
+    /// a `Material`, selects the shading in the fragment shader
+    material: u32,
+    /// equirectangular texture coordinates, see `regions::assign_uv`
+    uv: Vec2,
+}
+
+/// RGBA8 with red in the lowest byte, as `unpack4x8unorm` in WGSL reads it. The channels are
+/// clamped to 0..1, alpha is opaque.
+fn pack_color(color: Vec3) -> u32 {
+    let [r, g, b] = color
+        .clamp(Vec3::ZERO, Vec3::ONE)
+        .to_array()
+        .map(|c| (c * 255.).round() as u32);
+    r | g << 8 | b << 16 | 255 << 24
 }
 
 impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Uint32x4, 1 => Float32x4];
+    const ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        0 => Uint32x4, 1 => Uint32, 2 => Uint32, 3 => Float32x3, 4 => Uint32, 5 => Float32x2
+    ];
+
+    /// The packed color, unpacked again
+    pub fn color(&self) -> Vec3 {
+        let [r, g, b, _] = self.color.to_le_bytes();
+        vec3(r as f32, g as f32, b as f32) / 255.
+    }
 
     fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
@@ -35,158 +87,2319 @@ impl Vertex {
     }
 }
 
+/// Placement of a copy of the planet in the scene, e.g. one of several planets of a system
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    /// position of the center of the copy
+    pub translation: DVec3,
+    /// size of the copy relative to the planet
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: DVec3::ZERO,
+            scale: 1.,
+        }
+    }
+}
+
+/// A `Transform` as the vertex shader reads it for every instance. The translation is packed
+/// like the vertex positions, so the shader can subtract the camera position from it exactly.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Instance {
+    position: PackedVec3,
+    scale: f32,
+    _padding: [f32; 3],
+}
+
+impl From<Transform> for Instance {
+    fn from(transform: Transform) -> Self {
+        Instance {
+            position: transform.translation.into(),
+            scale: transform.scale as f32,
+            _padding: [0.; 3],
+        }
+    }
+}
+
+impl Instance {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![6 => Uint32x4, 7 => Float32];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
 impl Vertex {
+    /// Creates the vertices of a region, each corner offset outwards by the elevation
     #[rustfmt::skip]
-    fn from_region(region: &Region, classification: TectonicPlateClassification) -> [Self; 3] {
-        let color = match classification {
-            TectonicPlateClassification::Continental => vec3(0., 1., 0.),
-            TectonicPlateClassification::Oceanic => vec3(0., 0., 1.),
-        };
+    fn from_region(
+        region: &Region,
+        region_index: usize,
+        color: Vec3,
+        elevation: f64,
+        material: Material,
+    ) -> [Self; 3] {
+        let [a, b, c] = region.corners.map(|corner| corner + corner.normalize() * elevation);
+        let normal = (b - a).cross(c - a).normalize().as_vec3();
+        let [uv_a, uv_b, uv_c] = regions::assign_uv(region);
+        let color = pack_color(color);
+        let region = region_index as u32;
+        let material = material as u32;
         [
-            Vertex { position: region.corners[0].into(), color, _padding: 0. },
-            Vertex { position: region.corners[1].into(), color, _padding: 0. },
-            Vertex { position: region.corners[2].into(), color, _padding: 0. },
+            Vertex { position: a.into(), color, region, normal, material, uv: uv_a },
+            Vertex { position: b.into(), color, region, normal, material, uv: uv_b },
+            Vertex { position: c.into(), color, region, normal, material, uv: uv_c },
         ]
     }
 }
 
-pub fn build_planet() -> (Vec<Vertex>, Vec<u16>) {
-    let mut rng = Pcg32::seed_from_u64(1);
-    let regions = regions::create_regions(5);
-    let tectonic_plates = tectonic_plates::cluster_regions(&mut rng, &regions, 40);
+/// How the fragment shader lights a surface, the values match the constants in the shader
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum Material {
+    /// diffuse only
+    Land = 0,
+    /// with a specular highlight of the sun
+    Water = 1,
+}
+
+impl From<TectonicPlateClassification> for Material {
+    fn from(classification: TectonicPlateClassification) -> Self {
+        match classification {
+            TectonicPlateClassification::Continental => Material::Land,
+            TectonicPlateClassification::Oceanic => Material::Water,
+        }
+    }
+}
+
+/// Which normals the mesh is lit with
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Shading {
+    /// every region is lit by the normal of its own triangle
+    #[default]
+    Flat,
+    /// the normals are averaged over the triangles sharing a vertex, hiding the facets
+    Smooth,
+}
+
+/// How the planet surface is drawn
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RenderMode {
+    #[default]
+    Fill,
+    /// the edges of the triangles, only available with POLYGON_MODE_LINE
+    Wireframe,
+    /// a small square at the centroid of every region, shows how the regions are spread
+    Points,
+}
+
+/// What the region colors show
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Coloring {
+    /// elevation bands and climate of the plate classification
+    #[default]
+    Classification,
+    /// a distinct hue for every plate
+    Plate,
+    /// elevation alone, from the deepest rifts to the highest peaks
+    Elevation,
+    /// age of the oceanic crust, see `compute_seafloor_age`
+    SeafloorAge,
+}
+
+/// Color of the plate `plate_id` out of `num_plates`, their hues are evenly spaced around the
+/// color wheel so neighboring plates are told apart
+fn plate_color(plate_id: usize, num_plates: usize) -> Vec3 {
+    let hue = plate_id as f32 / num_plates.max(1) as f32;
+    hsv_to_rgb(hue, PLATE_SATURATION, PLATE_VALUE)
+}
+
+/// `hue`, `saturation` and `value` from 0 to 1
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> Vec3 {
+    let sector = hue.fract() * 6.;
+    let rgb = vec3(
+        ((sector - 3.).abs() - 1.).clamp(0., 1.),
+        (2. - (sector - 2.).abs()).clamp(0., 1.),
+        (2. - (sector - 4.).abs()).clamp(0., 1.),
+    );
+    value * Vec3::ONE.lerp(rgb, saturation)
+}
+
+/// How the regions are divided into tectonic plates
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PlateAssignment {
+    /// random flood fill from seed regions, gives irregular plates
+    #[default]
+    FloodFill,
+    /// nearest seed region, gives compact plates
+    Voronoi,
+}
+
+/// Subdivisions from which `PlanetData::generate_on_gpu` subdivides the icosphere on the GPU,
+/// below that the CPU subdivision takes a fraction of the generation
+pub const GPU_SUBDIVISIONS: usize = 7;
+
+/// Number of regions of the icosphere subdivided `subdivisions` times
+pub fn region_count(subdivisions: usize) -> usize {
+    20 * 4usize.pow(subdivisions as u32)
+}
+
+/// Fewest subdivisions with at least one region for each of `num_plates` plates
+pub fn min_subdivisions(num_plates: usize) -> usize {
+    (0..MAX_SUBDIVISIONS)
+        .find(|&subdivisions| region_count(subdivisions) >= num_plates)
+        .unwrap_or(MAX_SUBDIVISIONS)
+}
+
+#[derive(Clone, Copy)]
+pub struct GenerationParams {
+    /// number of times the icosahedron is subdivided into regions
+    pub subdivisions: usize,
+    pub num_plates: usize,
+    /// chance of a plate being continental, 0.3 gives about 30% continental plates
+    pub continental_fraction: f32,
+    pub plate_assignment: PlateAssignment,
+    /// Lloyd relaxation steps evening out the plate sizes, only used by Voronoi assignment
+    pub relax_iterations: usize,
+    pub climate: ClimateParams,
+    /// rivers traced from the highest land regions, capped for performance
+    pub max_rivers: usize,
+    /// raindrops eroding the land, 0 keeps the tectonic elevation as it is
+    pub erosion_iterations: usize,
+    pub erosion: ErosionParams,
+    /// relief added to the tectonic elevation before the erosion
+    pub noise: NoiseParams,
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        GenerationParams {
+            subdivisions: 5,
+            num_plates: 40,
+            continental_fraction: 0.4,
+            plate_assignment: PlateAssignment::default(),
+            relax_iterations: 0,
+            climate: ClimateParams::default(),
+            max_rivers: 64,
+            erosion_iterations: 0,
+            erosion: ErosionParams::default(),
+            noise: NoiseParams::default(),
+        }
+    }
+}
+
+const RIVER_COLOR: Vec3 = vec3(0.15, 0.35, 0.85);
+const YOUNG_SEAFLOOR_COLOR: Vec3 = vec3(1.0, 0.25, 0.1);
+const OLD_SEAFLOOR_COLOR: Vec3 = vec3(0.05, 0.1, 0.45);
+/// seafloor age in region hops from which the crust is colored as old
+const OLD_SEAFLOOR_AGE: u32 = 32;
+/// continents and oceans without a spreading ridge in the seafloor age coloring
+const NO_AGE_COLOR: Vec3 = vec3(0.4, 0.4, 0.4);
+const PLATE_SATURATION: f32 = 0.7;
+const PLATE_VALUE: f32 = 0.9;
+const PLATE_EDGE_COLOR: Vec3 = vec3(1.0, 1.0, 1.0);
+const REGION_EDGE_COLOR: Vec3 = vec3(0.02, 0.02, 0.02);
+/// width of the plate boundary lines on the surface
+const BOUNDARY_LINE_WIDTH: f64 = 0.006 * RADIUS;
+const MEASURE_COLOR: Vec3 = vec3(1.0, 0.85, 0.1);
+/// height of the measured arc above the surface, so the mountains along it don't hide it
+const MEASURE_LIFT: f64 = 0.003 * RADIUS;
+/// height of the latitude and longitude lines above sea level, as high as the highest
+/// mountains of the default elevation so the terrain doesn't hide them
+const GRATICULE_HEIGHT: f64 = 0.02 * RADIUS;
+/// longest segment of a latitude or longitude line in radians
+const GRATICULE_SEGMENT: f64 = 0.02;
+/// spacing of the latitude and longitude lines drawn by the planet, 15 degrees
+const GRATICULE_STEP: f64 = std::f64::consts::PI / 12.;
+
+fn boundary_color(kind: BoundaryType) -> Vec3 {
+    match kind {
+        BoundaryType::Convergent => vec3(1.0, 0.1, 0.1),
+        BoundaryType::Divergent => vec3(0.1, 1.0, 0.1),
+        BoundaryType::Transform => vec3(1.0, 0.9, 0.1),
+    }
+}
+
+/// The generated planet, before it is turned into a mesh
+pub struct PlanetData {
+    pub regions: Vec<Region>,
+    pub adjacency: Adjacency,
+    /// finds the region in a direction
+    pub index: RegionIndex,
+    pub plates: Vec<TectonicPlate>,
+    /// index into `plates` of the plate containing every region, see
+    /// `tectonic_plates::plate_ids`
+    pub plate_id: Vec<usize>,
+    pub boundaries: Vec<PlateBoundary>,
+    /// elevation of every region above the sphere
+    pub elevation: Vec<f64>,
+    /// elevation of the water surface, see `ElevationParams::sea_level`
+    pub sea_level: f64,
+    /// whether every region is under water, see `elevation::is_ocean`
+    pub ocean: Vec<bool>,
+    /// normalized ocean depth of every region, see `elevation::ocean_depth`
+    pub ocean_depth: Vec<f64>,
+    /// temperature of every region in degrees Celsius
+    pub temperature: Vec<f64>,
+    /// region indices of every river from its source downhill
+    pub rivers: Vec<Vec<usize>>,
+    /// number of rivers flowing through every region
+    pub river_flow: Vec<u32>,
+    /// age of the oceanic crust in every region, see `compute_seafloor_age`
+    pub seafloor_age: Vec<Option<u32>>,
+}
+
+impl PlanetData {
+    pub fn generate(
+        seed: u64,
+        generation_params: &GenerationParams,
+        elevation_params: &ElevationParams,
+    ) -> Self {
+        let regions = regions::create_regions(generation_params.subdivisions);
+        Self::from_regions(seed, regions, generation_params, elevation_params)
+    }
+
+    /// Same as `generate`, but from `GPU_SUBDIVISIONS` on the icosphere is subdivided with a
+    /// compute shader, which is much faster than on the CPU at that size. Falls back to the CPU
+    /// if the GPU subdivision fails.
+    pub fn generate_on_gpu(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        seed: u64,
+        generation_params: &GenerationParams,
+        elevation_params: &ElevationParams,
+    ) -> Self {
+        let subdivisions = generation_params.subdivisions;
+        let gpu_regions = (subdivisions >= GPU_SUBDIVISIONS)
+            .then(|| regions::create_regions_gpu(device, queue, subdivisions))
+            .and_then(|regions| {
+                regions
+                    .inspect_err(|err| log::warn!("Subdividing on the CPU, the GPU failed: {err}"))
+                    .ok()
+            });
+        let regions = gpu_regions.unwrap_or_else(|| regions::create_regions(subdivisions));
+        Self::from_regions(seed, regions, generation_params, elevation_params)
+    }
+
+    /// Assigns the regions to plates and derives the rest of the planet from them
+    fn from_regions(
+        seed: u64,
+        (regions, adjacency, index): (Vec<Region>, Adjacency, RegionIndex),
+        generation_params: &GenerationParams,
+        elevation_params: &ElevationParams,
+    ) -> Self {
+        let mut rng = Pcg32::seed_from_u64(seed);
+        let plates = match generation_params.plate_assignment {
+            PlateAssignment::FloodFill => tectonic_plates::cluster_regions(
+                &mut rng,
+                &regions,
+                &adjacency,
+                generation_params.num_plates,
+                generation_params.continental_fraction,
+            ),
+            PlateAssignment::Voronoi => tectonic_plates::cluster_regions_voronoi(
+                &mut rng,
+                &regions,
+                generation_params.num_plates,
+                generation_params.continental_fraction,
+                generation_params.relax_iterations,
+            ),
+        };
+        Self::from_plates(
+            &mut rng,
+            regions,
+            adjacency,
+            index,
+            plates,
+            generation_params,
+            elevation_params,
+        )
+    }
+
+    /// Derives the boundaries, elevation, climate and rivers of the plates. `rng` only drives
+    /// the relief of `base_elevation` and the erosion.
+    fn from_plates(
+        rng: &mut impl rand::Rng,
+        regions: Vec<Region>,
+        adjacency: Adjacency,
+        index: RegionIndex,
+        plates: Vec<TectonicPlate>,
+        generation_params: &GenerationParams,
+        elevation_params: &ElevationParams,
+    ) -> Self {
+        let plate_id = tectonic_plates::plate_ids(regions.len(), &plates);
+        let boundaries = tectonic_plates::classify_boundaries(&regions, &adjacency, &plates);
+        let mut elevation =
+            elevation::tectonic_elevation(&regions, &adjacency, &boundaries, elevation_params);
+        let relief = base_elevation(&regions, rng.random(), &generation_params.noise);
+        for (elevation, relief) in elevation.iter_mut().zip(relief) {
+            *elevation += relief;
+        }
+        let sea_level = elevation_params.sea_level;
+        let ocean = ocean_regions(&plates, &elevation, elevation_params);
+        erode(
+            rng,
+            &regions,
+            &adjacency,
+            &mut elevation,
+            &ocean,
+            generation_params.erosion_iterations,
+            &generation_params.erosion,
+        );
+        // the erosion moved the coasts
+        let ocean = ocean_regions(&plates, &elevation, elevation_params);
+        let ocean_depth = elevation::ocean_depth(&regions, &adjacency, &plates, elevation_params);
+        let temperature = climate::temperature(&regions, &elevation, &generation_params.climate);
+        let rivers = trace_rivers(
+            &regions,
+            &adjacency,
+            &elevation,
+            &ocean,
+            generation_params.max_rivers,
+        );
+        let river_flow = rivers::river_flow(regions.len(), &rivers);
+        let seafloor_age = compute_seafloor_age(&regions, &adjacency, &plates, &boundaries);
+
+        PlanetData {
+            regions,
+            adjacency,
+            index,
+            plates,
+            plate_id,
+            boundaries,
+            elevation,
+            sea_level,
+            ocean,
+            ocean_depth,
+            temperature,
+            rivers,
+            river_flow,
+            seafloor_age,
+        }
+    }
+
+    /// Vertices of every region of the plate
+    fn plate_vertices<'a>(
+        &'a self,
+        plate: &'a TectonicPlate,
+        elevation_params: &ElevationParams,
+        coloring: Coloring,
+    ) -> impl Iterator<Item = Vertex> + 'a {
+        let classification = plate.classification;
+        let elevation_params = *elevation_params;
+        plate
+            .contained_regions
+            .iter()
+            .flat_map(move |&region_index| {
+                let color =
+                    self.region_color(region_index, classification, &elevation_params, coloring);
+                let material = match self.ocean[region_index] {
+                    true => Material::Water,
+                    false => Material::Land,
+                };
+                Vertex::from_region(
+                    &self.regions[region_index],
+                    region_index,
+                    color,
+                    self.surface_elevation(region_index),
+                    material,
+                )
+            })
+    }
 
-    let mut vertices = vec![];
-    for plate in &tectonic_plates {
-        for region_index in &plate.contained_regions {
-            let region = &regions[*region_index];
-            let verts = Vertex::from_region(region, plate.classification);
-            for v in verts {
-                vertices.push(v);
+    fn region_color(
+        &self,
+        region_index: usize,
+        classification: TectonicPlateClassification,
+        elevation_params: &ElevationParams,
+        coloring: Coloring,
+    ) -> Vec3 {
+        match coloring {
+            Coloring::Classification => {}
+            Coloring::Plate => return plate_color(self.plate_id[region_index], self.plates.len()),
+            Coloring::Elevation => {
+                return elevation::elevation_color(self.elevation[region_index], elevation_params)
             }
+            Coloring::SeafloorAge => {
+                return match self.seafloor_age[region_index] {
+                    Some(age) => {
+                        let t = (age as f32 / OLD_SEAFLOOR_AGE as f32).min(1.);
+                        YOUNG_SEAFLOOR_COLOR.lerp(OLD_SEAFLOOR_COLOR, t)
+                    }
+                    None => NO_AGE_COLOR,
+                }
+            }
+        }
+        match classification {
+            TectonicPlateClassification::Continental
+                if !self.ocean[region_index] && self.river_flow[region_index] > 0 =>
+            {
+                RIVER_COLOR
+            }
+            _ => elevation::band_color(
+                classification,
+                self.ocean[region_index],
+                self.elevation[region_index],
+                self.ocean_depth[region_index],
+                self.temperature[region_index],
+                elevation_params,
+            ),
+        }
+    }
+
+    /// Color of every region, indexed like `regions`
+    pub fn region_colors(
+        &self,
+        elevation_params: &ElevationParams,
+        coloring: Coloring,
+    ) -> Vec<Vec3> {
+        let mut colors = vec![Vec3::ZERO; self.regions.len()];
+        for plate in &self.plates {
+            for &region_index in &plate.contained_regions {
+                colors[region_index] = self.region_color(
+                    region_index,
+                    plate.classification,
+                    elevation_params,
+                    coloring,
+                );
+            }
+        }
+        colors
+    }
+
+    /// The plate containing the region
+    pub fn plate_of(&self, region_index: usize) -> Option<&TectonicPlate> {
+        self.plates.get(self.plate_id[region_index])
+    }
+
+    /// Height of the drawn surface of the region above the sphere, the water surface is flat at
+    /// the sea level
+    pub fn surface_elevation(&self, region_index: usize) -> f64 {
+        match self.ocean[region_index] {
+            true => self.sea_level,
+            false => self.elevation[region_index],
+        }
+    }
+
+    /// Floods the regions below `sea_level` and drains the ones above it. Only the water changes,
+    /// the rivers and the climate stay as they were generated.
+    pub fn set_sea_level(&mut self, sea_level: f64, elevation_params: &ElevationParams) {
+        let elevation_params = ElevationParams {
+            sea_level,
+            ..*elevation_params
+        };
+        self.sea_level = sea_level;
+        self.ocean = ocean_regions(&self.plates, &self.elevation, &elevation_params);
+    }
+
+    /// Rotates the regions of every plate about its motion axis, by the length of the axis
+    /// times `angle`. The plates stay rigid, so they overlap or open gaps at their boundaries.
+    pub fn move_plates(&mut self, angle: f64) {
+        for plate in &self.plates {
+            let Some(axis) = plate.motion_axis.try_normalize() else {
+                continue;
+            };
+            let rotation = DQuat::from_axis_angle(axis, plate.motion_axis.length() * angle);
+            for &region_index in &plate.contained_regions {
+                let region = &mut self.regions[region_index];
+                region.corners = region.corners.map(|corner| rotation * corner);
+            }
+        }
+    }
+
+    /// Line list with the edges of every region, the edges on plate boundaries are highlighted.
+    /// Each edge is lifted to the higher of the two regions it separates.
+    pub fn edge_lines(&self) -> (Vec<Vertex>, Vec<u32>) {
+        let plate_edges = self
+            .plates
+            .iter()
+            .flat_map(|plate| plate.plate_edges.iter().copied())
+            .collect::<HashSet<_>>();
+
+        let mut seen = HashSet::new();
+        let mut line_vertices = vec![];
+        for (region_index, region) in self.regions.iter().enumerate() {
+            let neighbors = self.adjacency.neighbors(region_index);
+            for (k, edge) in region.edges.into_iter().enumerate() {
+                if !seen.insert(edge) {
+                    continue;
+                }
+                let color = match plate_edges.contains(&edge) {
+                    true => PLATE_EDGE_COLOR,
+                    false => REGION_EDGE_COLOR,
+                };
+                let elevation = self
+                    .surface_elevation(region_index)
+                    .max(self.surface_elevation(neighbors[k]));
+                // edge k goes from corner k to the next corner
+                let corners =
+                    Vertex::from_region(region, region_index, color, elevation, Material::Land);
+                line_vertices.extend([corners[k], corners[(k + 1) % 3]]);
+            }
+        }
+        deduplicate(line_vertices.into_iter())
+    }
+
+    /// A point at the centroid of every region in its color, each repeated six times so the
+    /// vertex shader can expand it into a quad
+    pub fn centroid_points(
+        &self,
+        elevation_params: &ElevationParams,
+        coloring: Coloring,
+    ) -> Vec<Vertex> {
+        let colors = self.region_colors(elevation_params, coloring);
+        self.regions
+            .iter()
+            .enumerate()
+            .flat_map(|(region_index, region)| {
+                let direction = region.centroid();
+                let material = match self.ocean[region_index] {
+                    true => Material::Water,
+                    false => Material::Land,
+                };
+                let vertex = Vertex {
+                    position: (direction * (RADIUS + self.surface_elevation(region_index))).into(),
+                    color: pack_color(colors[region_index]),
+                    region: region_index as u32,
+                    normal: direction.as_vec3(),
+                    material: material as u32,
+                    uv: Vec2::ZERO,
+                };
+                [vertex; 6]
+            })
+            .collect()
+    }
+
+    /// A line strip through the directions of `arc` as a line list, following the elevation of
+    /// the regions below it
+    pub fn arc_lines(&self, arc: &[DVec3]) -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = arc
+            .iter()
+            .map(|&direction| {
+                let direction = direction.normalize();
+                let region = self.index.locate(direction * RADIUS);
+                let height = RADIUS + self.surface_elevation(region) + MEASURE_LIFT;
+                Vertex {
+                    position: (direction * height).into(),
+                    color: pack_color(MEASURE_COLOR),
+                    region: region as u32,
+                    normal: direction.as_vec3(),
+                    material: Material::Land as u32,
+                    uv: Vec2::ZERO,
+                }
+            })
+            .collect::<Vec<_>>();
+        let indices = (1..vertices.len() as u32)
+            .flat_map(|i| [i - 1, i])
+            .collect();
+        (vertices, indices)
+    }
+
+    /// Ribbons of triangles of the given width along every plate boundary, colored by the kind
+    /// of boundary
+    pub fn boundary_lines(&self, width: f64) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for boundary in &self.boundaries {
+            let [a, b] = boundary.regions;
+            let region = &self.regions[a];
+            let Some(k) = region.edges.iter().position(|&edge| edge == boundary.edge) else {
+                continue;
+            };
+            let elevation = self.surface_elevation(a).max(self.surface_elevation(b));
+            let color = boundary_color(boundary.kind);
+            let [start, end] = [k, (k + 1) % 3].map(|corner| {
+                let corner = region.corners[corner];
+                corner + corner.normalize() * elevation
+            });
+            let side = (end - start).cross(start + end).normalize() * (width / 2.);
+            let first = vertices.len() as u32;
+            for position in [start - side, start + side, end + side, end - side] {
+                vertices.push(Vertex {
+                    position: position.into(),
+                    color: pack_color(color),
+                    region: a as u32,
+                    normal: (start + end).normalize().as_vec3(),
+                    material: Material::Land as u32,
+                    uv: Vec2::ZERO,
+                });
+            }
+            indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+        }
+        (vertices, indices)
+    }
+
+    /// Mesh shared by all regions. The first vertex of every triangle belongs to its region
+    /// alone, it provides the flat color and region index, the other two corners are shared.
+    /// Flat shaded meshes have zero normals, the fragment shader then uses the face normal.
+    pub fn mesh(
+        &self,
+        elevation_params: &ElevationParams,
+        shading: Shading,
+        coloring: Coloring,
+    ) -> (Vec<Vertex>, Vec<u32>) {
+        // vertices are only shared within a plate, so every vertex moves with a single plate
+        // in the `TectonicSim`
+        let triangle_vertices = self
+            .plates
+            .iter()
+            .enumerate()
+            .flat_map(|(plate_index, plate)| {
+                self.plate_vertices(plate, elevation_params, coloring)
+                    .map(move |vertex| (plate_index, vertex))
+            })
+            .collect::<Vec<_>>();
+
+        // corners on both sides of the texture seam have different uvs, they aren't shared
+        let mut at_position = HashMap::new();
+        let mut vertices = vec![];
+        // vertices that are already the first vertex of a triangle
+        let mut claimed = vec![];
+        let mut indices = Vec::with_capacity(triangle_vertices.len());
+        for triangle in triangle_vertices.chunks_exact(3) {
+            let plate_index = triangle[0].0;
+            let triangle = [0, 1, 2].map(|k| triangle[k].1);
+            let mut corners = [0, 1, 2].map(|k| {
+                let key = (
+                    plate_index,
+                    triangle[k].position,
+                    triangle[k].uv.to_array().map(f32::to_bits),
+                );
+                *at_position.entry(key).or_insert_with(|| {
+                    vertices.push(triangle[k]);
+                    claimed.push(false);
+                    (vertices.len() - 1) as u32
+                })
+            });
+            let first = match (0..3).find(|&k| !claimed[corners[k] as usize]) {
+                Some(k) => k,
+                None => {
+                    // every corner already provides another triangle, add a copy of one
+                    vertices.push(triangle[0]);
+                    claimed.push(false);
+                    corners[0] = (vertices.len() - 1) as u32;
+                    0
+                }
+            };
+            // rotating the corners keeps the winding order
+            corners.rotate_left(first);
+            let provoking = corners[0] as usize;
+            vertices[provoking] = triangle[first];
+            claimed[provoking] = true;
+            indices.extend(corners);
+        }
+
+        match shading {
+            Shading::Flat => {
+                for vertex in &mut vertices {
+                    vertex.normal = Vec3::ZERO;
+                }
+            }
+            Shading::Smooth => {
+                // copies of a vertex, e.g. on the texture seam, share the normal at their position
+                let mut normals = HashMap::<PackedVec3, DVec3>::new();
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|k| vertices[triangle[k] as usize].position);
+                    let [pa, pb, pc] = [a, b, c].map(DVec3::from);
+                    // not normalized, so larger triangles weigh more
+                    let normal = (pb - pa).cross(pc - pa);
+                    for position in [a, b, c] {
+                        *normals.entry(position).or_default() += normal;
+                    }
+                }
+                for vertex in &mut vertices {
+                    let normal = normals[&vertex.position];
+                    vertex.normal = normal.normalize_or_zero().as_vec3();
+                }
+            }
+        }
+        (vertices, indices)
+    }
+}
+
+/// Whether every region is under water, regions without a plate are land
+fn ocean_regions(
+    plates: &[TectonicPlate],
+    elevation: &[f64],
+    elevation_params: &ElevationParams,
+) -> Vec<bool> {
+    let mut ocean = vec![false; elevation.len()];
+    for plate in plates {
+        for &region_index in &plate.contained_regions {
+            ocean[region_index] = elevation::is_ocean(
+                plate.classification,
+                elevation[region_index],
+                elevation_params,
+            );
         }
     }
-    let indices = (0..vertices.len() as u16).collect();
+    ocean
+}
+
+/// Indexes the vertices, merging vertices at the same position with the same color.
+/// The normals of merged vertices are not kept.
+fn deduplicate(vertices: impl Iterator<Item = Vertex>) -> (Vec<Vertex>, Vec<u32>) {
+    let mut unique = HashMap::new();
+    let mut unique_vertices = vec![];
+    let indices = vertices
+        .map(|vertex| {
+            let key = (vertex.position, vertex.color);
+            *unique.entry(key).or_insert_with(|| {
+                unique_vertices.push(vertex);
+                (unique_vertices.len() - 1) as u32
+            })
+        })
+        .collect();
+    (unique_vertices, indices)
+}
+
+pub fn build_planet(
+    seed: u64,
+    generation_params: &GenerationParams,
+    elevation_params: &ElevationParams,
+    shading: Shading,
+) -> (Vec<Vertex>, Vec<u32>) {
+    PlanetData::generate(seed, generation_params, elevation_params).mesh(
+        elevation_params,
+        shading,
+        Coloring::default(),
+    )
+}
 
-    (vertices, indices)
+/// The region hit by a ray from `origin` in direction `dir`, treating the planet as a sphere
+/// of `RADIUS` without elevation
+pub fn pick(data: &PlanetData, origin: DVec3, dir: DVec3) -> Option<usize> {
+    pick_point(origin, dir).map(|hit| data.index.locate(hit))
+}
+
+/// The first point where the ray from `origin` along `dir` hits the sphere of the planet
+pub fn pick_point(origin: DVec3, dir: DVec3) -> Option<DVec3> {
+    let dir = dir.normalize();
+    // solve |origin + t * dir| = RADIUS for the closest t in front of the origin
+    let b = origin.dot(dir);
+    let c = origin.length_squared() - RADIUS * RADIUS;
+    let discriminant = b * b - c;
+    if discriminant < 0. {
+        return None;
+    }
+    let t = [-b - discriminant.sqrt(), -b + discriminant.sqrt()]
+        .into_iter()
+        .find(|&t| t >= 0.)?;
+    Some(origin + t * dir)
 }
 
 pub fn vertex_buffer(device: &wgpu::Device, vertices: &[Vertex]) -> Buffer<Vertex> {
     device.create_typed_buffer_init(&TypedBufferInitDescriptor {
         label: Some("Vertex Buffer"),
         contents: vertices,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+/// Line list of the parallels every `lat_step` and the meridians every `lon_step` radians, the
+/// parallels leave out the poles and the meridians run from pole to pole. The positions are
+/// in world units, just above the mountains.
+pub fn graticule(device: &wgpu::Device, lat_step: f64, lon_step: f64) -> Buffer<Vec3> {
+    use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+    let point = |latitude: f64, longitude: f64| {
+        let (sin_lat, cos_lat) = latitude.sin_cos();
+        let (sin_lon, cos_lon) = longitude.sin_cos();
+        let direction = dvec3(cos_lat * cos_lon, cos_lat * sin_lon, sin_lat);
+        (direction * (RADIUS + GRATICULE_HEIGHT)).as_vec3()
+    };
+    let mut positions = vec![];
+    // `at` goes along the line from 0 to `span`, which is `length` radians of a great circle
+    let mut line = |span: f64, length: f64, at: &dyn Fn(f64) -> Vec3| {
+        let segments = ((length / GRATICULE_SEGMENT).ceil() as usize).max(1);
+        for i in 0..segments {
+            positions.extend([i, i + 1].map(|j| at(span * j as f64 / segments as f64)));
+        }
+    };
+    let parallels = (FRAC_PI_2 / lat_step).ceil() as i64;
+    for k in 1 - parallels..parallels {
+        let latitude = k as f64 * lat_step;
+        line(TAU, TAU * latitude.cos(), &|longitude| {
+            point(latitude, longitude)
+        });
+    }
+    let meridians = (TAU / lon_step).round() as usize;
+    for k in 0..meridians {
+        let longitude = k as f64 * lon_step;
+        line(PI, PI, &|t| point(t - FRAC_PI_2, longitude));
+    }
+
+    device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+        label: Some("Graticule Buffer"),
+        contents: &positions,
         usage: wgpu::BufferUsages::VERTEX,
     })
 }
 
-pub fn index_buffer(device: &wgpu::Device, indices: &[u16]) -> Buffer<u16> {
+/// Usage of the vertex buffer of the mesh, the `TectonicSim` moves the vertices in place
+const MESH_USAGE: wgpu::BufferUsages = wgpu::BufferUsages::VERTEX
+    .union(wgpu::BufferUsages::COPY_DST)
+    .union(wgpu::BufferUsages::STORAGE);
+
+/// Pads the colors to `Vec4`, matching the array stride of `vec3<f32>` in WGSL
+fn padded(colors: &[Vec3]) -> Vec<Vec4> {
+    colors.iter().map(|color| color.extend(1.)).collect()
+}
+
+pub fn region_colors_buffer(device: &wgpu::Device, colors: &[Vec3]) -> Buffer<Vec4> {
+    device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+        label: Some("Region Colors Buffer"),
+        contents: &padded(colors),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+/// Writes the mesh into the buffers, growing them if needed
+fn write_mesh<I: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    vertex_buffer: &mut Buffer<Vertex>,
+    index_buffer: &mut Buffer<I>,
+    (vertices, indices): (Vec<Vertex>, Vec<I>),
+) {
+    vertex_buffer.ensure_capacity(
+        device,
+        vertices.len(),
+        wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    );
+    queue.write_typed_buffer(vertex_buffer, 0, &vertices);
+    index_buffer.ensure_capacity(
+        device,
+        indices.len(),
+        wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+    );
+    queue.write_typed_buffer(index_buffer, 0, &indices);
+}
+
+pub fn index_buffer(device: &wgpu::Device, indices: &[u32]) -> Buffer<u32> {
     device.create_typed_buffer_init(&TypedBufferInitDescriptor {
         label: Some("Index Buffer"),
         contents: indices,
-        usage: wgpu::BufferUsages::INDEX,
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+/// Size of the squares of the checkerboard in pixels, 22.5 degrees of longitude and latitude
+const CHECKER_SIZE: u32 = 32;
+
+/// Checkerboard with squares of equal longitude and latitude. On the planet the squares
+/// only get narrow towards the poles, anything else is a bug in the uv mapping.
+fn checkerboard_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+    let (width, height) = (16 * CHECKER_SIZE, 8 * CHECKER_SIZE);
+    let texels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x / CHECKER_SIZE + y / CHECKER_SIZE) % 2))
+        .flat_map(|odd| {
+            if odd == 1 {
+                [230, 230, 230, 255]
+            } else {
+                [40, 40, 40, 255]
+            }
+        })
+        .collect::<Vec<u8>>();
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("planet_checkerboard_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        &texels,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Uploads an sRGB image with a full mip chain, so the texture doesn't shimmer where it is
+/// squeezed towards the poles. Images larger than the device supports are scaled down.
+fn image_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut image: image::RgbaImage,
+) -> wgpu::TextureView {
+    use image::imageops::{self, FilterType};
+
+    let max_size = device.limits().max_texture_dimension_2d;
+    let (width, height) = image.dimensions();
+    if width > max_size || height > max_size {
+        let scale = max_size as f64 / width.max(height) as f64;
+        let scaled = [width, height].map(|size| ((size as f64 * scale) as u32).max(1));
+        log::warn!(
+            "Scaling the {width}x{height} texture down to {}x{}",
+            scaled[0],
+            scaled[1]
+        );
+        image = imageops::resize(&image, scaled[0], scaled[1], FilterType::Triangle);
+    }
+
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("planet_image_texture"),
+        size,
+        mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    for mip_level in 0..texture.mip_level_count() {
+        if mip_level > 0 {
+            // sizes that aren't a power of two round down, like the mips of the texture
+            let (width, height) = ((image.width() / 2).max(1), (image.height() / 2).max(1));
+            image = imageops::resize(&image, width, height, FilterType::Triangle);
+        }
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &image,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Pipeline constants telling the shader whether the colors need converting for `format`
+fn color_constants(format: wgpu::TextureFormat) -> HashMap<String, f64> {
+    HashMap::from([(
+        "LINEAR_TARGET".to_string(),
+        setup::linear_target(format) as u32 as f64,
+    )])
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    vertex_entry_point: &str,
+    fragment_entry_point: &str,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    polygon_mode: wgpu::PolygonMode,
+    depth_stencil: wgpu::DepthStencilState,
+) -> wgpu::RenderPipeline {
+    let constants = color_constants(format);
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        constants: &constants,
+        ..Default::default()
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some(vertex_entry_point),
+            buffers: &[Vertex::desc(), Instance::desc()],
+            compilation_options: compilation_options.clone(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment_entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options,
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(depth_stencil),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Depth test of the filled planet. After the depth prepass only the fragments on its depth
+/// are shaded, and the depth is already written.
+fn fill_depth_stencil_state(depth_prepass: bool) -> wgpu::DepthStencilState {
+    match depth_prepass {
+        true => wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Equal,
+            ..camera::depth_stencil_state()
+        },
+        false => camera::depth_stencil_state(),
+    }
+}
+
+fn line_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    vertex_entry_point: &str,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Option<wgpu::RenderPipeline> {
+    let polygon_mode = setup::polygon_mode(device, wgpu::PolygonMode::Line);
+    (polygon_mode == wgpu::PolygonMode::Line).then(|| {
+        render_pipeline(
+            device,
+            layout,
+            shader,
+            vertex_entry_point,
+            "fs_main",
+            format,
+            sample_count,
+            polygon_mode,
+            camera::depth_stencil_state(),
+        )
+    })
+}
+
+/// Pipeline drawing the point cloud, every point is expanded into a quad in the vertex shader
+fn point_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let constants = color_constants(format);
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        constants: &constants,
+        ..Default::default()
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Point Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_point"),
+            buffers: &[Vertex::desc(), Instance::desc()],
+            compilation_options: compilation_options.clone(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_line"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options,
+        }),
+        // the quads face the camera, no culling needed
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(camera::depth_stencil_state()),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Pipeline drawing lines on top of the filled planet, either the region edges as a line list
+/// or the plate boundaries as ribbons of triangles
+fn overlay_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    topology: wgpu::PrimitiveTopology,
+) -> wgpu::RenderPipeline {
+    let constants = color_constants(format);
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        constants: &constants,
+        ..Default::default()
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Overlay Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_line"),
+            buffers: &[Vertex::desc(), Instance::desc()],
+            compilation_options: compilation_options.clone(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_line"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options,
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            ..Default::default()
+        },
+        // the shader offsets the depth of the lines, they don't occlude anything themselves
+        depth_stencil: Some(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            ..camera::depth_stencil_state()
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Pipeline blending the latitude and longitude lines of `graticule` over the planet. They are
+/// depth tested, so the planet hides the lines on its far side.
+fn graticule_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+
+    let constants = color_constants(format);
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        constants: &constants,
+        ..Default::default()
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Graticule Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_graticule"),
+            buffers: &[
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vec3>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &ATTRIBS,
+                },
+                Instance::desc(),
+            ],
+            compilation_options: compilation_options.clone(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_graticule"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options,
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::LineList,
+            ..Default::default()
+        },
+        // offset towards the camera in the shader like the other lines
+        depth_stencil: Some(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            ..camera::depth_stencil_state()
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Pipeline that only writes the depth of the planet, without a color target
+fn depth_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    vertex_entry_point: &str,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Depth Prepass Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some(vertex_entry_point),
+            buffers: &[Vertex::desc(), Instance::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(camera::depth_stencil_state()),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// What culling needs to know about a triangle
+struct TriangleBounds {
+    /// bounding sphere
+    center: DVec3,
+    radius: f64,
+    normal: DVec3,
+}
+
+/// Pipeline drawing the depth of the planet into the shadow map
+fn shadow_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Planet Shadow Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_shadow"),
+            buffers: &[Vertex::desc(), Instance::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(shadow_map::depth_stencil_state()),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn triangle_bounds(vertices: &[Vertex], indices: &[u32]) -> Vec<TriangleBounds> {
+    indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let corners = [0, 1, 2].map(|i| DVec3::from(vertices[triangle[i] as usize].position));
+            let center = (corners[0] + corners[1] + corners[2]) / 3.;
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0., f64::max);
+            let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            let normal = normal.normalize() * normal.dot(center).signum();
+            TriangleBounds {
+                center,
+                radius,
+                normal,
+            }
+        })
+        .collect()
+}
+
+fn bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    camera_uniform: &wgpu::Buffer,
+    region_colors: Option<&Buffer<Vec4>>,
+    texture: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    let mut entries = vec![
+        camera::bind_group_entry(camera_uniform),
+        wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::TextureView(texture),
+        },
+        wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::Sampler(sampler),
+        },
+    ];
+    if let Some(region_colors) = region_colors {
+        entries.push(wgpu::BindGroupEntry {
+            binding: 1,
+            resource: region_colors.as_entire_binding(),
+        });
+    }
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &entries,
+        label: Some("camera_bind_group"),
     })
 }
 
 pub struct Planet {
+    pub data: PlanetData,
+    elevation_params: ElevationParams,
+    shading: Shading,
+    coloring: Coloring,
+    /// all triangles of the mesh, the index buffer only holds the visible ones when culling
+    indices: Vec<u32>,
+    bounds: Vec<TriangleBounds>,
+    /// only draw the triangles intersecting the view frustum
+    pub frustum_culling: bool,
+    /// only draw the triangles on the hemisphere facing the camera
+    pub hemisphere_culling: bool,
+    /// camera position, look direction, culling settings and `drift` of the last culling
+    culled_from: Option<(DVec3, Vec3, [bool; 2], f64)>,
+    /// the triangles moved since the last culling
+    bounds_changed: bool,
+    /// rotation of the plates in radians per second per unit of motion axis length
+    pub motion_rate: f64,
+    pub animate_plates: bool,
+    tectonic_sim: TectonicSim,
     vertex_buffer: Buffer<Vertex>,
-    index_buffer: Buffer<u16>,
+    index_buffer: Buffer<u32>,
+    line_vertex_buffer: Buffer<Vertex>,
+    line_index_buffer: Buffer<u32>,
+    /// draw the region edges on top of the filled planet
+    pub wireframe_overlay: bool,
+    boundary_vertex_buffer: Buffer<Vertex>,
+    boundary_index_buffer: Buffer<u32>,
+    /// draw the plate boundaries colored by their kind
+    pub show_boundaries: bool,
+    arrow_vertex_buffer: Buffer<Vertex>,
+    arrow_index_buffer: Buffer<u32>,
+    /// draw an arrow along the motion of every plate, see `tectonic_plates::motion_arrows`
+    pub show_motion_arrows: bool,
+    /// the region centroids drawn in `RenderMode::Points`
+    point_vertex_buffer: Buffer<Vertex>,
+    /// measured arc, empty when nothing is measured
+    measure_vertex_buffer: Buffer<Vertex>,
+    measure_index_buffer: Buffer<u32>,
+    /// latitude and longitude lines
+    graticule_buffer: Buffer<Vec3>,
+    pub show_graticule: bool,
+    /// copies of the planet that are drawn, a single one at the origin unless set by
+    /// `set_instances`
+    instances: Vec<Transform>,
+    instance_buffer: Buffer<Instance>,
+    /// color of every region, read by the vertex shader when it supports storage buffers
+    region_colors: Option<Buffer<Vec4>>,
+    /// kept to rebuild the bind group when the region color buffer is reallocated
+    camera_uniform: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// equirectangular, a checkerboard showing the uv mapping unless set by `with_texture`
+    texture: wgpu::TextureView,
+    sampler: wgpu::Sampler,
     bind_group: wgpu::BindGroup,
+    /// the shadow map for the scene pipelines and its light matrix for the shadow pipeline
+    shadow_bind_group: wgpu::BindGroup,
+    light_bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    shadow_pipeline_layout: wgpu::PipelineLayout,
+    vertex_entry_point: &'static str,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
     render_pipeline: wgpu::RenderPipeline,
+    /// colors the planet with the texture instead of the regions
+    textured_pipeline: wgpu::RenderPipeline,
+    /// wireframe pipeline, only available with POLYGON_MODE_LINE
+    line_render_pipeline: Option<wgpu::RenderPipeline>,
+    depth_pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+    boundary_pipeline: wgpu::RenderPipeline,
+    graticule_pipeline: wgpu::RenderPipeline,
+    point_pipeline: wgpu::RenderPipeline,
+    pub render_mode: RenderMode,
+    /// draw the depth of the planet in a separate pass before the background, so the later
+    /// passes can sample it from the camera depth texture. Toggled with
+    /// `toggle_depth_prepass`, the filled planet is then only shaded where the prepass left it.
+    depth_prepass: bool,
+    /// draw the texture instead of the region colors
+    pub show_texture: bool,
 }
 
 impl Planet {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        vertex_storage: bool,
         camera_uniform: &Buffer<camera::CameraUniform>,
+        shadow_map: &ShadowMap,
+        seed: u64,
+        generation_params: &GenerationParams,
+        elevation_params: &ElevationParams,
     ) -> Result<Self> {
-        let (vertices, indices) = build_planet();
+        let data =
+            PlanetData::generate_on_gpu(device, queue, seed, generation_params, elevation_params);
+        Self::from_data(
+            device,
+            queue,
+            config,
+            sample_count,
+            vertex_storage,
+            camera_uniform,
+            shadow_map,
+            data,
+            elevation_params,
+        )
+    }
 
-        let vertex_buffer = vertex_buffer(device, &vertices);
+    /// Builds the buffers and pipelines for planet data that was generated or loaded with
+    /// `elevation_params`, e.g. with `PlanetData::load`
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        vertex_storage: bool,
+        camera_uniform: &Buffer<camera::CameraUniform>,
+        shadow_map: &ShadowMap,
+        data: PlanetData,
+        elevation_params: &ElevationParams,
+    ) -> Result<Self> {
+        let elevation_params = *elevation_params;
+        let shading = Shading::default();
+        let coloring = Coloring::default();
+        log::info!(
+            "{}",
+            Stats::compute(&data.regions, &data.plates, &data.ocean)
+        );
+        let (vertices, indices) = data.mesh(&elevation_params, shading, coloring);
+        let bounds = triangle_bounds(&vertices, &indices);
+
+        let (line_vertices, line_indices) = data.edge_lines();
+        let line_vertex_buffer = vertex_buffer(device, &line_vertices);
+        let line_index_buffer = index_buffer(device, &line_indices);
+        let (boundary_vertices, boundary_indices) = data.boundary_lines(BOUNDARY_LINE_WIDTH);
+        let boundary_vertex_buffer = vertex_buffer(device, &boundary_vertices);
+        let boundary_index_buffer = index_buffer(device, &boundary_indices);
+        let (arrow_vertices, arrow_indices) =
+            tectonic_plates::motion_arrows(&data.plates, &data.regions);
+        let arrow_vertex_buffer = vertex_buffer(device, &arrow_vertices);
+        let arrow_index_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("Arrow Index Buffer"),
+            contents: &arrow_indices,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let point_vertex_buffer =
+            vertex_buffer(device, &data.centroid_points(&elevation_params, coloring));
+        let measure_vertex_buffer = vertex_buffer(device, &[]);
+        let measure_index_buffer = index_buffer(device, &[]);
+        let graticule_buffer = graticule(device, GRATICULE_STEP, GRATICULE_STEP);
+        let vertex_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: &vertices,
+            usage: MESH_USAGE,
+        });
         let index_buffer = index_buffer(device, &indices);
+        let tectonic_sim = TectonicSim::new(device, queue, &data, &vertices, &vertex_buffer)?;
+        let instances = vec![Transform::default()];
+        let instance_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("instance_buffer"),
+            contents: &[Instance::from(instances[0])],
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let region_colors = vertex_storage.then(|| {
+            region_colors_buffer(device, &data.region_colors(&elevation_params, coloring))
+        });
+        let vertex_entry_point = match region_colors {
+            Some(_) => "vs_main",
+            None => "vs_vertex_color",
+        };
 
-        let shader = setup::shader(device, "shaders/planet.wgsl")?;
+        let shader = setup::shader(device, SHADER)?;
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
+        let mut layout_entries = vec![
+            camera::bind_group_layout_entry(),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+        if region_colors.is_some() {
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 1,
                 visibility: wgpu::ShaderStages::VERTEX,
                 ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
                     has_dynamic_offset: false,
                     min_binding_size: None,
                 },
                 count: None,
-            }],
+            });
+        }
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &layout_entries,
             label: Some("camera_bind_group_layout"),
         });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_uniform.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
+        let camera_uniform = (**camera_uniform).clone();
+        let texture = checkerboard_texture(device, queue);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("planet_texture_sampler"),
+            // regions on the seam have u beyond 1
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
+        let bind_group = bind_group(
+            device,
+            &bind_group_layout,
+            &camera_uniform,
+            region_colors.as_ref(),
+            &texture,
+            &sampler,
+        );
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Triangle Render Pipeline Layout"),
-                bind_group_layouts: &[&bind_group_layout],
+                bind_group_layouts: &[&bind_group_layout, shadow_map.bind_group_layout()],
+                push_constant_ranges: &[],
+            });
+        let shadow_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Planet Shadow Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout, shadow_map.light_bind_group_layout()],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[Vertex::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(camera::depth_stencil_state()),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        let format = config.format;
+        let textured_pipeline = render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            vertex_entry_point,
+            "fs_textured",
+            format,
+            sample_count,
+            wgpu::PolygonMode::Fill,
+            fill_depth_stencil_state(false),
+        );
+        let render_pipeline = render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            vertex_entry_point,
+            "fs_main",
+            format,
+            sample_count,
+            wgpu::PolygonMode::Fill,
+            fill_depth_stencil_state(false),
+        );
+        let line_render_pipeline = line_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            vertex_entry_point,
+            format,
+            sample_count,
+        );
+        let depth_pipeline = depth_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            vertex_entry_point,
+            sample_count,
+        );
+        let shadow_pipeline = shadow_pipeline(device, &shadow_pipeline_layout, &shader);
+        let boundary_pipeline = overlay_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+        let overlay_pipeline = overlay_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+            wgpu::PrimitiveTopology::LineList,
+        );
+        let point_pipeline = point_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+        );
+        let graticule_pipeline = graticule_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            format,
+            sample_count,
+        );
 
         Ok(Planet {
+            data,
+            elevation_params,
+            shading,
+            coloring,
+            indices,
+            bounds,
+            frustum_culling: false,
+            hemisphere_culling: false,
+            culled_from: None,
+            bounds_changed: false,
+            motion_rate: 0.05,
+            animate_plates: false,
+            tectonic_sim,
             vertex_buffer,
             index_buffer,
+            line_vertex_buffer,
+            line_index_buffer,
+            wireframe_overlay: false,
+            boundary_vertex_buffer,
+            boundary_index_buffer,
+            show_boundaries: false,
+            arrow_vertex_buffer,
+            arrow_index_buffer,
+            show_motion_arrows: false,
+            point_vertex_buffer,
+            measure_vertex_buffer,
+            measure_index_buffer,
+            graticule_buffer,
+            show_graticule: false,
+            instances,
+            instance_buffer,
+            region_colors,
+            camera_uniform,
+            bind_group_layout,
+            texture,
+            sampler,
             bind_group,
+            shadow_bind_group: shadow_map.bind_group().clone(),
+            light_bind_group: shadow_map.light_bind_group().clone(),
+            render_pipeline_layout,
+            shadow_pipeline_layout,
+            vertex_entry_point,
+            format,
+            sample_count,
             render_pipeline,
+            textured_pipeline,
+            line_render_pipeline,
+            depth_pipeline,
+            shadow_pipeline,
+            overlay_pipeline,
+            boundary_pipeline,
+            graticule_pipeline,
+            point_pipeline,
+            render_mode: RenderMode::default(),
+            depth_prepass: false,
+            show_texture: false,
         })
     }
+
+    /// Advances the plate motion by `steps` steps of `dt` seconds. The mesh is moved on the
+    /// GPU by the `TectonicSim`, in a compute pass submitted before the frame is drawn. The
+    /// region edges, plate boundaries, motion arrows and centroid points are built on the CPU,
+    /// while one of them is shown the whole mesh is rebuilt and uploaded instead.
+    pub fn step(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, dt: f64, steps: u32) {
+        // every plate turns around a fixed axis, the steps add up to a single rotation
+        let angle = self.motion_rate * dt * steps as f64;
+        self.data.move_plates(angle);
+        if self.wireframe_overlay
+            || self.show_boundaries
+            || self.show_motion_arrows
+            || self.render_mode == RenderMode::Points
+        {
+            self.upload_mesh(device, queue);
+            return;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Tectonics Encoder"),
+        });
+        self.tectonic_sim.dispatch(queue, &mut encoder, angle);
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Largest distance in world units a vertex moved on the GPU since the mesh was uploaded,
+    /// `bounds` are that much behind
+    fn drift(&self) -> f64 {
+        let max_speed = self
+            .data
+            .plates
+            .iter()
+            .map(|plate| plate.motion_axis.length())
+            .fold(0., f64::max);
+        // a vertex moves along an arc of at most its distance from the center
+        max_speed * self.tectonic_sim.angle() * (RADIUS + self.elevation_params.peak_height)
+    }
+
+    /// Replaces the planet, e.g. with one generated at another subdivision level, and uploads
+    /// it. The buffers are reallocated when they are too small for the new planet.
+    pub fn set_data(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: PlanetData) {
+        self.data = data;
+        self.upload_mesh(device, queue);
+        if let Some(region_colors) = &mut self.region_colors {
+            region_colors.ensure_capacity(
+                device,
+                self.data.regions.len(),
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            );
+            let colors = self
+                .data
+                .region_colors(&self.elevation_params, self.coloring);
+            queue.write_typed_buffer(region_colors, 0, &padded(&colors));
+            self.bind_group = bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.camera_uniform,
+                Some(region_colors),
+                &self.texture,
+                &self.sampler,
+            );
+        }
+    }
+
+    /// Draws the great circle arc through the directions of `arc` on the surface, an empty arc
+    /// removes it
+    pub fn set_measurement(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, arc: &[DVec3]) {
+        write_mesh(
+            device,
+            queue,
+            &mut self.measure_vertex_buffer,
+            &mut self.measure_index_buffer,
+            self.data.arc_lines(arc),
+        );
+    }
+
+    /// Draws a copy of the planet for every transform, all in the same draw calls. An empty
+    /// slice draws nothing.
+    pub fn set_instances(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        transforms: &[Transform],
+    ) {
+        let instances = transforms
+            .iter()
+            .copied()
+            .map(Instance::from)
+            .collect::<Vec<_>>();
+        self.instance_buffer.ensure_capacity(
+            device,
+            instances.len(),
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        );
+        queue.write_typed_buffer(&self.instance_buffer, 0, &instances);
+        self.instances = transforms.to_vec();
+        // the culling only knows the planet at the origin
+        self.culled_from = None;
+        self.upload_indices(device, queue, self.indices.clone());
+    }
+
+    fn upload_mesh(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let (vertices, indices) =
+            self.data
+                .mesh(&self.elevation_params, self.shading, self.coloring);
+        self.vertex_buffer
+            .ensure_capacity(device, vertices.len(), MESH_USAGE);
+        queue.write_typed_buffer(&self.vertex_buffer, 0, &vertices);
+        self.tectonic_sim
+            .reset(device, queue, &self.data, &vertices, &self.vertex_buffer);
+        self.bounds = triangle_bounds(&vertices, &indices);
+        self.indices = indices;
+        self.upload_indices(device, queue, self.indices.clone());
+        self.bounds_changed = true;
+
+        write_mesh(
+            device,
+            queue,
+            &mut self.line_vertex_buffer,
+            &mut self.line_index_buffer,
+            self.data.edge_lines(),
+        );
+        write_mesh(
+            device,
+            queue,
+            &mut self.boundary_vertex_buffer,
+            &mut self.boundary_index_buffer,
+            self.data.boundary_lines(BOUNDARY_LINE_WIDTH),
+        );
+        write_mesh(
+            device,
+            queue,
+            &mut self.arrow_vertex_buffer,
+            &mut self.arrow_index_buffer,
+            tectonic_plates::motion_arrows(&self.data.plates, &self.data.regions),
+        );
+        self.upload_points(device, queue);
+    }
+
+    fn upload_points(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let points = self
+            .data
+            .centroid_points(&self.elevation_params, self.coloring);
+        self.point_vertex_buffer.ensure_capacity(
+            device,
+            points.len(),
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        );
+        queue.write_typed_buffer(&self.point_vertex_buffer, 0, &points);
+    }
+
+    /// Camera movement after which the visible triangles are culled again, in world units for
+    /// the position and radians for the look direction
+    const CULL_DISTANCE: f64 = 0.01 * RADIUS;
+    const CULL_ANGLE: f32 = 0.01;
+    /// Triangles facing slightly away from the camera are kept, so the regions at the limb
+    /// don't pop
+    const HEMISPHERE_SLACK: f64 = 0.05;
+
+    /// Rewrites the index buffer with the triangles that are visible from the camera. Culling
+    /// only reruns when the camera moved enough, the triangle bounds are grown by the allowed
+    /// movement so nothing pops in at the edges of the screen in the meantime. The triangles
+    /// are culled for the planet at the origin, with other instances everything is drawn.
+    pub fn cull(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera) {
+        let settings = [self.frustum_culling, self.hemisphere_culling];
+        if settings == [false, false] || self.instances != [Transform::default()] {
+            if self.culled_from.take().is_some() {
+                self.upload_indices(device, queue, self.indices.clone());
+            }
+            return;
+        }
+        let drift = self.drift();
+        if let Some((position, look_dir, culled_settings, culled_drift)) = self.culled_from {
+            if position.distance(camera.position) < Self::CULL_DISTANCE
+                && look_dir.angle_between(camera.look_dir) < Self::CULL_ANGLE
+                && culled_settings == settings
+                && drift - culled_drift < Self::CULL_DISTANCE
+                && !self.bounds_changed
+            {
+                return;
+            }
+        }
+        self.culled_from = Some((camera.position, camera.look_dir, settings, drift));
+        self.bounds_changed = false;
+
+        let planes = camera.frustum_planes().map(|plane| plane.as_dvec4());
+        // the plates move the triangles away from their bounds, by up to another cull distance
+        // before culling again
+        let drift = drift + Self::CULL_DISTANCE;
+        let slack =
+            Self::CULL_DISTANCE + camera.position.length() * Self::CULL_ANGLE as f64 + drift;
+        let view_dir = camera.position.normalize_or_zero();
+        // the direction towards the camera changes by up to this much before culling again, the
+        // normals turn with the plates
+        let hemisphere_slack = Self::HEMISPHERE_SLACK
+            + Self::CULL_DISTANCE / camera.position.length().max(RADIUS)
+            + drift / RADIUS;
+        let visible = self
+            .indices
+            .chunks_exact(3)
+            .zip(&self.bounds)
+            .filter(|(_, bounds)| {
+                let center = (bounds.center - camera.position).extend(1.);
+                let in_frustum = || {
+                    planes
+                        .iter()
+                        .all(|plane| plane.dot(center) >= -(bounds.radius + slack))
+                };
+                let facing = || bounds.normal.dot(view_dir) >= -hemisphere_slack;
+                (!self.frustum_culling || in_frustum()) && (!self.hemisphere_culling || facing())
+            })
+            .flat_map(|(triangle, _)| triangle.iter().copied())
+            .collect::<Vec<_>>();
+        self.upload_indices(device, queue, visible);
+    }
+
+    fn upload_indices(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, indices: Vec<u32>) {
+        self.index_buffer.ensure_capacity(
+            device,
+            indices.len(),
+            wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        );
+        queue.write_typed_buffer(&self.index_buffer, 0, &indices);
+    }
+
+    /// Uploads the colors of the regions after the planet data changed. Only the small region
+    /// color buffer is written when the vertex shader reads it, otherwise the whole mesh.
+    pub fn recolor(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        match &self.region_colors {
+            Some(region_colors) => {
+                let colors = self
+                    .data
+                    .region_colors(&self.elevation_params, self.coloring);
+                queue.write_typed_buffer(region_colors, 0, &padded(&colors));
+                // the points carry their color in the vertices either way
+                self.upload_points(device, queue);
+            }
+            None => self.upload_mesh(device, queue),
+        }
+    }
+
+    /// Cycles through solid, wireframe and point rendering. The wireframe is skipped without
+    /// POLYGON_MODE_LINE.
+    pub fn toggle_render_mode(&mut self) {
+        self.render_mode = match self.render_mode {
+            RenderMode::Fill if self.line_render_pipeline.is_some() => RenderMode::Wireframe,
+            RenderMode::Fill | RenderMode::Wireframe => RenderMode::Points,
+            RenderMode::Points => RenderMode::Fill,
+        };
+        log::info!("Render mode {:?}", self.render_mode);
+    }
+
+    /// Whether the frame starts with the depth prepass. The main pass then keeps its depth, the
+    /// points and the wireframe are drawn without it.
+    pub fn depth_prepass(&self) -> bool {
+        self.depth_prepass
+            && match self.render_mode {
+                RenderMode::Fill => true,
+                RenderMode::Wireframe => self.line_render_pipeline.is_none(),
+                RenderMode::Points => false,
+            }
+    }
+
+    /// Switches the depth prepass on or off, rebuilding the pipelines of the filled planet for
+    /// the depth test, and returns whether it is on now. Both stay as they were if the pipelines
+    /// can't be rebuilt.
+    pub fn toggle_depth_prepass(&mut self, device: &wgpu::Device) -> Result<bool> {
+        self.depth_prepass = !self.depth_prepass;
+        if let Err(err) = self.reload_shader(device) {
+            self.depth_prepass = !self.depth_prepass;
+            return Err(err);
+        }
+        Ok(self.depth_prepass)
+    }
+
+    /// Wraps the equirectangular PNG or JPEG image at `path` around the planet, drawn instead
+    /// of the region colors
+    pub fn with_texture(
+        mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("can't load texture {}", path.display()))?
+            .into_rgba8();
+        self.texture = image_texture(device, queue, image);
+        self.bind_group = bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.camera_uniform,
+            self.region_colors.as_ref(),
+            &self.texture,
+            &self.sampler,
+        );
+        self.show_texture = true;
+        Ok(self)
+    }
+
+    /// Cycles through the classification, plate, elevation and seafloor age coloring
+    pub fn toggle_coloring(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.coloring = match self.coloring {
+            Coloring::Classification => Coloring::Plate,
+            Coloring::Plate => Coloring::Elevation,
+            Coloring::Elevation => Coloring::SeafloorAge,
+            Coloring::SeafloorAge => Coloring::Classification,
+        };
+        log::info!("Coloring {:?}", self.coloring);
+        self.recolor(device, queue);
+    }
+
+    /// Floods and drains the regions for the water surface at `sea_level` and rebuilds the mesh
+    pub fn set_sea_level(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, sea_level: f64) {
+        self.data.set_sea_level(sea_level, &self.elevation_params);
+        self.upload_mesh(device, queue);
+        if self.region_colors.is_some() {
+            self.recolor(device, queue);
+        }
+    }
+
+    /// Switches between flat and smooth normals and rebuilds the mesh
+    pub fn toggle_shading(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.shading = match self.shading {
+            Shading::Flat => Shading::Smooth,
+            Shading::Smooth => Shading::Flat,
+        };
+        self.upload_mesh(device, queue);
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_buffer.len
+    }
+
+    /// Number of triangles that are drawn, after culling
+    pub fn triangle_count(&self) -> usize {
+        self.index_buffer.len / 3
+    }
+
+    fn instance_range(&self) -> std::ops::Range<u32> {
+        0..self.instance_buffer.len as u32
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        let (fill, textured, line, depth, shadow, overlay, boundary, point, graticule) =
+            setup::validated(device, || {
+                let shader = setup::shader(device, SHADER)?;
+                let layout = &self.render_pipeline_layout;
+                Ok((
+                    render_pipeline(
+                        device,
+                        layout,
+                        &shader,
+                        self.vertex_entry_point,
+                        "fs_main",
+                        self.format,
+                        self.sample_count,
+                        wgpu::PolygonMode::Fill,
+                        fill_depth_stencil_state(self.depth_prepass),
+                    ),
+                    render_pipeline(
+                        device,
+                        layout,
+                        &shader,
+                        self.vertex_entry_point,
+                        "fs_textured",
+                        self.format,
+                        self.sample_count,
+                        wgpu::PolygonMode::Fill,
+                        fill_depth_stencil_state(self.depth_prepass),
+                    ),
+                    line_render_pipeline(
+                        device,
+                        layout,
+                        &shader,
+                        self.vertex_entry_point,
+                        self.format,
+                        self.sample_count,
+                    ),
+                    depth_pipeline(
+                        device,
+                        layout,
+                        &shader,
+                        self.vertex_entry_point,
+                        self.sample_count,
+                    ),
+                    shadow_pipeline(device, &self.shadow_pipeline_layout, &shader),
+                    overlay_pipeline(
+                        device,
+                        layout,
+                        &shader,
+                        self.format,
+                        self.sample_count,
+                        wgpu::PrimitiveTopology::LineList,
+                    ),
+                    overlay_pipeline(
+                        device,
+                        layout,
+                        &shader,
+                        self.format,
+                        self.sample_count,
+                        wgpu::PrimitiveTopology::TriangleList,
+                    ),
+                    point_pipeline(device, layout, &shader, self.format, self.sample_count),
+                    graticule_pipeline(device, layout, &shader, self.format, self.sample_count),
+                ))
+            })?;
+        self.render_pipeline = fill;
+        self.textured_pipeline = textured;
+        self.line_render_pipeline = line;
+        self.depth_pipeline = depth;
+        self.shadow_pipeline = shadow;
+        self.overlay_pipeline = overlay;
+        self.boundary_pipeline = boundary;
+        self.point_pipeline = point;
+        self.graticule_pipeline = graticule;
+        Ok(())
+    }
+
+    /// Recompiles the compute shader moving the plates, see `TectonicSim::reload_shader`
+    pub fn reload_tectonics_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.tectonic_sim.reload_shader(device)
+    }
 }
 
 pub fn render(render_pass: &mut wgpu::RenderPass, planet: &Planet) {
-    render_pass.set_pipeline(&planet.render_pipeline);
     render_pass.set_bind_group(0, &planet.bind_group, &[]);
+    render_pass.set_bind_group(1, &planet.shadow_bind_group, &[]);
+    render_pass.set_typed_vertex_buffer(1, &planet.instance_buffer);
+    if planet.render_mode == RenderMode::Points {
+        render_pass.set_pipeline(&planet.point_pipeline);
+        render_pass.set_typed_vertex_buffer(0, &planet.point_vertex_buffer);
+        render_pass.draw(
+            0..planet.point_vertex_buffer.len as _,
+            planet.instance_range(),
+        );
+    } else {
+        let pipeline = match (planet.render_mode, &planet.line_render_pipeline) {
+            (RenderMode::Wireframe, Some(line_render_pipeline)) => line_render_pipeline,
+            _ if planet.show_texture => &planet.textured_pipeline,
+            _ => &planet.render_pipeline,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_typed_vertex_buffer(0, &planet.vertex_buffer);
+        render_pass.set_typed_index_buffer(&planet.index_buffer);
+        render_pass.draw_indexed(0..planet.index_buffer.len as _, 0, planet.instance_range());
+    }
+
+    if planet.wireframe_overlay {
+        render_pass.set_pipeline(&planet.overlay_pipeline);
+        render_pass.set_typed_vertex_buffer(0, &planet.line_vertex_buffer);
+        render_pass.set_typed_index_buffer(&planet.line_index_buffer);
+        render_pass.draw_indexed(
+            0..planet.line_index_buffer.len as _,
+            0,
+            planet.instance_range(),
+        );
+    }
+    if planet.show_boundaries {
+        render_pass.set_pipeline(&planet.boundary_pipeline);
+        render_pass.set_typed_vertex_buffer(0, &planet.boundary_vertex_buffer);
+        render_pass.set_typed_index_buffer(&planet.boundary_index_buffer);
+        render_pass.draw_indexed(
+            0..planet.boundary_index_buffer.len as _,
+            0,
+            planet.instance_range(),
+        );
+    }
+    if planet.show_motion_arrows {
+        render_pass.set_pipeline(&planet.boundary_pipeline);
+        render_pass.set_typed_vertex_buffer(0, &planet.arrow_vertex_buffer);
+        render_pass.set_typed_index_buffer(&planet.arrow_index_buffer);
+        render_pass.draw_indexed(
+            0..planet.arrow_index_buffer.len as _,
+            0,
+            planet.instance_range(),
+        );
+    }
+    if planet.show_graticule {
+        render_pass.set_pipeline(&planet.graticule_pipeline);
+        render_pass.set_typed_vertex_buffer(0, &planet.graticule_buffer);
+        render_pass.draw(0..planet.graticule_buffer.len as _, planet.instance_range());
+    }
+    if planet.measure_index_buffer.len > 0 {
+        render_pass.set_pipeline(&planet.overlay_pipeline);
+        render_pass.set_typed_vertex_buffer(0, &planet.measure_vertex_buffer);
+        render_pass.set_typed_index_buffer(&planet.measure_index_buffer);
+        render_pass.draw_indexed(
+            0..planet.measure_index_buffer.len as _,
+            0,
+            planet.instance_range(),
+        );
+    }
+}
+
+/// Draws only the depth of the planet, for a pass without color attachments
+pub fn render_depth(render_pass: &mut wgpu::RenderPass, planet: &Planet) {
+    render_pass.set_pipeline(&planet.depth_pipeline);
+    render_pass.set_bind_group(0, &planet.bind_group, &[]);
+    render_pass.set_bind_group(1, &planet.shadow_bind_group, &[]);
+    render_pass.set_typed_vertex_buffer(0, &planet.vertex_buffer);
+    render_pass.set_typed_vertex_buffer(1, &planet.instance_buffer);
+    render_pass.set_typed_index_buffer(&planet.index_buffer);
+    render_pass.draw_indexed(0..planet.index_buffer.len as _, 0, planet.instance_range());
+}
+
+/// Draws the depth of the planet seen from the sun, in the pass into the shadow map. Only the
+/// triangles left by culling cast shadows.
+pub fn render_shadow(render_pass: &mut wgpu::RenderPass, planet: &Planet) {
+    render_pass.set_pipeline(&planet.shadow_pipeline);
+    render_pass.set_bind_group(0, &planet.bind_group, &[]);
+    render_pass.set_bind_group(1, &planet.light_bind_group, &[]);
     render_pass.set_typed_vertex_buffer(0, &planet.vertex_buffer);
+    render_pass.set_typed_vertex_buffer(1, &planet.instance_buffer);
     render_pass.set_typed_index_buffer(&planet.index_buffer);
-    render_pass.draw_indexed(0..planet.index_buffer.len as _, 0, 0..1);
+    render_pass.draw_indexed(0..planet.index_buffer.len as _, 0, planet.instance_range());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_normals_of_a_sphere_point_outwards() {
+        let generation_params = GenerationParams {
+            subdivisions: 3,
+            ..Default::default()
+        };
+        let elevation_params = ElevationParams::default();
+        let mut data = PlanetData::generate(0, &generation_params, &elevation_params);
+        // without elevation the surface is the sphere of the regions
+        data.elevation.fill(0.);
+        data.sea_level = 0.;
+        let (vertices, _) = data.mesh(&elevation_params, Shading::Smooth, Coloring::default());
+        for vertex in vertices {
+            let radial = DVec3::from(vertex.position).normalize().as_vec3();
+            // the averaged face normals are only radial where the triangles around the vertex
+            // are symmetric, the faces themselves are tilted by several degrees
+            assert!(
+                vertex.normal.angle_between(radial) < 1f32.to_radians(),
+                "{} is not {radial}",
+                vertex.normal
+            );
+        }
+    }
 }
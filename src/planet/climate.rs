@@ -0,0 +1,62 @@
+use super::regions::{self, Region};
+use crate::utils::*;
+use crate::RADIUS;
+
+#[derive(Clone, Copy)]
+pub struct ClimateParams {
+    /// temperature at sea level on the equator, in degrees Celsius
+    pub equator_temperature: f64,
+    /// temperature at sea level on the poles
+    pub pole_temperature: f64,
+    /// temperature drop per planet radius of elevation
+    pub lapse_rate: f64,
+    /// latitude in radians above which the land is always frozen
+    pub ice_cap_latitude: f64,
+}
+
+impl Default for ClimateParams {
+    fn default() -> Self {
+        ClimateParams {
+            equator_temperature: 30.,
+            pole_temperature: -25.,
+            lapse_rate: 1000. / RADIUS,
+            ice_cap_latitude: 75f64.to_radians(),
+        }
+    }
+}
+
+/// Temperature below which land is covered in ice
+const FREEZING: f64 = -10.;
+
+/// Temperature of every region, falling from the equator to the poles and with elevation
+pub fn temperature(regions: &[Region], elevation: &[f64], params: &ClimateParams) -> Vec<f64> {
+    regions
+        .iter()
+        .zip(elevation)
+        .map(|(region, &elevation)| {
+            let latitude = regions::latitude(region).abs();
+            let sea_level = params.equator_temperature
+                + (params.pole_temperature - params.equator_temperature) * latitude.sin();
+            let temperature = sea_level - params.lapse_rate * elevation.max(0.);
+            if latitude > params.ice_cap_latitude {
+                temperature.min(FREEZING)
+            } else {
+                temperature
+            }
+        })
+        .collect()
+}
+
+/// Color of land by its climate
+pub fn biome_color(temperature: f64, elevation: f64) -> Vec3 {
+    match temperature {
+        t if t <= FREEZING => vec3(0.95, 0.97, 1.),
+        // tundra
+        t if t < 0. => vec3(0.55, 0.6, 0.5),
+        // forest, thinning out higher up
+        t if t < 22. && elevation > 0. => vec3(0.1, 0.45, 0.1),
+        t if t < 22. => vec3(0.05, 0.4, 0.05),
+        // desert
+        _ => vec3(0.85, 0.75, 0.45),
+    }
+}
@@ -0,0 +1,201 @@
+use super::climate::biome_color;
+use super::regions::{Adjacency, Region};
+use super::tectonic_plates::{
+    BoundaryType, PlateBoundary, TectonicPlate, TectonicPlateClassification,
+};
+use crate::utils::*;
+use crate::RADIUS;
+use std::collections::VecDeque;
+
+const LOW_COLOR: Vec3 = vec3(0.0, 0.0, 0.1);
+const HIGH_COLOR: Vec3 = vec3(1.0, 1.0, 1.0);
+/// fraction of the peak height an oceanic mountain has to rise above the sea to be an island
+const ISLAND_HEIGHT: f64 = 0.5;
+
+#[derive(Clone, Copy)]
+pub struct ElevationParams {
+    /// height of the mountains right at a convergent boundary
+    pub peak_height: f64,
+    /// depth of the rift valleys right at a divergent boundary
+    pub rift_depth: f64,
+    /// distance from the boundary over which mountains and rifts taper off to zero
+    pub taper_width: f64,
+    /// number of regions from the nearest continent over which the ocean goes from the
+    /// shallow shelf to the abyss
+    pub shelf_width: usize,
+    /// elevation of the water surface, continental regions below it are flooded
+    pub sea_level: f64,
+}
+
+impl Default for ElevationParams {
+    fn default() -> Self {
+        ElevationParams {
+            peak_height: 0.02 * RADIUS,
+            rift_depth: 0.01 * RADIUS,
+            taper_width: 0.1 * RADIUS,
+            shelf_width: 6,
+            sea_level: -0.002 * RADIUS,
+        }
+    }
+}
+
+/// Smooth falloff from 1 at the boundary to 0 at `width`
+fn taper(distance: f64, width: f64) -> f64 {
+    let x = (1. - distance / width).clamp(0., 1.);
+    x * x * (3. - 2. * x)
+}
+
+/// Great-circle distance from every region to the closest boundary of the given kind. The
+/// closest boundary is found with a breadth first search outwards from the boundaries, regions
+/// that can't be reached are infinitely far away.
+fn boundary_distance(
+    regions: &[Region],
+    adjacency: &Adjacency,
+    boundaries: &[PlateBoundary],
+    kind: BoundaryType,
+) -> Vec<f64> {
+    let mut nearest: Vec<Option<DVec3>> = vec![None; regions.len()];
+    let mut queue = VecDeque::new();
+
+    for boundary in boundaries.iter().filter(|b| b.kind == kind) {
+        let [a, b] = boundary.regions;
+        let point = (regions[a].centroid() + regions[b].centroid()).normalize();
+        for region_index in boundary.regions {
+            if nearest[region_index].is_none() {
+                nearest[region_index] = Some(point);
+                queue.push_back(region_index);
+            }
+        }
+    }
+
+    while let Some(region_index) = queue.pop_front() {
+        let point = nearest[region_index];
+        for neighbor in adjacency.neighbors(region_index) {
+            if nearest[neighbor].is_none() {
+                nearest[neighbor] = point;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    regions
+        .iter()
+        .zip(nearest)
+        .map(|(region, point)| {
+            point.map_or(f64::INFINITY, |p| {
+                region.centroid().angle_between(p) * RADIUS
+            })
+        })
+        .collect()
+}
+
+/// Elevation of every region: mountain belts along convergent boundaries and rift valleys
+/// along divergent boundaries
+pub fn tectonic_elevation(
+    regions: &[Region],
+    adjacency: &Adjacency,
+    boundaries: &[PlateBoundary],
+    params: &ElevationParams,
+) -> Vec<f64> {
+    let convergent = boundary_distance(regions, adjacency, boundaries, BoundaryType::Convergent);
+    let divergent = boundary_distance(regions, adjacency, boundaries, BoundaryType::Divergent);
+
+    convergent
+        .iter()
+        .zip(&divergent)
+        .map(|(&c, &d)| {
+            params.peak_height * taper(c, params.taper_width)
+                - params.rift_depth * taper(d, params.taper_width)
+        })
+        .collect()
+}
+
+/// Depth of the ocean in every region, from 0 next to a continent to 1 at `shelf_width` or
+/// more regions away. The distance is counted in region hops with a breadth first search
+/// outwards from the continental regions, continental regions themselves have a depth of 0.
+pub fn ocean_depth(
+    regions: &[Region],
+    adjacency: &Adjacency,
+    plates: &[TectonicPlate],
+    params: &ElevationParams,
+) -> Vec<f64> {
+    let mut hops: Vec<Option<usize>> = vec![None; regions.len()];
+    let mut queue = VecDeque::new();
+
+    for plate in plates
+        .iter()
+        .filter(|p| p.classification == TectonicPlateClassification::Continental)
+    {
+        for &region_index in &plate.contained_regions {
+            hops[region_index] = Some(0);
+            queue.push_back(region_index);
+        }
+    }
+
+    while let Some(region_index) = queue.pop_front() {
+        let distance = hops[region_index].map(|h| h + 1);
+        for neighbor in adjacency.neighbors(region_index) {
+            if hops[neighbor].is_none() {
+                hops[neighbor] = distance;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    hops.into_iter()
+        .map(|h| {
+            h.map_or(1., |h| {
+                (h as f64 / params.shelf_width.max(1) as f64).min(1.)
+            })
+        })
+        .collect()
+}
+
+/// Whether a region is under water. Continental regions are below the sea level, oceanic
+/// regions everywhere except on the mountains rising high enough above the sea to be islands.
+pub fn is_ocean(
+    classification: TectonicPlateClassification,
+    elevation: f64,
+    params: &ElevationParams,
+) -> bool {
+    let height = elevation - params.sea_level;
+    match classification {
+        TectonicPlateClassification::Continental => height < 0.,
+        TectonicPlateClassification::Oceanic => height < ISLAND_HEIGHT * params.peak_height,
+    }
+}
+
+/// Plain gradient from the deepest rifts to the highest peaks, ignoring the classification
+/// and climate
+pub fn elevation_color(elevation: f64, params: &ElevationParams) -> Vec3 {
+    let t = ((elevation + params.rift_depth) / (params.peak_height + params.rift_depth)) as f32;
+    LOW_COLOR.lerp(HIGH_COLOR, t.clamp(0., 1.))
+}
+
+/// Color of a region by its plate classification and elevation band. Oceans, see `is_ocean`,
+/// go from shelf blue to abyssal blue with `ocean_depth`, lowlands are colored by their climate.
+pub fn band_color(
+    classification: TectonicPlateClassification,
+    ocean: bool,
+    elevation: f64,
+    ocean_depth: f64,
+    temperature: f64,
+    params: &ElevationParams,
+) -> Vec3 {
+    let height = elevation / params.peak_height;
+    let depth = -elevation / params.rift_depth;
+    if ocean {
+        let color = vec3(0.1, 0.5, 1.).lerp(vec3(0., 0., 0.35), ocean_depth as f32);
+        return if depth > 0.5 { color * 0.6 } else { color };
+    }
+    match classification {
+        TectonicPlateClassification::Continental if height > 0.75 => vec3(1., 1., 1.),
+        TectonicPlateClassification::Continental if height > 0.3 => vec3(0.5, 0.4, 0.3),
+        TectonicPlateClassification::Continental if depth > 0.5 => {
+            biome_color(temperature, elevation) * 0.7
+        }
+        TectonicPlateClassification::Continental => biome_color(temperature, elevation),
+        // islands
+        TectonicPlateClassification::Oceanic => vec3(0.5, 0.4, 0.3),
+    }
+}
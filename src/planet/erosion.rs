@@ -0,0 +1,121 @@
+use super::regions::{Adjacency, Region};
+use crate::utils::*;
+use rand::Rng;
+
+#[derive(Clone, Copy)]
+pub struct ErosionParams {
+    /// fraction of the missing sediment a droplet picks up from a region per step
+    pub erosion_rate: f64,
+    /// fraction of the excess sediment a droplet drops per step
+    pub deposition_rate: f64,
+    /// how much a droplet keeps its direction instead of following the steepest descent,
+    /// from 0 to 1
+    pub inertia: f64,
+    /// sediment a droplet can carry per unit of elevation it drops in a step
+    pub capacity: f64,
+    /// fraction of the water evaporating per step
+    pub evaporation: f64,
+    /// steps after which a droplet is dropped, even if it still flows
+    pub max_steps: usize,
+}
+
+impl Default for ErosionParams {
+    fn default() -> Self {
+        ErosionParams {
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            inertia: 0.3,
+            capacity: 4.,
+            evaporation: 0.02,
+            max_steps: 64,
+        }
+    }
+}
+
+/// Water left in a droplet below which it has evaporated
+const MIN_WATER: f64 = 0.01;
+
+/// Simulates `iterations` droplets of rain on random land regions above sea level. Every
+/// droplet flows downhill over the neighbors, eroding while it speeds down slopes and
+/// depositing sediment where it slows down, until it reaches the ocean, gets stuck in a pit or
+/// evaporates. The same `rng` state erodes the same way.
+pub fn erode(
+    rng: &mut impl Rng,
+    regions: &[Region],
+    adjacency: &Adjacency,
+    elevation: &mut [f64],
+    ocean: &[bool],
+    iterations: usize,
+    params: &ErosionParams,
+) {
+    let sources = (0..regions.len())
+        .filter(|&r| !ocean[r] && elevation[r] > 0.)
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        return;
+    }
+    let centroids = regions.iter().map(Region::centroid).collect::<Vec<_>>();
+
+    for _ in 0..iterations {
+        let mut current = sources[rng.random_range(0..sources.len())];
+        // direction of the last step, tangent to the sphere
+        let mut direction = DVec3::ZERO;
+        let mut water = 1.;
+        let mut sediment = 0.;
+
+        for _ in 0..params.max_steps {
+            let center = centroids[current];
+            let neighbors = adjacency.neighbors(current);
+            let tangents = neighbors.map(|n| {
+                (centroids[n] - center)
+                    .reject_from(center)
+                    .normalize_or_zero()
+            });
+            let descent = neighbors
+                .iter()
+                .zip(tangents)
+                .map(|(&n, tangent)| {
+                    let slope =
+                        (elevation[current] - elevation[n]) / center.angle_between(centroids[n]);
+                    tangent * slope.max(0.)
+                })
+                .sum::<DVec3>()
+                .normalize_or_zero();
+            direction = (direction.reject_from(center) * params.inertia
+                + descent * (1. - params.inertia))
+                .normalize_or_zero();
+            let next = neighbors
+                .into_iter()
+                .zip(tangents)
+                .max_by(|(_, a), (_, b)| a.dot(direction).total_cmp(&b.dot(direction)))
+                .map(|(n, _)| n)
+                .expect("regions have three neighbors");
+
+            let drop = elevation[current] - elevation[next];
+            if drop <= 0. {
+                // stuck in a pit, the sediment fills it up below
+                break;
+            }
+
+            let capacity = drop * water * params.capacity;
+            if sediment > capacity {
+                let deposit = (sediment - capacity) * params.deposition_rate;
+                elevation[current] += deposit;
+                sediment -= deposit;
+            } else {
+                // never dig below the next region, that would leave a pit behind
+                let eroded = ((capacity - sediment) * params.erosion_rate).min(drop);
+                elevation[current] -= eroded;
+                sediment += eroded;
+            }
+
+            current = next;
+            water *= 1. - params.evaporation;
+            if ocean[current] || water < MIN_WATER {
+                break;
+            }
+        }
+        // whatever the droplet still carries settles where it ended, in a pit or a river delta
+        elevation[current] += sediment;
+    }
+}
@@ -0,0 +1,238 @@
+use super::tectonic_plates::TectonicPlateClassification;
+use super::{deduplicate, Coloring, ElevationParams, GenerationParams, PlanetData};
+use crate::utils::*;
+use anyhow::Result;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Writes the planet mesh as a Wavefront OBJ file. Vertices shared between triangles with the
+/// same color are merged, the colors are written with the common `v x y z r g b` extension.
+pub fn export_obj(path: &Path, seed: u64, generation_params: &GenerationParams) -> Result<()> {
+    let elevation_params = ElevationParams::default();
+    let data = PlanetData::generate(seed, generation_params, &elevation_params);
+    let (vertices, indices) = deduplicate(
+        data.plates
+            .iter()
+            .flat_map(|plate| data.plate_vertices(plate, &elevation_params, Coloring::default())),
+    );
+
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "# planet-placer")?;
+    for vertex in vertices {
+        let position = DVec3::from(vertex.position);
+        let color = vertex.color();
+        writeln!(
+            file,
+            "v {} {} {} {} {} {}",
+            position.x, position.y, position.z, color.x, color.y, color.z
+        )?;
+    }
+    // OBJ indices start at 1
+    for face in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| face[i] + 1);
+        writeln!(file, "f {a} {b} {c}")?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes the planet mesh as a binary glTF 2.0 file (`.glb`). The mesh has one primitive per
+/// plate classification, each with its own material and flat per-face normals.
+pub fn export_gltf(path: &Path, seed: u64, generation_params: &GenerationParams) -> Result<()> {
+    const ARRAY_BUFFER: u32 = 34962;
+    const FLOAT: u32 = 5126;
+    const TRIANGLES: u32 = 4;
+
+    let elevation_params = ElevationParams::default();
+    let data = PlanetData::generate(seed, generation_params, &elevation_params);
+
+    let materials = [
+        (
+            TectonicPlateClassification::Continental,
+            "continental",
+            [0., 1., 0.],
+        ),
+        (
+            TectonicPlateClassification::Oceanic,
+            "oceanic",
+            [0., 0., 1.],
+        ),
+    ];
+
+    let mut bin = Vec::<u8>::new();
+    let mut buffer_views = vec![];
+    let mut accessors = vec![];
+    let mut primitives = vec![];
+
+    for (material, (classification, _, _)) in materials.iter().enumerate() {
+        let vertices = data
+            .plates
+            .iter()
+            .filter(|plate| plate.classification == *classification)
+            .flat_map(|plate| data.plate_vertices(plate, &elevation_params, Coloring::default()))
+            .collect::<Vec<_>>();
+        // accessors can't be empty, so skip classifications without any regions
+        if vertices.is_empty() {
+            continue;
+        }
+
+        let positions = vertices
+            .iter()
+            .map(|vertex| DVec3::from(vertex.position).as_vec3())
+            .collect::<Vec<_>>();
+        let normals = vertices
+            .iter()
+            .map(|vertex| vertex.normal)
+            .collect::<Vec<_>>();
+        let min = positions.iter().copied().fold(Vec3::MAX, Vec3::min);
+        let max = positions.iter().copied().fold(Vec3::MIN, Vec3::max);
+
+        let mut attribute = |values: &[Vec3], bounds: String| {
+            let offset = bin.len();
+            for value in values {
+                for component in value.to_array() {
+                    bin.extend_from_slice(&component.to_le_bytes());
+                }
+            }
+            buffer_views.push(format!(
+                r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{},"target":{ARRAY_BUFFER}}}"#,
+                bin.len() - offset
+            ));
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":{FLOAT},"count":{},"type":"VEC3"{bounds}}}"#,
+                buffer_views.len() - 1,
+                values.len()
+            ));
+            accessors.len() - 1
+        };
+        let position = attribute(
+            &positions,
+            format!(
+                r#","min":[{},{},{}],"max":[{},{},{}]"#,
+                min.x, min.y, min.z, max.x, max.y, max.z
+            ),
+        );
+        let normal = attribute(&normals, String::new());
+        primitives.push(format!(
+            r#"{{"attributes":{{"POSITION":{position},"NORMAL":{normal}}},"material":{material},"mode":{TRIANGLES}}}"#
+        ));
+    }
+
+    let materials = materials
+        .iter()
+        .map(|(_, name, [r, g, b])| {
+            format!(
+                r#"{{"name":"{name}","pbrMetallicRoughness":{{"baseColorFactor":[{r},{g},{b},1],"metallicFactor":0,"roughnessFactor":1}}}}"#
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut json = format!(
+        r#"{{"asset":{{"version":"2.0","generator":"planet-placer"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0,"name":"planet"}}],"meshes":[{{"primitives":[{}]}}],"materials":[{}],"buffers":[{{"byteLength":{}}}],"bufferViews":[{}],"accessors":[{}]}}"#,
+        primitives.join(","),
+        materials.join(","),
+        bin.len(),
+        buffer_views.join(","),
+        accessors.join(","),
+    )
+    .into_bytes();
+
+    // chunks must be 4 byte aligned, json is padded with spaces and the binary chunk with zeros
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let total_length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"glTF")?;
+    file.write_all(&2u32.to_le_bytes())?;
+    file.write_all(&(total_length as u32).to_le_bytes())?;
+    file.write_all(&(json.len() as u32).to_le_bytes())?;
+    file.write_all(b"JSON")?;
+    file.write_all(&json)?;
+    file.write_all(&(bin.len() as u32).to_le_bytes())?;
+    file.write_all(b"BIN\0")?;
+    file.write_all(&bin)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Samples per pixel along each axis of the heightmap. Towards the poles a row of pixels covers
+/// only a few regions, without supersampling their edges would be long jagged steps.
+const HEIGHTMAP_SUPERSAMPLING: u32 = 3;
+
+/// Writes the elevation of the regions as an equirectangular 16 bit grayscale PNG, with the
+/// same layout as `regions::assign_uv`. The lowest region is black and the highest white.
+pub fn export_heightmap(path: &Path, data: &PlanetData, width: u32, height: u32) -> Result<()> {
+    use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+    let (min, max) = data
+        .elevation
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), &e| {
+            (min.min(e), max.max(e))
+        });
+    let range = (max - min).max(f64::EPSILON);
+
+    let samples = HEIGHTMAP_SUPERSAMPLING;
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.;
+            for sy in 0..samples {
+                for sx in 0..samples {
+                    let u = (x as f64 + (sx as f64 + 0.5) / samples as f64) / width as f64;
+                    let v = (y as f64 + (sy as f64 + 0.5) / samples as f64) / height as f64;
+                    let (longitude, latitude) = (u * TAU - PI, FRAC_PI_2 - v * PI);
+                    let (sin_lon, cos_lon) = longitude.sin_cos();
+                    let (sin_lat, cos_lat) = latitude.sin_cos();
+                    let dir = dvec3(cos_lat * cos_lon, cos_lat * sin_lon, sin_lat);
+                    sum += (data.elevation[data.index.locate(dir)] - min) / range;
+                }
+            }
+            let value = sum / (samples * samples) as f64;
+            pixels.push((value * u16::MAX as f64).round() as u16);
+        }
+    }
+
+    image::ImageBuffer::<image::Luma<u16>, _>::from_raw(width, height, pixels)
+        .expect("one pixel per sample")
+        .save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_round_trip_keeps_the_triangles() {
+        let generation_params = GenerationParams {
+            subdivisions: 2,
+            num_plates: 8,
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("planet-{}.obj", std::process::id()));
+        export_obj(&path, 0, &generation_params).unwrap();
+        let obj = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let vertices = obj.lines().filter(|line| line.starts_with("v ")).count();
+        let faces = obj
+            .lines()
+            .filter_map(|line| line.strip_prefix("f "))
+            .map(|face| {
+                face.split_whitespace()
+                    .map(|index| index.parse::<usize>().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(faces.len(), 20 * 4usize.pow(2));
+        for face in faces {
+            assert_eq!(face.len(), 3);
+            assert!(face.iter().all(|&index| (1..=vertices).contains(&index)));
+        }
+        // vertices shared by triangles of the same color are merged
+        assert!(vertices < 20 * 4usize.pow(2) * 3);
+    }
+}
@@ -0,0 +1,130 @@
+use super::regions::Region;
+use crate::utils::*;
+use crate::RADIUS;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+
+#[derive(Clone, Copy)]
+pub struct NoiseParams {
+    /// largest height of the relief above or below the tectonic elevation, 0 turns it off
+    pub height: f64,
+    /// noise features per unit of distance in the first octave
+    pub frequency: f64,
+    /// layers of noise, each finer than the one before
+    pub octaves: usize,
+    /// factor the frequency grows by from one octave to the next
+    pub lacunarity: f64,
+    /// factor the amplitude shrinks by from one octave to the next
+    pub persistence: f64,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        NoiseParams {
+            height: 0.006 * RADIUS,
+            frequency: 2. / RADIUS,
+            octaves: 5,
+            lacunarity: 2.,
+            persistence: 0.5,
+        }
+    }
+}
+
+/// Gradient noise in 3D, the improved noise of Ken Perlin with a permutation shuffled by the
+/// seed. It is 0 at integer coordinates and stays roughly between -1 and 1.
+struct Perlin {
+    /// permutation of 0..256, repeated so the hashes of a cell corner can't run past the end
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let mut rng = Pcg32::seed_from_u64(seed);
+        let mut shuffled: [u8; 256] = std::array::from_fn(|i| i as u8);
+        shuffled.shuffle(&mut rng);
+        Perlin {
+            permutation: std::array::from_fn(|i| shuffled[i % 256]),
+        }
+    }
+
+    fn hash(&self, x: usize, y: usize, z: usize) -> u8 {
+        let p = &self.permutation;
+        p[p[p[x] as usize + y] as usize + z]
+    }
+
+    fn sample(&self, point: DVec3) -> f64 {
+        let cell = point.floor();
+        let [x, y, z] = (cell.as_i64vec3() & 255).to_array().map(|c| c as usize);
+        let local = point - cell;
+        let fade = local * local * local * (local * (local * 6. - 15.) + 10.);
+
+        let corner = |dx: usize, dy: usize, dz: usize| {
+            let offset = dvec3(dx as f64, dy as f64, dz as f64);
+            gradient(self.hash(x + dx, y + dy, z + dz), local - offset)
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let y0 = lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), fade.x),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), fade.x),
+            fade.y,
+        );
+        let y1 = lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), fade.x),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), fade.x),
+            fade.y,
+        );
+        lerp(y0, y1, fade.z)
+    }
+}
+
+/// Dot product of `offset` with one of the 12 edge directions of a cube, picked by `hash`
+fn gradient(hash: u8, offset: DVec3) -> f64 {
+    let DVec3 { x, y, z } = offset;
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+/// Relief of every region from fractal noise sampled at its centroid, between `-height` and
+/// `height`. It is added to the tectonic elevation so the plates aren't flat between their
+/// boundaries. The same seed gives the same relief.
+pub fn base_elevation(regions: &[Region], seed: u64, params: &NoiseParams) -> Vec<f64> {
+    let perlin = Perlin::new(seed);
+    // the octaves sum up to at most 1 before they are scaled by the height
+    let total = (0..params.octaves)
+        .map(|octave| params.persistence.powi(octave as i32))
+        .sum::<f64>();
+    if total == 0. || params.height == 0. {
+        return vec![0.; regions.len()];
+    }
+
+    regions
+        .iter()
+        .map(|region| {
+            let point = region.centroid() * RADIUS;
+            let mut frequency = params.frequency;
+            let mut amplitude = 1.;
+            let mut sum = 0.;
+            for octave in 0..params.octaves {
+                // shifted per octave, otherwise the zeros at the cell corners line up
+                let offset = DVec3::splat(octave as f64 * 17.31);
+                sum += perlin.sample(point * frequency + offset) * amplitude;
+                frequency *= params.lacunarity;
+                amplitude *= params.persistence;
+            }
+            (sum / total).clamp(-1., 1.) * params.height
+        })
+        .collect()
+}
@@ -1,82 +1,69 @@
 use crate::utils::*;
+use crate::RADIUS;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-const PHI: f64 = 1.61803398875; // Golden ratio
-
-#[rustfmt::skip]
-const ICOS_VERTICES: &[DVec3] = &[
-    DVec3::new(-1.0,  PHI,  0.0),
-    DVec3::new( 1.0,  PHI,  0.0),
-    DVec3::new(-1.0, -PHI,  0.0),
-    DVec3::new( 1.0, -PHI,  0.0),
-    DVec3::new( 0.0, -1.0,  PHI),
-    DVec3::new( 0.0,  1.0,  PHI),
-    DVec3::new( 0.0, -1.0, -PHI),
-    DVec3::new( 0.0,  1.0, -PHI),
-    DVec3::new( PHI,  0.0, -1.0),
-    DVec3::new( PHI,  0.0,  1.0),
-    DVec3::new(-PHI,  0.0, -1.0),
-    DVec3::new(-PHI,  0.0,  1.0),
-];
-
-#[rustfmt::skip]
-const ICOS_INDICES: &[u16] = &[
-    0, 11, 5,  0, 5, 1,  0, 1, 7,  0, 7, 10,  0, 10, 11,
-    1, 5, 9,  5, 11, 4,  11, 10, 2,  10, 7, 6,  7, 1, 8,
-    3, 9, 4,  3, 4, 2,  3, 2, 6,  3, 6, 8,  3, 8, 9,
-    4, 9, 5,  2, 4, 11,  6, 2, 10,  8, 6, 7,  9, 8, 1,
-];
-
-fn subdivide(vertices: &mut Vec<DVec3>, indices: &mut Vec<u16>) {
-    let mut new_indices = Vec::new();
-    let mut midpoint_cache = std::collections::HashMap::new();
-
-    let midpoint = |a: u16,
-                    b: u16,
-                    vertices: &mut Vec<DVec3>,
-                    cache: &mut std::collections::HashMap<(u16, u16), u16>|
-     -> u16 {
-        let key = if a < b { (a, b) } else { (b, a) };
-        if let Some(&mid) = cache.get(&key) {
-            return mid;
-        }
-        let mid_pos = (vertices[a as usize] + vertices[b as usize]) * 0.5;
-        let mid_index = vertices.len() as u16;
-        vertices.push(mid_pos.normalize());
-        cache.insert(key, mid_index);
-        mid_index
-    };
-
-    for chunk in indices.chunks_exact(3) {
-        let m1 = midpoint(chunk[0], chunk[1], vertices, &mut midpoint_cache);
-        let m2 = midpoint(chunk[1], chunk[2], vertices, &mut midpoint_cache);
-        let m3 = midpoint(chunk[2], chunk[0], vertices, &mut midpoint_cache);
+mod gpu_subdivision;
+pub mod icosphere;
+mod validation;
+pub use validation::{fix_winding, validate, weld_vertices, WELD_DISTANCE};
 
-        new_indices.extend_from_slice(&[
-            chunk[0], m1, m3, m1, chunk[1], m2, m3, m2, chunk[2], m1, m2, m3,
-        ]);
-    }
-    *indices = new_indices;
+/// Key identifying the edge between two vertices, independent of their order
+fn edge_key(a: u32, b: u32) -> u64 {
+    ((a.min(b) as u64) << 32) | a.max(b) as u64
 }
 
 pub struct Region {
     pub corners: [DVec3; 3],
-    pub edges: [u32; 3],
+    pub edges: [u64; 3],
 }
 
 impl Region {
-    fn new(indices: &[u16], vertices: &[DVec3]) -> Self {
-        let a = indices[0] as usize;
-        let b = indices[1] as usize;
-        let c = indices[2] as usize;
-        let ab = ((a.min(b) << 16) | a.max(b)) as u32;
-        let bc = ((b.min(c) << 16) | b.max(c)) as u32;
-        let ca = ((c.min(a) << 16) | c.max(a)) as u32;
+    fn new(indices: &[u32], vertices: &[DVec3]) -> Self {
+        let [a, b, c] = [indices[0], indices[1], indices[2]];
         Region {
-            corners: [vertices[a], vertices[b], vertices[c]],
-            edges: [ab, bc, ca],
+            corners: [
+                vertices[a as usize],
+                vertices[b as usize],
+                vertices[c as usize],
+            ],
+            edges: [edge_key(a, b), edge_key(b, c), edge_key(c, a)],
+        }
+    }
+
+    /// Average of the corners, projected back onto the unit sphere
+    pub fn centroid(&self) -> DVec3 {
+        (self.corners[0] + self.corners[1] + self.corners[2]).normalize()
+    }
+
+    /// Area of the spherical triangle on the unit sphere, from its spherical excess
+    pub fn area(&self) -> f64 {
+        let [a, b, c] = self.corners.map(DVec3::normalize);
+        let triple = a.dot(b.cross(c)).abs();
+        2. * triple.atan2(1. + a.dot(b) + b.dot(c) + c.dot(a))
+    }
+
+    /// Unit normal of the flat triangle, pointing away from the center of the sphere
+    pub fn normal(&self) -> DVec3 {
+        let [a, b, c] = self.corners;
+        let normal = (b - a).cross(c - a).normalize();
+        if normal.dot(a + b + c) < 0. {
+            -normal
+        } else {
+            normal
         }
     }
 
+    /// Checks if the direction `point` goes through the region, i.e lies on the inner side of
+    /// all three great circles through its edges
+    pub fn contains(&self, point: DVec3) -> bool {
+        let [a, b, c] = self.corners;
+        // the sign of the winding, so the antipodal region doesn't match as well
+        let winding = a.cross(b).dot(c).signum();
+        [a.cross(b), b.cross(c), c.cross(a)]
+            .iter()
+            .all(|n| n.dot(point) * winding >= 0.)
+    }
+
     /// Checks if self borders other (returns true if self and other share an edge)
     pub fn borders(&self, other: &Region) -> bool {
         self.edges.iter().any(|&e| other.edges.contains(&e))
@@ -89,20 +76,368 @@ impl Region {
     }
 }
 
-pub fn create_regions(subdivisions: usize) -> Vec<Region> {
-    // create vertices by subdividing an icosahedron
-    let mut vertices = ICOS_VERTICES.to_owned();
-    let mut indices = ICOS_INDICES.to_owned();
-    for vertex in &mut vertices {
-        *vertex = vertex.normalize();
+/// Latitude of the centroid in radians, positive in the northern (+z) hemisphere
+pub fn latitude(region: &Region) -> f64 {
+    region.centroid().z.clamp(-1., 1.).asin()
+}
+
+/// Distance between two directions along the surface of the planet, ignoring the elevation
+pub fn arc_distance(a: DVec3, b: DVec3) -> f64 {
+    a.angle_between(b) * RADIUS
+}
+
+/// Distance between the centroids of two regions along the surface of the planet
+pub fn great_circle_distance(a: &Region, b: &Region) -> f64 {
+    arc_distance(a.centroid(), b.centroid())
+}
+
+/// Equirectangular texture coordinates of the corners, from their longitude and latitude.
+/// u goes from 0 at longitude -π to 1 at +π and v from 0 at the north pole to 1 at the south
+/// pole. The corners of a region crossing the seam at ±π are kept together by moving the ones
+/// west of the seam past u = 1, so textures have to repeat in u.
+pub fn assign_uv(region: &Region) -> [Vec2; 3] {
+    use std::f64::consts::{PI, TAU};
+
+    let mut uv = region.corners.map(|corner| {
+        let dir = corner.normalize();
+        let longitude = dir.y.atan2(dir.x);
+        let latitude = dir.z.clamp(-1., 1.).asin();
+        dvec2(longitude / TAU + 0.5, 0.5 - latitude / PI)
+    });
+    let [min, max] = [f64::min, f64::max].map(|f| uv.iter().map(|uv| uv.x).reduce(f).unwrap());
+    if max - min > 0.5 {
+        for uv in &mut uv {
+            if uv.x < 0.5 {
+                uv.x += 1.;
+            }
+        }
+    }
+    uv.map(|uv| uv.as_vec2())
+}
+
+/// For each region, the three regions sharing one of its edges. The neighbors are in the
+/// same order as `Region::edges`, so `neighbors(i)[k]` is on the other side of `edges[k]`.
+pub struct Adjacency {
+    neighbors: Vec<[usize; 3]>,
+}
+
+impl Adjacency {
+    pub fn new(regions: &[Region]) -> Self {
+        let mut edge_regions = HashMap::<u64, [usize; 2]>::with_capacity(regions.len() * 3 / 2);
+        for (index, region) in regions.iter().enumerate() {
+            for edge in region.edges {
+                edge_regions
+                    .entry(edge)
+                    .and_modify(|pair| pair[1] = index)
+                    .or_insert([index, usize::MAX]);
+            }
+        }
+
+        let neighbors = regions
+            .iter()
+            .enumerate()
+            .map(|(index, region)| {
+                region.edges.map(|edge| {
+                    let [a, b] = edge_regions[&edge];
+                    if a == index {
+                        b
+                    } else {
+                        a
+                    }
+                })
+            })
+            .collect();
+        Adjacency { neighbors }
+    }
+
+    pub fn neighbors(&self, region_index: usize) -> [usize; 3] {
+        self.neighbors[region_index]
     }
-    for _ in 0..subdivisions {
-        subdivide(&mut vertices, &mut indices);
+
+    /// All regions at most `radius` hops away from `center`, including `center` itself, in
+    /// order of increasing hop count
+    pub fn ring(&self, center: usize, radius: usize) -> Vec<usize> {
+        let mut ring = vec![center];
+        let mut visited = HashSet::from([center]);
+        let mut queue = VecDeque::from([(center, 0)]);
+        while let Some((region_index, hops)) = queue.pop_front() {
+            if hops == radius {
+                continue;
+            }
+            for neighbor in self.neighbors(region_index) {
+                if visited.insert(neighbor) {
+                    ring.push(neighbor);
+                    queue.push_back((neighbor, hops + 1));
+                }
+            }
+        }
+        ring
     }
-    // create the regions
-    let mut regions = vec![];
-    for i in 0..indices.len() / 3 {
-        regions.push(Region::new(&indices[3 * i..(3 * i + 3)], &vertices));
+}
+
+/// Extra fraction of a cell around the bounds of a region, for the bulge of its edges between
+/// the sampled points
+const CELL_MARGIN: f64 = 0.05;
+
+fn min_max<const N: usize>(values: [f64; N]) -> (f64, f64) {
+    values
+        .into_iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), v| {
+            (min.min(v), max.max(v))
+        })
+}
+
+/// Buckets the regions in a grid of latitude and longitude, so finding the region containing a
+/// direction only tests the few regions overlapping its cell
+pub struct RegionIndex {
+    rows: usize,
+    columns: usize,
+    /// regions overlapping every cell, row by row from the north pole
+    cells: Vec<Vec<usize>>,
+    /// normals of the great circles through the edges of every region, pointing inwards
+    edge_normals: Vec<[DVec3; 3]>,
+}
+
+impl RegionIndex {
+    pub fn new(regions: &[Region]) -> Self {
+        // a cell for every two regions, each cell overlaps about ten of them
+        let rows = ((regions.len() as f64 / 4.).sqrt().ceil() as usize).max(1);
+        let columns = 2 * rows;
+        let mut index = RegionIndex {
+            rows,
+            columns,
+            cells: vec![vec![]; rows * columns],
+            edge_normals: regions
+                .iter()
+                .map(|region| {
+                    let [a, b, c] = region.corners;
+                    let winding = a.cross(b).dot(c).signum();
+                    [a.cross(b), b.cross(c), c.cross(a)].map(|n| n.normalize() * winding)
+                })
+                .collect(),
+        };
+
+        for (region_index, region) in regions.iter().enumerate() {
+            let [a, b, c] = region.corners;
+            // the edges bulge towards the poles, their midpoints cover most of it
+            let points = [a, b, c, a + b, b + c, c + a].map(|p| index.grid_position(p.normalize()));
+            let mut columns = points.map(|point| point.y);
+            let (min, max) = min_max(columns);
+            // keep the corners of regions on the seam at ±π together
+            if max - min > index.columns as f64 / 2. {
+                for column in &mut columns {
+                    if *column < index.columns as f64 / 2. {
+                        *column += index.columns as f64;
+                    }
+                }
+            }
+            let (min_row, max_row) = min_max(points.map(|point| point.x));
+            let (min_column, max_column) = min_max(columns);
+            let mut rows = (min_row - CELL_MARGIN).max(0.) as usize
+                ..=((max_row + CELL_MARGIN) as usize).min(index.rows - 1);
+            let mut columns = (min_column - CELL_MARGIN + index.columns as f64) as usize
+                ..=(max_column + CELL_MARGIN + index.columns as f64) as usize;
+            // regions around a pole cover every longitude
+            for (pole, row) in [(DVec3::Z, 0), (DVec3::NEG_Z, index.rows - 1)] {
+                if index.inside(region_index, pole) >= 0. {
+                    rows = (*rows.start()).min(row)..=(*rows.end()).max(row);
+                    columns = 0..=index.columns - 1;
+                }
+            }
+            let columns =
+                *columns.start()..=(*columns.end()).min(columns.start() + index.columns - 1);
+            for row in rows {
+                for column in columns.clone() {
+                    index.cells[row * index.columns + column % index.columns].push(region_index);
+                }
+            }
+        }
+        index
+    }
+
+    /// Row and column of the unit vector `dir` in the grid, in fractions of cells
+    fn grid_position(&self, dir: DVec3) -> DVec2 {
+        use std::f64::consts::{PI, TAU};
+
+        let latitude = dir.z.clamp(-1., 1.).asin();
+        let longitude = dir.y.atan2(dir.x);
+        dvec2(
+            (0.5 - latitude / PI) * self.rows as f64,
+            (longitude / TAU + 0.5) * self.columns as f64,
+        )
+    }
+
+    /// Distance of `dir` to the closest edge of the region, negative outside of it
+    fn inside(&self, region_index: usize, dir: DVec3) -> f64 {
+        self.edge_normals[region_index]
+            .iter()
+            .map(|normal| normal.dot(dir))
+            .fold(f64::MAX, f64::min)
+    }
+
+    /// The region containing the direction `dir`
+    pub fn locate(&self, dir: DVec3) -> usize {
+        let dir = dir.normalize();
+        let position = self.grid_position(dir);
+        let row = (position.x as usize).min(self.rows - 1);
+        let column = (position.y as usize).min(self.columns - 1);
+        // a direction on an edge can be just outside of both regions due to rounding, so take
+        // the region it is the least outside of
+        *self.cells[row * self.columns + column]
+            .iter()
+            .max_by(|&&a, &&b| self.inside(a, dir).total_cmp(&self.inside(b, dir)))
+            .expect("every cell overlaps a region")
+    }
+}
+
+pub fn create_regions(subdivisions: usize) -> (Vec<Region>, Adjacency, RegionIndex) {
+    let (vertices, indices) = icosphere::subdivided(subdivisions);
+    repaired_regions(vertices, indices)
+}
+
+/// Same as `create_regions`, but subdivides the icosahedron with a compute shader. The regions
+/// are in the same order, their corners are only accurate to f32 precision.
+pub fn create_regions_gpu(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    subdivisions: usize,
+) -> anyhow::Result<(Vec<Region>, Adjacency, RegionIndex)> {
+    let (vertices, indices) = gpu_subdivision::subdivide(device, queue, subdivisions)?;
+    // normalized in f64, so the f32 rounding doesn't take the corners off the unit sphere
+    let vertices = vertices
+        .into_iter()
+        .map(DVec3::normalize)
+        .collect::<Vec<_>>();
+    Ok(repaired_regions(vertices, indices))
+}
+
+/// Regions of the mesh. If they don't pass `validate` the vertices are welded and the
+/// triangles winding inwards flipped first.
+fn repaired_regions(
+    mut vertices: Vec<DVec3>,
+    mut indices: Vec<u32>,
+) -> (Vec<Region>, Adjacency, RegionIndex) {
+    let regions = indices
+        .chunks_exact(3)
+        .map(|triangle| Region::new(triangle, &vertices))
+        .collect::<Vec<_>>();
+    let issues = validate(&regions);
+    let Some(first) = issues.first() else {
+        return regions_from_mesh(&vertices, &indices);
+    };
+    let merged = weld_vertices(&mut vertices, &mut indices, WELD_DISTANCE);
+    let flipped = fix_winding(&vertices, &mut indices);
+    log::warn!(
+        "Found {} issues in the regions, e.g. {first}, welded {merged} vertices and flipped \
+         {flipped} triangles",
+        issues.len()
+    );
+    regions_from_mesh(&vertices, &indices)
+}
+
+fn regions_from_mesh(vertices: &[DVec3], indices: &[u32]) -> (Vec<Region>, Adjacency, RegionIndex) {
+    let regions = indices
+        .chunks_exact(3)
+        .map(|triangle| Region::new(triangle, vertices))
+        .collect::<Vec<_>>();
+    let adjacency = Adjacency::new(&regions);
+    let index = RegionIndex::new(&regions);
+    (regions, adjacency, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct_edges_have_distinct_keys() {
+        // past subdivision 6 the vertex indices no longer fit into 16 bits
+        let (vertices, indices) = icosphere::subdivided(7);
+        assert!(vertices.len() > u16::MAX as usize + 1);
+        let mut edges = HashMap::new();
+        for triangle in indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                let edge = (a.min(b), a.max(b));
+                let previous = *edges.entry(edge_key(a, b)).or_insert(edge);
+                assert_eq!(previous, edge, "edges share the key {}", edge_key(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn icosahedron_regions_have_equal_areas() {
+        let (regions, _, _) = create_regions(0);
+        assert_eq!(regions.len(), 20);
+        // together they cover the unit sphere
+        let area = 4. * std::f64::consts::PI / 20.;
+        for region in &regions {
+            assert!((region.area() - area).abs() < 1e-12, "{}", region.area());
+        }
+    }
+
+    #[test]
+    fn centroids_are_on_the_unit_sphere() {
+        for subdivisions in [0, 3] {
+            let (regions, _, _) = create_regions(subdivisions);
+            for region in &regions {
+                assert!((region.centroid().length() - 1.).abs() < 1e-12);
+                assert!(region.normal().dot(region.centroid()) > 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn icosahedron_regions_have_three_neighbors() {
+        let (regions, adjacency, _) = create_regions(0);
+        for (region_index, region) in regions.iter().enumerate() {
+            let ring = adjacency.ring(region_index, 1);
+            assert_eq!(ring.len(), 4);
+            assert_eq!(ring[0], region_index);
+            for &neighbor in &ring[1..] {
+                assert!(region.borders(&regions[neighbor]));
+                assert!(adjacency.neighbors(neighbor).contains(&region_index));
+            }
+            // the icosahedron is regular, the neighboring centroids are equally far away
+            let distance = great_circle_distance(region, &regions[ring[1]]);
+            assert!(distance > 0.);
+            for &neighbor in &ring[2..] {
+                let other = great_circle_distance(region, &regions[neighbor]);
+                assert!((other - distance).abs() < 1e-9 * RADIUS);
+            }
+        }
+        // two hops reach the 3 neighbors of every neighbor, 6 of them new
+        assert_eq!(adjacency.ring(0, 2).len(), 10);
+    }
+
+    #[test]
+    fn cpu_and_gpu_regions_share_the_icosphere() {
+        let (vertices, indices) = icosphere::subdivided(3);
+        let (regions, _, _) = create_regions(3);
+        for (region, triangle) in regions.iter().zip(indices.chunks_exact(3)) {
+            assert_eq!(
+                region.corners,
+                [0, 1, 2].map(|i| vertices[triangle[i] as usize])
+            );
+        }
+
+        let Some((device, queue)) = crate::setup::test_device() else {
+            return;
+        };
+        let (gpu_regions, _, _) = create_regions_gpu(&device, &queue, 3).unwrap();
+        assert_eq!(gpu_regions.len(), regions.len());
+        for (gpu_region, region) in gpu_regions.iter().zip(&regions) {
+            for (gpu_corner, corner) in gpu_region.corners.iter().zip(region.corners) {
+                assert!(gpu_corner.distance(corner) < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn locate_finds_the_region_of_every_centroid() {
+        let (regions, _, index) = create_regions(4);
+        for (region_index, region) in regions.iter().enumerate() {
+            assert_eq!(index.locate(region.centroid()), region_index);
+        }
     }
-    regions
 }
@@ -0,0 +1,238 @@
+use super::icosphere;
+use crate::{setup, utils::*};
+use anyhow::{ensure, Result};
+
+pub const SHADER: &str = "shaders/subdivide.wgsl";
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    triangle_count: u32,
+    table_size: u32,
+    _padding: [u32; 2],
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(layout),
+        module: shader,
+        entry_point: Some(entry_point),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: None,
+    })
+}
+
+/// Subdivides the icosahedron on the GPU, giving the same triangles in the same order as the
+/// CPU subdivision. The vertices are computed in f32 and numbered in a different order.
+pub fn subdivide(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    subdivisions: usize,
+) -> Result<(Vec<DVec3>, Vec<u32>)> {
+    let (base_vertices, base_indices) = icosphere::base_mesh();
+    let final_triangles = base_indices.len() / 3 * 4usize.pow(subdivisions as u32);
+    // from Euler's formula, with every edge shared by two triangles
+    let final_vertices = 2 + final_triangles / 2;
+    ensure!(
+        final_triangles * 3 <= u32::MAX as usize,
+        "{subdivisions} subdivisions are too many for the GPU subdivision"
+    );
+
+    let shader = setup::shader(device, SHADER)?;
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage_entry(1, false),
+            storage_entry(2, true),
+            storage_entry(3, false),
+            storage_entry(4, false),
+            storage_entry(5, false),
+            storage_entry(6, false),
+        ],
+        label: Some("subdivide_bind_group_layout"),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Subdivide Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let allocate_pipeline = compute_pipeline(device, &layout, &shader, "allocate_midpoints");
+    let split_pipeline = compute_pipeline(device, &layout, &shader, "split_triangles");
+
+    let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+    let params: Buffer<Params> = device.create_typed_buffer(&TypedBufferDescriptor {
+        label: Some("subdivide_params"),
+        len: 1,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut vertices: Buffer<Vec4> = device.create_typed_buffer(&TypedBufferDescriptor {
+        label: Some("subdivide_vertices"),
+        len: final_vertices,
+        usage: storage | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    // the indices go back and forth between the two buffers every level
+    let mut indices: [Buffer<u32>; 2] =
+        ["subdivide_indices_a", "subdivide_indices_b"].map(|label| {
+            device.create_typed_buffer(&TypedBufferDescriptor {
+                label: Some(label),
+                len: final_triangles * 3,
+                usage: storage | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        });
+    // at most half full on the last level, which has the most edges
+    let table_size = (final_triangles * 3 / 4).next_power_of_two() as u32;
+    let occupied: Buffer<u32> = device.create_typed_buffer(&TypedBufferDescriptor {
+        label: Some("subdivide_occupied"),
+        len: table_size as usize,
+        usage: storage,
+        mapped_at_creation: false,
+    });
+    // edge start and end, and the midpoint, padded to 16 bytes
+    let table: Buffer<[u32; 4]> = device.create_typed_buffer(&TypedBufferDescriptor {
+        label: Some("subdivide_table"),
+        len: table_size as usize,
+        usage: storage,
+        mapped_at_creation: false,
+    });
+    let vertex_count: Buffer<u32> = device.create_typed_buffer(&TypedBufferDescriptor {
+        label: Some("subdivide_vertex_count"),
+        len: 1,
+        usage: storage,
+        mapped_at_creation: false,
+    });
+
+    let padded_vertices = base_vertices
+        .iter()
+        .map(|vertex| vertex.as_vec3().extend(0.))
+        .collect::<Vec<_>>();
+    queue.write_typed_buffer(&vertices, 0, &padded_vertices);
+    queue.write_typed_buffer(&indices[0], 0, &base_indices);
+
+    let bind_group = |input: &Buffer<u32>, output: &Buffer<u32>| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                params.as_entire_binding(),
+                vertices.as_entire_binding(),
+                input.as_entire_binding(),
+                output.as_entire_binding(),
+                occupied.as_entire_binding(),
+                table.as_entire_binding(),
+                vertex_count.as_entire_binding(),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource,
+            })
+            .collect::<Vec<_>>(),
+            label: Some("subdivide_bind_group"),
+        })
+    };
+    let bind_groups = [
+        bind_group(&indices[0], &indices[1]),
+        bind_group(&indices[1], &indices[0]),
+    ];
+
+    let mut triangle_count = base_indices.len() / 3;
+    let mut level_vertices = base_vertices.len();
+    for level in 0..subdivisions {
+        queue.write_typed_buffer(
+            &params,
+            0,
+            &[Params {
+                triangle_count: triangle_count as u32,
+                table_size,
+                _padding: [0; 2],
+            }],
+        );
+        queue.write_typed_buffer(&vertex_count, 0, &[level_vertices as u32]);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Subdivide Encoder"),
+        });
+        encoder.clear_buffer(&occupied, 0, None);
+        encoder.clear_buffer(&table, 0, None);
+        let workgroups = (triangle_count as u32).div_ceil(64);
+        for pipeline in [&allocate_pipeline, &split_pipeline] {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Subdivide Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_groups[level % 2], &[]);
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        level_vertices += triangle_count * 3 / 2;
+        triangle_count *= 4;
+    }
+
+    let output = &mut indices[subdivisions % 2];
+    output.truncate(triangle_count * 3);
+    vertices.truncate(level_vertices);
+    let indices = output.read(device, queue)?;
+    let vertices = vertices
+        .read(device, queue)?
+        .into_iter()
+        .map(|vertex| vertex.truncate().as_dvec3())
+        .collect();
+    Ok((vertices, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_cpu_subdivision() {
+        let Some((device, queue)) = setup::test_device() else {
+            return;
+        };
+        let (vertices, indices) = subdivide(&device, &queue, 4).unwrap();
+        let (cpu_vertices, cpu_indices) = icosphere::subdivided(4);
+        assert_eq!(vertices.len(), cpu_vertices.len());
+        assert_eq!(indices.len(), cpu_indices.len());
+        // the vertices are numbered differently, but the triangles come in the same order
+        for (&index, &cpu_index) in indices.iter().zip(&cpu_indices) {
+            let (vertex, cpu_vertex) = (vertices[index as usize], cpu_vertices[cpu_index as usize]);
+            assert!(
+                vertex.distance(cpu_vertex) < 1e-6,
+                "{vertex} != {cpu_vertex}"
+            );
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::utils::*;
+
+const PHI: f64 = 1.61803398875; // Golden ratio
+
+#[rustfmt::skip]
+const VERTICES: &[DVec3] = &[
+    DVec3::new(-1.0,  PHI,  0.0),
+    DVec3::new( 1.0,  PHI,  0.0),
+    DVec3::new(-1.0, -PHI,  0.0),
+    DVec3::new( 1.0, -PHI,  0.0),
+    DVec3::new( 0.0, -1.0,  PHI),
+    DVec3::new( 0.0,  1.0,  PHI),
+    DVec3::new( 0.0, -1.0, -PHI),
+    DVec3::new( 0.0,  1.0, -PHI),
+    DVec3::new( PHI,  0.0, -1.0),
+    DVec3::new( PHI,  0.0,  1.0),
+    DVec3::new(-PHI,  0.0, -1.0),
+    DVec3::new(-PHI,  0.0,  1.0),
+];
+
+#[rustfmt::skip]
+const INDICES: &[u32] = &[
+    0, 11, 5,  0, 5, 1,  0, 1, 7,  0, 7, 10,  0, 10, 11,
+    1, 5, 9,  5, 11, 4,  11, 10, 2,  10, 7, 6,  7, 1, 8,
+    3, 9, 4,  3, 4, 2,  3, 2, 6,  3, 6, 8,  3, 8, 9,
+    4, 9, 5,  2, 4, 11,  6, 2, 10,  8, 6, 7,  9, 8, 1,
+];
+
+/// Splits every triangle into four, with new vertices at the edge midpoints projected onto the
+/// unit sphere
+#[cfg(not(feature = "parallel"))]
+pub fn subdivide(vertices: &mut Vec<DVec3>, indices: &mut Vec<u32>) {
+    let mut new_indices = Vec::new();
+    let mut midpoint_cache = std::collections::HashMap::new();
+
+    let midpoint = |a: u32,
+                    b: u32,
+                    vertices: &mut Vec<DVec3>,
+                    cache: &mut std::collections::HashMap<(u32, u32), u32>|
+     -> u32 {
+        let key = if a < b { (a, b) } else { (b, a) };
+        if let Some(&mid) = cache.get(&key) {
+            return mid;
+        }
+        let mid_pos = (vertices[a as usize] + vertices[b as usize]) * 0.5;
+        let mid_index = vertices.len() as u32;
+        vertices.push(mid_pos.normalize());
+        cache.insert(key, mid_index);
+        mid_index
+    };
+
+    for chunk in indices.chunks_exact(3) {
+        let m1 = midpoint(chunk[0], chunk[1], vertices, &mut midpoint_cache);
+        let m2 = midpoint(chunk[1], chunk[2], vertices, &mut midpoint_cache);
+        let m3 = midpoint(chunk[2], chunk[0], vertices, &mut midpoint_cache);
+
+        new_indices.extend_from_slice(&[
+            chunk[0], m1, m3, m1, chunk[1], m2, m3, m2, chunk[2], m1, m2, m3,
+        ]);
+    }
+    *indices = new_indices;
+}
+
+/// Same result as the serial subdivision, in two phases so the triangles can be split in
+/// parallel: the unique edges are collected and given a midpoint in the order the serial
+/// version creates them, then every triangle looks up the midpoints of its edges.
+#[cfg(feature = "parallel")]
+pub fn subdivide(vertices: &mut Vec<DVec3>, indices: &mut Vec<u32>) {
+    use super::edge_key;
+    use rayon::prelude::*;
+
+    // every edge of every triangle, with its position in `indices`
+    let mut edges = (0..indices.len())
+        .into_par_iter()
+        .map(|i| {
+            let next = i - i % 3 + (i % 3 + 1) % 3;
+            (edge_key(indices[i], indices[next]), i)
+        })
+        .collect::<Vec<_>>();
+    edges.par_sort_unstable();
+
+    // the serial version creates a midpoint the first time it sees an edge
+    let mut unique = edges
+        .par_chunk_by(|a, b| a.0 == b.0)
+        .map(|shared| (shared[0].1, shared))
+        .collect::<Vec<_>>();
+    unique.par_sort_unstable_by_key(|&(first, _)| first);
+
+    let first_midpoint = vertices.len() as u32;
+    let new_vertices = unique
+        .par_iter()
+        .map(|(_, shared)| {
+            let (a, b) = (shared[0].0 >> 32, shared[0].0 & u32::MAX as u64);
+            ((vertices[a as usize] + vertices[b as usize]) * 0.5).normalize()
+        })
+        .collect::<Vec<_>>();
+    vertices.extend(new_vertices);
+
+    let mut midpoints = unique
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(n, (_, shared))| {
+            shared
+                .iter()
+                .map(move |&(_, i)| (i, first_midpoint + n as u32))
+        })
+        .collect::<Vec<_>>();
+    midpoints.par_sort_unstable();
+
+    *indices = indices
+        .par_chunks_exact(3)
+        .zip(midpoints.par_chunks_exact(3))
+        .flat_map_iter(|(chunk, mids)| {
+            let (m1, m2, m3) = (mids[0].1, mids[1].1, mids[2].1);
+            [
+                chunk[0], m1, m3, m1, chunk[1], m2, m3, m2, chunk[2], m1, m2, m3,
+            ]
+        })
+        .collect();
+}
+
+/// The unit icosahedron
+pub fn base_mesh() -> (Vec<DVec3>, Vec<u32>) {
+    let vertices = VERTICES.iter().map(|vertex| vertex.normalize()).collect();
+    (vertices, INDICES.to_owned())
+}
+
+/// The icosahedron subdivided `subdivisions` times
+pub fn subdivided(subdivisions: usize) -> (Vec<DVec3>, Vec<u32>) {
+    let (mut vertices, mut indices) = base_mesh();
+    for _ in 0..subdivisions {
+        subdivide(&mut vertices, &mut indices);
+    }
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::edge_key;
+    use super::*;
+    use std::collections::HashMap;
+
+    const SUBDIVISIONS: std::ops::Range<usize> = 0..5;
+
+    /// The number of triangles using every edge
+    fn edge_counts(indices: &[u32]) -> HashMap<u64, usize> {
+        let mut counts = HashMap::new();
+        for triangle in indices.chunks_exact(3) {
+            for i in 0..3 {
+                *counts
+                    .entry(edge_key(triangle[i], triangle[(i + 1) % 3]))
+                    .or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn every_edge_is_shared_by_two_triangles() {
+        for subdivisions in SUBDIVISIONS {
+            let (_, indices) = subdivided(subdivisions);
+            for (edge, count) in edge_counts(&indices) {
+                assert_eq!(
+                    count,
+                    2,
+                    "edge {}-{} at {subdivisions} subdivisions",
+                    edge >> 32,
+                    edge & u32::MAX as u64
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn euler_characteristic_is_two() {
+        for subdivisions in SUBDIVISIONS {
+            let (vertices, indices) = subdivided(subdivisions);
+            let edges = edge_counts(&indices).len();
+            let faces = indices.len() / 3;
+            assert_eq!(
+                vertices.len() as i64 - edges as i64 + faces as i64,
+                2,
+                "{subdivisions} subdivisions"
+            );
+        }
+    }
+
+    #[test]
+    fn vertices_are_on_the_unit_sphere() {
+        for subdivisions in SUBDIVISIONS {
+            let (vertices, _) = subdivided(subdivisions);
+            for vertex in vertices {
+                assert!(
+                    (vertex.length() - 1.).abs() < 1e-12,
+                    "{vertex} at {subdivisions} subdivisions"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_vertex_has_an_antipode() {
+        for subdivisions in SUBDIVISIONS {
+            let (vertices, _) = subdivided(subdivisions);
+            for &vertex in &vertices {
+                assert!(
+                    vertices
+                        .iter()
+                        .any(|&other| (vertex + other).length() < 1e-9),
+                    "{vertex} at {subdivisions} subdivisions"
+                );
+            }
+        }
+    }
+}
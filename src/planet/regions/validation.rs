@@ -0,0 +1,232 @@
+use super::Region;
+use crate::utils::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Regions with a smaller area on the unit sphere are slivers, they break the lighting and
+/// the picking
+const MIN_AREA: f64 = 1e-12;
+/// Largest distance of a corner from the unit sphere
+const UNIT_TOLERANCE: f64 = 1e-9;
+/// Vertices closer than this are welded into one by `weld_vertices`
+pub const WELD_DISTANCE: f64 = 1e-9;
+
+/// Something wrong with the regions found by `validate`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RegionIssue {
+    /// the corners are (almost) on a line or coincide
+    ZeroArea { region: usize, area: f64 },
+    /// a corner isn't on the unit sphere
+    NotUnit {
+        region: usize,
+        corner: usize,
+        magnitude: f64,
+    },
+    /// an edge is shared by more than two regions, or twice by one region
+    DuplicateEdge { edge: u64, count: usize },
+    /// the corners wind clockwise seen from outside, the region is culled as a back face
+    Reversed { region: usize },
+}
+
+impl fmt::Display for RegionIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegionIssue::ZeroArea { region, area } => {
+                write!(f, "region {region} has an area of {area:e}")
+            }
+            RegionIssue::NotUnit {
+                region,
+                corner,
+                magnitude,
+            } => write!(
+                f,
+                "corner {corner} of region {region} has a length of {magnitude}"
+            ),
+            RegionIssue::DuplicateEdge { edge, count } => write!(
+                f,
+                "edge {}-{} is used {count} times",
+                edge >> 32,
+                edge & u32::MAX as u64
+            ),
+            RegionIssue::Reversed { region } => write!(f, "region {region} winds inwards"),
+        }
+    }
+}
+
+/// Whether the corners wind counterclockwise seen from outside the sphere, so the triangle is
+/// a front face with `FrontFace::Ccw`
+fn winds_outwards([a, b, c]: [DVec3; 3]) -> bool {
+    (b - a).cross(c - a).dot(a + b + c) > 0.
+}
+
+/// Checks the regions for slivers, corners off the unit sphere, edges used by more than the
+/// two regions on either side and inward winding. A well formed icosphere has no issues.
+pub fn validate(regions: &[Region]) -> Vec<RegionIssue> {
+    let mut issues = vec![];
+    let mut edge_counts = HashMap::<u64, usize>::with_capacity(regions.len() * 3 / 2);
+    for (region_index, region) in regions.iter().enumerate() {
+        let area = region.area();
+        // NaN for coinciding corners, those are slivers as well
+        if area.is_nan() || area < MIN_AREA {
+            issues.push(RegionIssue::ZeroArea {
+                region: region_index,
+                area,
+            });
+        }
+        if !winds_outwards(region.corners) {
+            issues.push(RegionIssue::Reversed {
+                region: region_index,
+            });
+        }
+        for (corner, position) in region.corners.iter().enumerate() {
+            let magnitude = position.length();
+            if (magnitude - 1.).abs() > UNIT_TOLERANCE {
+                issues.push(RegionIssue::NotUnit {
+                    region: region_index,
+                    corner,
+                    magnitude,
+                });
+            }
+        }
+        for edge in region.edges {
+            *edge_counts.entry(edge).or_default() += 1;
+        }
+    }
+
+    let mut duplicates = edge_counts
+        .into_iter()
+        .filter(|&(_, count)| count > 2)
+        .collect::<Vec<_>>();
+    // the hash map iterates in any order, keep the report reproducible
+    duplicates.sort_unstable();
+    issues.extend(
+        duplicates
+            .into_iter()
+            .map(|(edge, count)| RegionIssue::DuplicateEdge { edge, count }),
+    );
+    issues
+}
+
+/// Merges vertices closer than `distance` into the first of them and projects every vertex
+/// back onto the unit sphere. Triangles left with two equal corners are removed, the other
+/// triangles keep their order. Returns the number of merged vertices, they stay in `vertices`
+/// unused so the indices of the others don't shift.
+pub fn weld_vertices(vertices: &mut [DVec3], indices: &mut Vec<u32>, distance: f64) -> usize {
+    for vertex in vertices.iter_mut() {
+        *vertex = vertex.normalize();
+    }
+
+    // vertices by the cell of a grid with `distance` sized cells, a close vertex is in the
+    // same or a neighboring cell
+    let cell = |vertex: DVec3| (vertex / distance).floor().as_i64vec3();
+    let mut grid = HashMap::<I64Vec3, Vec<u32>>::new();
+    let mut remap = (0..vertices.len() as u32).collect::<Vec<_>>();
+    let mut merged = 0;
+    for (index, &vertex) in vertices.iter().enumerate() {
+        let center = cell(vertex);
+        let neighbors = (-1..=1).flat_map(|x| {
+            (-1..=1).flat_map(move |y| (-1..=1).map(move |z| center + i64vec3(x, y, z)))
+        });
+        let close = neighbors
+            .filter_map(|c| grid.get(&c))
+            .flatten()
+            .find(|&&other| vertices[other as usize].distance(vertex) < distance);
+        match close {
+            Some(&other) => {
+                remap[index] = other;
+                merged += 1;
+            }
+            None => grid.entry(center).or_default().push(index as u32),
+        }
+    }
+    if merged == 0 {
+        return 0;
+    }
+
+    let welded = indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]].map(|i| remap[i as usize]))
+        .filter(|[a, b, c]| a != b && b != c && c != a)
+        .flatten()
+        .collect();
+    *indices = welded;
+    merged
+}
+
+/// Swaps two corners of every triangle winding inwards, returns the number of flipped triangles
+pub fn fix_winding(vertices: &[DVec3], indices: &mut [u32]) -> usize {
+    let mut flipped = 0;
+    for triangle in indices.chunks_exact_mut(3) {
+        let corners = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+        if !winds_outwards(corners) {
+            triangle.swap(1, 2);
+            flipped += 1;
+        }
+    }
+    flipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create_regions, icosphere};
+    use super::*;
+
+    fn regions(vertices: &[DVec3], indices: &[u32]) -> Vec<Region> {
+        indices
+            .chunks_exact(3)
+            .map(|triangle| Region::new(triangle, vertices))
+            .collect()
+    }
+
+    #[test]
+    fn repaired_regions_have_no_issues() {
+        let (regions, ..) = create_regions(6);
+        assert_eq!(regions.len(), 20 * 4usize.pow(6));
+        assert_eq!(validate(&regions), vec![]);
+    }
+
+    #[test]
+    fn welding_removes_near_duplicate_vertices() {
+        let (mut vertices, mut indices) = icosphere::subdivided(2);
+        let triangles = indices.len() / 3;
+        // a copy of a corner drifted by rounding, used by a sliver between it and the original
+        let [a, b, _] = [0, 1, 2].map(|i| indices[i]);
+        let drifted = vertices[a as usize] * (1. + 1e-12);
+        vertices.push(drifted);
+        indices.extend_from_slice(&[a, b, vertices.len() as u32 - 1]);
+        assert!(!validate(&regions(&vertices, &indices)).is_empty());
+
+        assert_eq!(weld_vertices(&mut vertices, &mut indices, WELD_DISTANCE), 1);
+        assert_eq!(indices.len() / 3, triangles);
+        assert_eq!(validate(&regions(&vertices, &indices)), vec![]);
+    }
+
+    #[test]
+    fn subdivision_keeps_the_triangles_facing_outwards() {
+        for subdivisions in 0..6 {
+            let (vertices, indices) = icosphere::subdivided(subdivisions);
+            let regions = regions(&vertices, &indices);
+            for (region_index, region) in regions.iter().enumerate() {
+                assert!(
+                    region.normal().dot(region.centroid()) > 0.,
+                    "region {region_index} faces inwards at subdivision {subdivisions}"
+                );
+            }
+            let reversed = validate(&regions)
+                .into_iter()
+                .filter(|issue| matches!(issue, RegionIssue::Reversed { .. }))
+                .count();
+            assert_eq!(reversed, 0, "at subdivision {subdivisions}");
+        }
+    }
+
+    #[test]
+    fn fix_winding_flips_reversed_triangles() {
+        let (vertices, mut indices) = icosphere::subdivided(1);
+        indices.swap(1, 2);
+        indices.swap(10, 11);
+        assert_eq!(fix_winding(&vertices, &mut indices), 2);
+        assert_eq!(fix_winding(&vertices, &mut indices), 0);
+        assert_eq!(validate(&regions(&vertices, &indices)), vec![]);
+    }
+}
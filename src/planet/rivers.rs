@@ -0,0 +1,50 @@
+use super::regions::{Adjacency, Region};
+
+/// Traces up to `max_rivers` rivers downhill, starting from the highest land regions. Each
+/// river repeatedly steps to its lowest neighbor until it reaches the ocean, or ends in a
+/// local minimum where it would form a lake. Rivers joining another river follow it down, so
+/// the paths overlap.
+pub fn trace_rivers(
+    regions: &[Region],
+    adjacency: &Adjacency,
+    elevation: &[f64],
+    ocean: &[bool],
+    max_rivers: usize,
+) -> Vec<Vec<usize>> {
+    let mut sources = (0..regions.len())
+        .filter(|&r| !ocean[r] && elevation[r] > 0.)
+        .collect::<Vec<_>>();
+    sources.sort_by(|&a, &b| elevation[b].total_cmp(&elevation[a]));
+    sources.truncate(max_rivers);
+
+    sources
+        .into_iter()
+        .map(|source| {
+            let mut river = vec![source];
+            let mut current = source;
+            while !ocean[current] {
+                let lowest = adjacency
+                    .neighbors(current)
+                    .into_iter()
+                    .min_by(|&a, &b| elevation[a].total_cmp(&elevation[b]))
+                    .expect("regions have three neighbors");
+                // the elevation strictly decreases, so the river can't loop
+                if elevation[lowest] >= elevation[current] {
+                    break;
+                }
+                river.push(lowest);
+                current = lowest;
+            }
+            river
+        })
+        .collect()
+}
+
+/// Number of rivers flowing through every region
+pub fn river_flow(region_count: usize, rivers: &[Vec<usize>]) -> Vec<u32> {
+    let mut flow = vec![0; region_count];
+    for &region_index in rivers.iter().flatten() {
+        flow[region_index] += 1;
+    }
+    flow
+}
@@ -0,0 +1,118 @@
+use super::regions::{Adjacency, Region, RegionIndex};
+use super::tectonic_plates::{TectonicPlate, TectonicPlateClassification};
+use super::{ElevationParams, GenerationParams, PlanetData};
+use crate::utils::*;
+use anyhow::{Context, Result};
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The generated regions and plates, everything else is derived from them when loading
+#[derive(Serialize, Deserialize)]
+struct PlanetFile {
+    regions: Vec<RegionFile>,
+    plates: Vec<PlateFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegionFile {
+    corners: [[f64; 3]; 3],
+    edges: [u64; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlateFile {
+    classification: TectonicPlateClassification,
+    motion_axis: [f64; 3],
+    contained_regions: Vec<usize>,
+}
+
+impl PlanetData {
+    /// Writes the regions and plates as JSON, so the planet can be reproduced even after the
+    /// generation changes
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = PlanetFile {
+            regions: self
+                .regions
+                .iter()
+                .map(|region| RegionFile {
+                    corners: region.corners.map(|corner| corner.to_array()),
+                    edges: region.edges,
+                })
+                .collect(),
+            plates: self
+                .plates
+                .iter()
+                .map(|plate| PlateFile {
+                    classification: plate.classification,
+                    motion_axis: plate.motion_axis.to_array(),
+                    contained_regions: plate.contained_regions.clone(),
+                })
+                .collect(),
+        };
+        let json = serde_json::to_string(&file)?;
+        std::fs::write(path, json).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Reads a planet written by `save`, recomputing the boundaries, elevation and climate with
+    /// the given parameters
+    pub fn load(
+        path: &Path,
+        generation_params: &GenerationParams,
+        elevation_params: &ElevationParams,
+    ) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let file: PlanetFile = serde_json::from_str(&json)
+            .with_context(|| format!("invalid planet file {}", path.display()))?;
+
+        let regions = file
+            .regions
+            .into_iter()
+            .map(|region| Region {
+                corners: region.corners.map(DVec3::from_array),
+                edges: region.edges,
+            })
+            .collect::<Vec<_>>();
+        anyhow::ensure!(
+            3 * regions.len() <= u32::MAX as usize,
+            "{} regions in {} are too many for the 32 bit indices of the mesh",
+            regions.len(),
+            path.display()
+        );
+        let plates = file
+            .plates
+            .into_iter()
+            .map(|plate| {
+                anyhow::ensure!(
+                    plate.contained_regions.iter().all(|&r| r < regions.len()),
+                    "plate region out of range in {}",
+                    path.display()
+                );
+                let mut tectonic_plate = TectonicPlate {
+                    classification: plate.classification,
+                    motion_axis: DVec3::from_array(plate.motion_axis),
+                    contained_regions: plate.contained_regions,
+                    ..Default::default()
+                };
+                tectonic_plate.recompute_edges(&regions);
+                Ok(tectonic_plate)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let adjacency = Adjacency::new(&regions);
+        let index = RegionIndex::new(&regions);
+
+        // the file doesn't keep the seed, loaded planets always erode and get the same relief
+        let mut rng = Pcg32::seed_from_u64(0);
+        Ok(PlanetData::from_plates(
+            &mut rng,
+            regions,
+            adjacency,
+            index,
+            plates,
+            generation_params,
+            elevation_params,
+        ))
+    }
+}
@@ -0,0 +1,77 @@
+use super::regions::Region;
+use super::tectonic_plates::{TectonicPlate, TectonicPlateClassification};
+use std::fmt;
+
+/// Summary of a generated planet, for tuning the generation
+pub struct Stats {
+    pub region_count: usize,
+    /// number of regions and their summed area (on the unit sphere) for every plate
+    pub plate_sizes: Vec<(usize, f64)>,
+    /// fraction of the surface area covered by continental plates
+    pub continental_fraction: f64,
+    /// fraction of the surface area under water, see `PlanetData::ocean`
+    pub ocean_fraction: f64,
+    pub empty_plates: usize,
+}
+
+impl Stats {
+    pub fn compute(regions: &[Region], plates: &[TectonicPlate], ocean: &[bool]) -> Self {
+        let plate_sizes = plates
+            .iter()
+            .map(|plate| {
+                let area = plate.contained_regions.iter().map(|&r| regions[r].area());
+                (plate.contained_regions.len(), area.sum())
+            })
+            .collect::<Vec<(usize, f64)>>();
+
+        let total_area = plate_sizes.iter().map(|&(_, area)| area).sum::<f64>();
+        let continental_area = plates
+            .iter()
+            .zip(&plate_sizes)
+            .filter(|(plate, _)| plate.classification == TectonicPlateClassification::Continental)
+            .map(|(_, &(_, area))| area)
+            .sum::<f64>();
+        let ocean_area = plates
+            .iter()
+            .flat_map(|plate| &plate.contained_regions)
+            .filter(|&&r| ocean[r])
+            .map(|&r| regions[r].area())
+            .sum::<f64>();
+        let fraction = |area: f64| {
+            if total_area > 0. {
+                area / total_area
+            } else {
+                0.
+            }
+        };
+
+        Stats {
+            region_count: regions.len(),
+            empty_plates: plate_sizes.iter().filter(|&&(count, _)| count == 0).count(),
+            continental_fraction: fraction(continental_area),
+            ocean_fraction: fraction(ocean_area),
+            plate_sizes,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} regions in {} plates ({} empty), {:.1}% continental, {:.1}% oceanic, \
+             {:.1}% land, {:.1}% ocean",
+            self.region_count,
+            self.plate_sizes.len(),
+            self.empty_plates,
+            100. * self.continental_fraction,
+            100. * (1. - self.continental_fraction),
+            100. * (1. - self.ocean_fraction),
+            100. * self.ocean_fraction,
+        )?;
+        for (index, (count, area)) in self.plate_sizes.iter().enumerate() {
+            write!(f, "\n  plate {index}: {count} regions, area {area:.4}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,21 @@
-use super::Region;
-use crate::utils::*;
+use super::regions::{Adjacency, Region};
+use super::{pack_color, Material, Vertex};
+use crate::{utils::*, RADIUS};
 use rand::{seq::SliceRandom, Rng};
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 
-fn multi_insert_edge(set: &mut HashSet<u32>, values: &[u32]) {
+/// Color of the motion arrows, apart from the colors of the plate boundaries
+const ARROW_COLOR: Vec3 = vec3(0.2, 0.9, 1.0);
+/// Length of the arrow of a plate at unit speed, the arrows are scaled by the speed
+const ARROW_LENGTH: f64 = 0.3 * RADIUS;
+const ARROW_WIDTH: f64 = 0.01 * RADIUS;
+/// Width and length of the arrowhead, the head of a short arrow is at most half of it
+const ARROW_HEAD_WIDTH: f64 = 0.04 * RADIUS;
+const ARROW_HEAD_LENGTH: f64 = 0.05 * RADIUS;
+/// Height of the arrows above sea level, above the highest mountains of the default elevation
+const ARROW_HEIGHT: f64 = 0.025 * RADIUS;
+
+fn multi_insert_edge(set: &mut HashSet<u64>, values: &[u64]) {
     for val in values {
         if !set.insert(*val) {
             set.remove(val);
@@ -11,11 +23,7 @@ fn multi_insert_edge(set: &mut HashSet<u32>, values: &[u32]) {
     }
 }
 
-fn multi_contains(set: &HashSet<u32>, values: &[u32]) -> bool {
-    values.iter().any(|e| set.contains(e))
-}
-
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub enum TectonicPlateClassification {
     #[default]
     Oceanic,
@@ -31,7 +39,24 @@ pub struct TectonicPlate {
     /// contains the indices of the regions inside the tectonic plate
     pub contained_regions: Vec<usize>,
     /// contains the edges forming the border of the tectonic plate
-    pub plate_edges: HashSet<u32>,
+    pub plate_edges: HashSet<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BoundaryType {
+    /// the plates move towards each other
+    Convergent,
+    /// the plates move away from each other
+    Divergent,
+    /// the plates slide past each other
+    Transform,
+}
+
+pub struct PlateBoundary {
+    pub edge: u64,
+    /// the two regions on either side of the edge, each in a different plate
+    pub regions: [usize; 2],
+    pub kind: BoundaryType,
 }
 
 impl TectonicPlate {
@@ -41,48 +66,388 @@ impl TectonicPlate {
             .any(|e| other.plate_edges.contains(e))
     }
 
-    fn assign_classification(&mut self, rng: &mut impl Rng) {
-        if rng.random::<f32>() > 0.6 {
+    fn assign_classification(&mut self, rng: &mut impl Rng, continental_fraction: f32) {
+        if rng.random::<f32>() < continental_fraction {
             self.classification = TectonicPlateClassification::Continental;
         } else {
             self.classification = TectonicPlateClassification::Oceanic;
         }
     }
+
+    fn assign_motion(&mut self, rng: &mut impl Rng) {
+        let z = rng.random_range(-1.0..1.0f64);
+        let theta = rng.random_range(0.0..std::f64::consts::TAU);
+        let r = (1. - z * z).sqrt();
+        let axis = dvec3(r * theta.cos(), r * theta.sin(), z);
+        self.motion_axis = axis * rng.random::<f64>();
+    }
+
+    /// Rebuilds `plate_edges` from the contained regions
+    pub fn recompute_edges(&mut self, regions: &[Region]) {
+        self.plate_edges.clear();
+        for &region_index in &self.contained_regions {
+            multi_insert_edge(&mut self.plate_edges, &regions[region_index].edges);
+        }
+    }
+
+    /// Velocity of a point on the surface of the plate
+    pub fn velocity(&self, point: DVec3) -> DVec3 {
+        self.motion_axis.cross(point)
+    }
 }
 
+/// Grows the plates from random seed regions with a flood fill over the region adjacency,
+/// expanding a randomly picked frontier region each step so the plates get irregular shapes.
+/// Every region is visited once, so the fill always terminates with all regions assigned.
+/// Each plate is continental with a probability of `continental_fraction`.
 pub fn cluster_regions(
     rng: &mut impl Rng,
     regions: &[Region],
+    adjacency: &Adjacency,
     num_plates: usize,
+    continental_fraction: f32,
 ) -> Vec<TectonicPlate> {
     let mut plates = vec![TectonicPlate::default(); num_plates];
     plates
         .iter_mut()
-        .for_each(|plate| plate.assign_classification(rng));
+        .for_each(|plate| plate.assign_classification(rng, continental_fraction));
 
     let mut region_indices = (0..regions.len()).collect::<Vec<_>>();
     region_indices.shuffle(rng);
 
-    for i in 0..num_plates {
+    let mut assigned = vec![false; regions.len()];
+    // (region, plate) pairs of unassigned regions bordering a plate
+    let mut frontier = vec![];
+
+    for (plate_index, plate) in plates.iter_mut().enumerate() {
         let region_index = region_indices.pop().unwrap();
-        let region = &regions[region_index];
-        plates[i].contained_regions.push(region_index);
-        multi_insert_edge(&mut plates[i].plate_edges, &region.edges);
-    }
-
-    while let Some(region_index) = region_indices.pop() {
-        let region = &regions[region_index];
-        if let Some(plate) = plates
-            .iter_mut()
-            .find(|plate| multi_contains(&plate.plate_edges, &region.edges))
-        {
-            plate.contained_regions.push(region_index);
-            multi_insert_edge(&mut plate.plate_edges, &region.edges);
-            plates.shuffle(rng);
-        } else {
-            region_indices.insert(0, region_index);
+        plate.contained_regions.push(region_index);
+        multi_insert_edge(&mut plate.plate_edges, &regions[region_index].edges);
+        assigned[region_index] = true;
+        frontier.extend(adjacency.neighbors(region_index).map(|n| (n, plate_index)));
+    }
+
+    while !frontier.is_empty() {
+        let (region_index, plate_index) = frontier.swap_remove(rng.random_range(0..frontier.len()));
+        if assigned[region_index] {
+            continue;
         }
+        assigned[region_index] = true;
+
+        let plate = &mut plates[plate_index];
+        plate.contained_regions.push(region_index);
+        multi_insert_edge(&mut plate.plate_edges, &regions[region_index].edges);
+        frontier.extend(
+            adjacency
+                .neighbors(region_index)
+                .into_iter()
+                .filter(|&n| !assigned[n])
+                .map(|n| (n, plate_index)),
+        );
     }
 
+    plates.iter_mut().for_each(|plate| plate.assign_motion(rng));
+
     plates
 }
+
+/// Assigns every region to the plate of the nearest seed region by great-circle distance
+/// between centroids, which gives compact and connected plates. Each of the
+/// `relax_iterations` Lloyd relaxation steps moves the seeds to the area-weighted centroid of
+/// their plate and reassigns the regions, evening out the plate sizes.
+/// Each plate is continental with a probability of `continental_fraction`.
+pub fn cluster_regions_voronoi(
+    rng: &mut impl Rng,
+    regions: &[Region],
+    num_plates: usize,
+    continental_fraction: f32,
+    relax_iterations: usize,
+) -> Vec<TectonicPlate> {
+    let mut plates = vec![TectonicPlate::default(); num_plates];
+    plates
+        .iter_mut()
+        .for_each(|plate| plate.assign_classification(rng, continental_fraction));
+
+    let mut seeds = rand::seq::index::sample(rng, regions.len(), num_plates.min(regions.len()))
+        .iter()
+        .map(|region_index| regions[region_index].centroid())
+        .collect::<Vec<_>>();
+    assign_nearest(regions, &seeds, &mut plates);
+
+    for _ in 0..relax_iterations {
+        for (seed, plate) in seeds.iter_mut().zip(&plates) {
+            let weighted = plate
+                .contained_regions
+                .iter()
+                .map(|&r| regions[r].centroid() * regions[r].area())
+                .sum::<DVec3>();
+            // an empty plate keeps its seed
+            *seed = weighted.try_normalize().unwrap_or(*seed);
+        }
+        assign_nearest(regions, &seeds, &mut plates);
+    }
+
+    plates.iter_mut().for_each(|plate| plate.assign_motion(rng));
+
+    plates
+}
+
+/// Replaces the regions of the plates with the regions closest to each plate's seed point
+fn assign_nearest(regions: &[Region], seeds: &[DVec3], plates: &mut [TectonicPlate]) {
+    for plate in plates.iter_mut() {
+        plate.contained_regions.clear();
+        plate.plate_edges.clear();
+    }
+    for (region_index, region) in regions.iter().enumerate() {
+        let centroid = region.centroid();
+        // the closest point on the sphere has the largest dot product
+        let Some(plate_index) = (0..seeds.len())
+            .max_by(|&a, &b| centroid.dot(seeds[a]).total_cmp(&centroid.dot(seeds[b])))
+        else {
+            return;
+        };
+        let plate = &mut plates[plate_index];
+        plate.contained_regions.push(region_index);
+        multi_insert_edge(&mut plate.plate_edges, &region.edges);
+    }
+}
+
+/// Index of the plate containing every region, `usize::MAX` for regions in no plate
+pub fn plate_ids(num_regions: usize, plates: &[TectonicPlate]) -> Vec<usize> {
+    let mut plate_ids = vec![usize::MAX; num_regions];
+    for (plate_index, plate) in plates.iter().enumerate() {
+        plate
+            .contained_regions
+            .iter()
+            .for_each(|&r| plate_ids[r] = plate_index);
+    }
+    plate_ids
+}
+
+/// Finds every edge between two plates and classifies it by the relative motion of the
+/// plates at that edge
+pub fn classify_boundaries(
+    regions: &[Region],
+    adjacency: &Adjacency,
+    plates: &[TectonicPlate],
+) -> Vec<PlateBoundary> {
+    let mut region_plates = vec![usize::MAX; regions.len()];
+    for (plate_index, plate) in plates.iter().enumerate() {
+        for &region_index in &plate.contained_regions {
+            region_plates[region_index] = plate_index;
+        }
+    }
+
+    let mut boundaries = vec![];
+    for (a, region) in regions.iter().enumerate() {
+        for (edge, b) in region.edges.into_iter().zip(adjacency.neighbors(a)) {
+            let (plate_a, plate_b) = (region_plates[a], region_plates[b]);
+            // every edge is seen from both sides, only handle it once
+            if a > b || plate_a == plate_b {
+                continue;
+            }
+
+            let (centroid_a, centroid_b) = (regions[a].centroid(), regions[b].centroid());
+            let point = (centroid_a + centroid_b).normalize();
+            // direction from a to b, tangent to the sphere
+            let normal = (centroid_b - centroid_a).reject_from(point).normalize();
+            let relative = plates[plate_a].velocity(point) - plates[plate_b].velocity(point);
+            let approach = relative.dot(normal);
+            let shear = (relative - approach * normal).length();
+
+            let kind = if approach.abs() < shear {
+                BoundaryType::Transform
+            } else if approach > 0. {
+                BoundaryType::Convergent
+            } else {
+                BoundaryType::Divergent
+            };
+            boundaries.push(PlateBoundary {
+                edge,
+                regions: [a, b],
+                kind,
+            });
+        }
+    }
+    boundaries
+}
+
+/// Age of the oceanic crust in every region, the number of region hops to the nearest
+/// divergent boundary where it was created. The distance is counted with a breadth first
+/// search through the oceanic regions only, continental regions and oceans without a spreading
+/// ridge have no age.
+pub fn compute_seafloor_age(
+    regions: &[Region],
+    adjacency: &Adjacency,
+    plates: &[TectonicPlate],
+    boundaries: &[PlateBoundary],
+) -> Vec<Option<u32>> {
+    let mut oceanic = vec![false; regions.len()];
+    for plate in plates
+        .iter()
+        .filter(|p| p.classification == TectonicPlateClassification::Oceanic)
+    {
+        plate
+            .contained_regions
+            .iter()
+            .for_each(|&r| oceanic[r] = true);
+    }
+
+    let mut age = vec![None; regions.len()];
+    let mut queue = VecDeque::new();
+    for boundary in boundaries
+        .iter()
+        .filter(|b| b.kind == BoundaryType::Divergent)
+    {
+        for region_index in boundary.regions {
+            if oceanic[region_index] && age[region_index].is_none() {
+                age[region_index] = Some(0);
+                queue.push_back(region_index);
+            }
+        }
+    }
+
+    while let Some(region_index) = queue.pop_front() {
+        let next = age[region_index].map(|a| a + 1);
+        for neighbor in adjacency.neighbors(region_index) {
+            if oceanic[neighbor] && age[neighbor].is_none() {
+                age[neighbor] = next;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    age
+}
+
+/// An arrow for every moving plate, from the centroid of the plate along the velocity of the
+/// surface there, `motion_axis × centroid`. The arrows are triangles above the surface, their
+/// length is proportional to the speed.
+pub fn motion_arrows(plates: &[TectonicPlate], regions: &[Region]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for plate in plates {
+        let Some(centroid) = plate
+            .contained_regions
+            .iter()
+            .map(|&r| regions[r].centroid())
+            .sum::<DVec3>()
+            .try_normalize()
+        else {
+            continue;
+        };
+        let velocity = plate.velocity(centroid);
+        let Some(forward) = velocity.try_normalize() else {
+            continue;
+        };
+        // counterclockwise seen from outside, like the regions
+        let side = centroid.cross(forward);
+        let length = ARROW_LENGTH * velocity.length();
+        let head_length = ARROW_HEAD_LENGTH.min(length / 2.);
+        let neck = length - head_length;
+        let outline = [
+            (0., -ARROW_WIDTH / 2.),
+            (neck, -ARROW_WIDTH / 2.),
+            (neck, ARROW_WIDTH / 2.),
+            (0., ARROW_WIDTH / 2.),
+            (neck, -ARROW_HEAD_WIDTH / 2.),
+            (length, 0.),
+            (neck, ARROW_HEAD_WIDTH / 2.),
+        ];
+
+        let first = vertices.len() as u32;
+        for (along, across) in outline {
+            let direction = (centroid + (forward * along + side * across) / RADIUS).normalize();
+            vertices.push(Vertex {
+                position: (direction * (RADIUS + ARROW_HEIGHT)).into(),
+                color: pack_color(ARROW_COLOR),
+                region: plate.contained_regions[0] as u32,
+                normal: centroid.as_vec3(),
+                material: Material::Land as u32,
+                uv: Vec2::ZERO,
+            });
+        }
+        // the shaft and the head
+        indices.extend([0, 1, 2, 0, 2, 3, 4, 5, 6].map(|i| first + i));
+    }
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::regions::create_regions;
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    const SEED: u64 = 0;
+
+    #[test]
+    fn flood_fill_assigns_every_region_once() {
+        for (subdivisions, num_plates) in [(0, 1), (0, 20), (2, 7), (4, 40)] {
+            let (regions, adjacency, _) = create_regions(subdivisions);
+            let mut rng = Pcg32::seed_from_u64(SEED);
+            let plates = cluster_regions(&mut rng, &regions, &adjacency, num_plates, 0.3);
+            assert_eq!(plates.len(), num_plates);
+
+            let mut counts = vec![0; regions.len()];
+            for plate in &plates {
+                plate.contained_regions.iter().for_each(|&r| counts[r] += 1);
+            }
+            assert!(
+                counts.iter().all(|&count| count == 1),
+                "{num_plates} plates at {subdivisions} subdivisions"
+            );
+        }
+    }
+
+    #[test]
+    fn flood_fill_is_reproducible() {
+        let (regions, adjacency, _) = create_regions(3);
+        let [first, second] = [0, 1].map(|_| {
+            let mut rng = Pcg32::seed_from_u64(SEED);
+            cluster_regions(&mut rng, &regions, &adjacency, 10, 0.3)
+        });
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!(a.contained_regions, b.contained_regions);
+            assert_eq!(a.classification, b.classification);
+        }
+    }
+
+    #[test]
+    fn no_continental_fraction_gives_an_ocean_world() {
+        let (regions, adjacency, _) = create_regions(2);
+        let mut rng = Pcg32::seed_from_u64(SEED);
+        let flood_fill = cluster_regions(&mut rng, &regions, &adjacency, 40, 0.);
+        let voronoi = cluster_regions_voronoi(&mut rng, &regions, 40, 0., 0);
+        for plate in flood_fill.iter().chain(&voronoi) {
+            assert_eq!(plate.classification, TectonicPlateClassification::Oceanic);
+        }
+    }
+
+    #[test]
+    fn relaxation_evens_out_the_plate_areas() {
+        let (regions, _, _) = create_regions(4);
+        let variances = (0..4)
+            .map(|relax_iterations| {
+                let mut rng = Pcg32::seed_from_u64(SEED);
+                let plates = cluster_regions_voronoi(&mut rng, &regions, 20, 0.4, relax_iterations);
+                let areas = plates
+                    .iter()
+                    .map(|plate| {
+                        plate
+                            .contained_regions
+                            .iter()
+                            .map(|&r| regions[r].area())
+                            .sum()
+                    })
+                    .collect::<Vec<f64>>();
+                let mean = areas.iter().sum::<f64>() / areas.len() as f64;
+                areas.iter().map(|area| (area - mean).powi(2)).sum::<f64>() / areas.len() as f64
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            variances.windows(2).all(|pair| pair[1] < pair[0]),
+            "{variances:?}"
+        );
+    }
+}
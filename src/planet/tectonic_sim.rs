@@ -0,0 +1,258 @@
+use super::{PlanetData, Vertex};
+use crate::{setup, utils::*};
+use anyhow::Result;
+
+pub const SHADER: &str = "shaders/tectonics.wgsl";
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    angle: f32,
+    vertex_count: u32,
+    _padding: [u32; 2],
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Tectonics Pipeline"),
+        layout: Some(layout),
+        module: shader,
+        entry_point: Some("move_plates"),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: None,
+    })
+}
+
+/// Moves the plates of the uploaded mesh in a compute pass, instead of rebuilding the mesh on
+/// the CPU every step. The vertices are rotated from a copy of the mesh as it was uploaded by
+/// the angle the plates turned since, so the packed positions don't drift. Every vertex moves
+/// with the plate of its region, which needs a mesh without vertices shared across plates.
+pub struct TectonicSim {
+    params: Buffer<Params>,
+    rest_vertices: Buffer<Vertex>,
+    /// plate of every region, `u32::MAX` for none
+    plate_ids: Buffer<u32>,
+    /// motion axis of every plate, padded to `Vec4`
+    motion_axes: Buffer<Vec4>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+    /// radians per unit of motion axis length since the mesh was uploaded
+    angle: f64,
+}
+
+impl TectonicSim {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &PlanetData,
+        vertices: &[Vertex],
+        vertex_buffer: &Buffer<Vertex>,
+    ) -> Result<Self> {
+        let shader = setup::shader(device, SHADER)?;
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, false),
+                storage_entry(3, true),
+                storage_entry(4, true),
+            ],
+            label: Some("tectonics_bind_group_layout"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tectonics Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = compute_pipeline(device, &pipeline_layout, &shader);
+
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let params = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("tectonics_params"),
+            len: 1,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let rest_vertices = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("tectonics_rest_vertices"),
+            contents: vertices,
+            usage: storage,
+        });
+        let plate_ids = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("tectonics_plate_ids"),
+            contents: &plate_ids(data),
+            usage: storage,
+        });
+        let motion_axes = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("tectonics_motion_axes"),
+            contents: &motion_axes(data),
+            usage: storage,
+        });
+        let bind_group = bind_group(
+            device,
+            &bind_group_layout,
+            [
+                &params,
+                &rest_vertices,
+                vertex_buffer,
+                &plate_ids,
+                &motion_axes,
+            ],
+        );
+
+        let sim = TectonicSim {
+            params,
+            rest_vertices,
+            plate_ids,
+            motion_axes,
+            bind_group_layout,
+            bind_group,
+            pipeline_layout,
+            pipeline,
+            angle: 0.,
+        };
+        sim.write_params(queue);
+        Ok(sim)
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.pipeline = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok(compute_pipeline(device, &self.pipeline_layout, &shader))
+        })?;
+        Ok(())
+    }
+
+    /// Starts over from a newly uploaded mesh, `vertices` are the contents of `vertex_buffer`.
+    /// The buffers are reallocated when they are too small for the new planet.
+    pub fn reset(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &PlanetData,
+        vertices: &[Vertex],
+        vertex_buffer: &Buffer<Vertex>,
+    ) {
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let plate_ids = plate_ids(data);
+        let motion_axes = motion_axes(data);
+        self.rest_vertices
+            .ensure_capacity(device, vertices.len(), storage);
+        self.plate_ids
+            .ensure_capacity(device, plate_ids.len(), storage);
+        self.motion_axes
+            .ensure_capacity(device, motion_axes.len(), storage);
+        queue.write_typed_buffer(&self.rest_vertices, 0, vertices);
+        queue.write_typed_buffer(&self.plate_ids, 0, &plate_ids);
+        queue.write_typed_buffer(&self.motion_axes, 0, &motion_axes);
+        // the vertex buffer may have been reallocated as well
+        self.bind_group = bind_group(
+            device,
+            &self.bind_group_layout,
+            [
+                &self.params,
+                &self.rest_vertices,
+                vertex_buffer,
+                &self.plate_ids,
+                &self.motion_axes,
+            ],
+        );
+        self.angle = 0.;
+        self.write_params(queue);
+    }
+
+    fn write_params(&self, queue: &wgpu::Queue) {
+        let params = Params {
+            angle: self.angle as f32,
+            vertex_count: self.rest_vertices.len as u32,
+            _padding: [0; 2],
+        };
+        queue.write_typed_buffer(&self.params, 0, &[params]);
+    }
+
+    /// Turns the plates further by `angle` radians per unit of motion axis length, in a compute
+    /// pass recorded into `encoder`. It has to run before the passes drawing the planet.
+    pub fn dispatch(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        angle: f64,
+    ) {
+        self.angle += angle;
+        self.write_params(queue);
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Tectonics Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups((self.rest_vertices.len as u32).div_ceil(64), 1, 1);
+    }
+
+    /// Radians per unit of motion axis length the plates turned since the mesh was uploaded
+    pub fn angle(&self) -> f64 {
+        self.angle
+    }
+}
+
+fn bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: [&wgpu::Buffer; 5],
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &buffers
+            .into_iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        label: Some("tectonics_bind_group"),
+    })
+}
+
+fn plate_ids(data: &PlanetData) -> Vec<u32> {
+    data.plate_id
+        .iter()
+        .map(|&id| u32::try_from(id).unwrap_or(u32::MAX))
+        .collect()
+}
+
+fn motion_axes(data: &PlanetData) -> Vec<Vec4> {
+    data.plates
+        .iter()
+        .map(|plate| plate.motion_axis.as_vec3().extend(0.))
+        .collect()
+}
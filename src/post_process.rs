@@ -0,0 +1,585 @@
+use crate::{setup, targets::Targets, utils::*};
+use anyhow::Result;
+use std::collections::HashMap;
+
+pub const SHADER: &str = "shaders/post_process.wgsl";
+pub const BLOOM_SHADER: &str = "shaders/bloom.wgsl";
+
+/// Format of the scene, the passes before the tonemapping render into it
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub struct BloomParams {
+    /// brightness above which the scene blooms, the sun highlight on the oceans and the
+    /// atmosphere limb reach it
+    pub threshold: f32,
+    /// how much of the bloom is added to the scene, 0 turns it off
+    pub intensity: f32,
+    /// number of times the bright parts are halved in resolution and blurred, more levels
+    /// spread the glow further. Fixed when the post process is created.
+    pub levels: usize,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        BloomParams {
+            threshold: 0.5,
+            intensity: 0.6,
+            levels: 5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PostProcessUniform {
+    exposure: f32,
+    bloom_intensity: f32,
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BloomUniform {
+    threshold: f32,
+    _padding: [f32; 3],
+}
+
+/// Configuration of the scene passes, the surface configuration with the HDR format
+pub fn scene_config(config: &wgpu::SurfaceConfiguration) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        format: HDR_FORMAT,
+        ..config.clone()
+    }
+}
+
+/// Render target of a bloom pass, the following passes read it as a texture
+fn target(
+    device: &wgpu::Device,
+    label: &str,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn texture_entry(binding: u32, filterable: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+/// Bind group of a bloom pass reading `source`
+fn bloom_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &Buffer<BloomUniform>,
+    source: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("bloom_bind_group"),
+    })
+}
+
+/// One level of the bloom chain, at half the size of the previous level or the scene
+struct BloomLevel {
+    /// the blurred bright parts, with the smaller levels added on top after upsampling
+    blurred: wgpu::TextureView,
+    /// the horizontal blur, before the vertical one
+    scratch: wgpu::TextureView,
+    /// reads the previous level, or the scene for the first level
+    downsample_bind_group: wgpu::BindGroup,
+    /// reads `blurred`, for the horizontal blur and the upsampling into the previous level
+    blurred_bind_group: wgpu::BindGroup,
+    /// reads `scratch`, for the vertical blur
+    scratch_bind_group: wgpu::BindGroup,
+}
+
+fn bloom_levels(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &Buffer<BloomUniform>,
+    sampler: &wgpu::Sampler,
+    scene: &wgpu::TextureView,
+    config: &wgpu::SurfaceConfiguration,
+    levels: usize,
+) -> Vec<BloomLevel> {
+    let mut bloom_levels: Vec<BloomLevel> = vec![];
+    for level in 1..=levels.max(1) as u32 {
+        let (width, height) = (config.width >> level, config.height >> level);
+        let (_, blurred) = target(device, "bloom_texture", width, height);
+        let (_, scratch) = target(device, "bloom_scratch_texture", width, height);
+        let source = bloom_levels
+            .last()
+            .map_or(scene, |previous| &previous.blurred);
+        let bind_group = |view| bloom_bind_group(device, layout, uniform_buffer, view, sampler);
+        bloom_levels.push(BloomLevel {
+            downsample_bind_group: bind_group(source),
+            blurred_bind_group: bind_group(&blurred),
+            scratch_bind_group: bind_group(&scratch),
+            blurred,
+            scratch,
+        });
+    }
+    bloom_levels
+}
+
+fn bloom_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fragment_entry_point: &str,
+    blend: Option<wgpu::BlendState>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Bloom Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment_entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: HDR_FORMAT,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+struct BloomPipelines {
+    threshold: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    blur_horizontal: wgpu::RenderPipeline,
+    blur_vertical: wgpu::RenderPipeline,
+    /// adds onto the level it draws into
+    upsample: wgpu::RenderPipeline,
+}
+
+fn bloom_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> BloomPipelines {
+    let additive = wgpu::BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent::REPLACE,
+    };
+    BloomPipelines {
+        threshold: bloom_pipeline(device, layout, shader, "fs_threshold", None),
+        downsample: bloom_pipeline(device, layout, shader, "fs_downsample", None),
+        blur_horizontal: bloom_pipeline(device, layout, shader, "fs_blur_horizontal", None),
+        blur_vertical: bloom_pipeline(device, layout, shader, "fs_blur_vertical", None),
+        upsample: bloom_pipeline(device, layout, shader, "fs_upsample", Some(additive)),
+    }
+}
+
+fn bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &Buffer<PostProcessUniform>,
+    hdr_view: &wgpu::TextureView,
+    bloom_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(hdr_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(bloom_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("post_process_bind_group"),
+    })
+}
+
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let constants = HashMap::from([(
+        "LINEAR_TARGET".to_string(),
+        setup::linear_target(format) as u32 as f64,
+    )]);
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Post Process Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                ..Default::default()
+            },
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Blooms the bright parts of the HDR scene target and tonemaps it to the output
+pub struct PostProcess {
+    /// scales the scene before the tonemapping, 1 keeps it as it is
+    pub exposure: f32,
+    pub bloom: BloomParams,
+    sampler: wgpu::Sampler,
+    uniform_buffer: Buffer<PostProcessUniform>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    render_pipeline: wgpu::RenderPipeline,
+    bloom_uniform_buffer: Buffer<BloomUniform>,
+    bloom_bind_group_layout: wgpu::BindGroupLayout,
+    bloom_levels: Vec<BloomLevel>,
+    bloom_pipeline_layout: wgpu::PipelineLayout,
+    bloom_pipelines: BloomPipelines,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &Targets,
+        exposure: f32,
+        bloom: BloomParams,
+    ) -> Result<Self> {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post_process_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("post_process_uniform_buffer"),
+            len: 1,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bloom_uniform_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("bloom_uniform_buffer"),
+            len: 1,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = setup::shader(device, SHADER)?;
+        let bloom_shader = setup::shader(device, BLOOM_SHADER)?;
+
+        let bloom_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[uniform_entry(0), texture_entry(1, true), sampler_entry(2)],
+                label: Some("bloom_bind_group_layout"),
+            });
+        let bloom_levels = bloom_levels(
+            device,
+            &bloom_bind_group_layout,
+            &bloom_uniform_buffer,
+            &sampler,
+            targets.hdr_view(),
+            config,
+            bloom.levels,
+        );
+        let bloom_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Bloom Render Pipeline Layout"),
+                bind_group_layouts: &[&bloom_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let bloom_pipelines = bloom_pipelines(device, &bloom_pipeline_layout, &bloom_shader);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                uniform_entry(0),
+                texture_entry(1, false),
+                texture_entry(2, true),
+                sampler_entry(3),
+            ],
+            label: Some("post_process_bind_group_layout"),
+        });
+        let bind_group = bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            targets.hdr_view(),
+            &bloom_levels[0].blurred,
+            &sampler,
+        );
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Post Process Render Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let format = config.format;
+        let render_pipeline = render_pipeline(device, &render_pipeline_layout, &shader, format);
+
+        Ok(PostProcess {
+            exposure,
+            bloom,
+            sampler,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            render_pipeline_layout,
+            format,
+            render_pipeline,
+            bloom_uniform_buffer,
+            bloom_bind_group_layout,
+            bloom_levels,
+            bloom_pipeline_layout,
+            bloom_pipelines,
+        })
+    }
+
+    /// Recreates the bloom levels at the new surface size and binds the new HDR target, after
+    /// the targets were recreated
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &Targets,
+    ) {
+        self.bloom_levels = bloom_levels(
+            device,
+            &self.bloom_bind_group_layout,
+            &self.bloom_uniform_buffer,
+            &self.sampler,
+            targets.hdr_view(),
+            config,
+            self.bloom.levels,
+        );
+        self.bind_group = bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            targets.hdr_view(),
+            &self.bloom_levels[0].blurred,
+            &self.sampler,
+        );
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.render_pipeline = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok(render_pipeline(
+                device,
+                &self.render_pipeline_layout,
+                &shader,
+                self.format,
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// Recompiles the bloom shader and rebuilds its pipelines, keeping the current ones on
+    /// errors
+    pub fn reload_bloom_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.bloom_pipelines = setup::validated(device, || {
+            let shader = setup::shader(device, BLOOM_SHADER)?;
+            Ok(bloom_pipelines(
+                device,
+                &self.bloom_pipeline_layout,
+                &shader,
+            ))
+        })?;
+        Ok(())
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue) {
+        let uniform = PostProcessUniform {
+            exposure: self.exposure,
+            bloom_intensity: self.bloom.intensity,
+            _padding: [0.; 2],
+        };
+        queue.write_typed_buffer(&self.uniform_buffer, 0, &[uniform]);
+        let bloom_uniform = BloomUniform {
+            threshold: self.bloom.threshold,
+            _padding: [0.; 3],
+        };
+        queue.write_typed_buffer(&self.bloom_uniform_buffer, 0, &[bloom_uniform]);
+    }
+}
+
+fn bloom_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Bloom Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+/// Records the bloom passes, after the scene and before the tonemapping. Every level takes the
+/// bright parts of the previous one at half the size and blurs them horizontally, then
+/// vertically. The levels are then added back up from the smallest into the first one.
+pub fn bloom(encoder: &mut wgpu::CommandEncoder, post_process: &PostProcess) {
+    let pipelines = &post_process.bloom_pipelines;
+    let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+    for (i, level) in post_process.bloom_levels.iter().enumerate() {
+        let downsample = match i {
+            0 => &pipelines.threshold,
+            _ => &pipelines.downsample,
+        };
+        bloom_pass(
+            encoder,
+            &level.blurred,
+            clear,
+            downsample,
+            &level.downsample_bind_group,
+        );
+        bloom_pass(
+            encoder,
+            &level.scratch,
+            clear,
+            &pipelines.blur_horizontal,
+            &level.blurred_bind_group,
+        );
+        bloom_pass(
+            encoder,
+            &level.blurred,
+            clear,
+            &pipelines.blur_vertical,
+            &level.scratch_bind_group,
+        );
+    }
+    for pair in post_process.bloom_levels.windows(2).rev() {
+        bloom_pass(
+            encoder,
+            &pair[0].blurred,
+            wgpu::LoadOp::Load,
+            &pipelines.upsample,
+            &pair[1].blurred_bind_group,
+        );
+    }
+}
+
+/// Tonemaps the HDR scene and the bloom into the render pass target
+pub fn render(render_pass: &mut wgpu::RenderPass, post_process: &PostProcess) {
+    render_pass.set_pipeline(&post_process.render_pipeline);
+    render_pass.set_bind_group(0, &post_process.bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
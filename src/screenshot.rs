@@ -0,0 +1,143 @@
+use crate::utils::*;
+use anyhow::{bail, ensure, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Contents of a texture copied into a mappable buffer. Rows are padded to
+/// `COPY_BYTES_PER_ROW_ALIGNMENT` as required for texture to buffer copies.
+pub struct Readback {
+    buffer: Buffer<u8>,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+}
+
+/// Records a copy of the texture into a new readback buffer. The texture needs `COPY_SRC` usage
+/// and an 8 bit RGBA or BGRA format.
+pub fn copy_texture(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> Readback {
+    let (width, height) = (texture.width(), texture.height());
+    let bytes_per_pixel = texture.format().block_copy_size(None).unwrap_or(4);
+    let padded_bytes_per_row =
+        (width * bytes_per_pixel).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+        label: Some("Readback Buffer"),
+        len: (padded_bytes_per_row * height) as usize,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+
+    Readback {
+        buffer,
+        format: texture.format(),
+        width,
+        height,
+        padded_bytes_per_row,
+    }
+}
+
+impl Readback {
+    /// Maps the buffer, waiting for the copy to finish, and returns the tightly packed RGBA8
+    /// pixels. The copy must have been submitted to the queue.
+    pub fn pixels(&self, device: &wgpu::Device) -> Result<Vec<u8>> {
+        let swap_red_blue = match self.format.remove_srgb_suffix() {
+            wgpu::TextureFormat::Rgba8Unorm => false,
+            wgpu::TextureFormat::Bgra8Unorm => true,
+            format => bail!("can't read back textures with format {format:?}"),
+        };
+
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let row_bytes = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let mapped = slice.get_mapped_range();
+            for row in mapped.chunks_exact(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+
+        if swap_red_blue {
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+        Ok(pixels)
+    }
+
+    pub fn save_png(&self, device: &wgpu::Device, path: &Path) -> Result<()> {
+        let pixels = self.pixels(device)?;
+        image::save_buffer(
+            path,
+            &pixels,
+            self.width,
+            self.height,
+            image::ExtendedColorType::Rgba8,
+        )?;
+        Ok(())
+    }
+}
+
+/// Fails if any pixel of the image at `path` differs from the one in `reference` by more than
+/// `tolerance` in a color channel, or if the images have different sizes
+pub fn compare(path: &Path, reference: &Path, tolerance: u8) -> Result<()> {
+    let open = |path: &Path| {
+        image::open(path)
+            .with_context(|| format!("can't load {}", path.display()))
+            .map(|image| image.into_rgba8())
+    };
+    let (image, reference_image) = (open(path)?, open(reference)?);
+    ensure!(
+        image.dimensions() == reference_image.dimensions(),
+        "{} is {:?} pixels, the reference {} is {:?}",
+        path.display(),
+        image.dimensions(),
+        reference.display(),
+        reference_image.dimensions()
+    );
+
+    let differing = image
+        .pixels()
+        .zip(reference_image.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0).any(|(&a, b)| a.abs_diff(b) > tolerance))
+        .count();
+    ensure!(
+        differing == 0,
+        "{differing} of {} pixels differ from {} by more than {tolerance}",
+        image.pixels().len(),
+        reference.display()
+    );
+    Ok(())
+}
+
+/// A new file name in the working directory, unique per second
+pub fn path() -> PathBuf {
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    PathBuf::from(format!("screenshot-{}.png", time.as_secs()))
+}
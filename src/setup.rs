@@ -1,4 +1,5 @@
-use anyhow::Result;
+use crate::utils::camera;
+use anyhow::{Context, Result};
 use std::borrow::Cow;
 use std::ffi::OsStr;
 use std::fs::File;
@@ -9,19 +10,35 @@ use winit::window::{Window, WindowBuilder};
 
 pub type WindowSize = winit::dpi::PhysicalSize<u32>;
 
-const FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
+const BACKENDS: wgpu::Backends = wgpu::Backends::PRIMARY;
+const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::empty();
+/// Features that are used when the adapter supports them
+const OPTIONAL_FEATURES: wgpu::Features =
+    wgpu::Features::POLYGON_MODE_LINE.union(wgpu::Features::TIMESTAMP_QUERY);
+/// Number of samples per pixel used for multisample anti-aliasing
+const MSAA_SAMPLES: u32 = 4;
 
-pub fn window(window_target: &EventLoopWindowTarget<()>) -> Result<Window> {
-    Ok(WindowBuilder::new().build(window_target)?)
+pub fn window(
+    window_target: &EventLoopWindowTarget<()>,
+    title: &str,
+    width: u32,
+    height: u32,
+) -> Result<Window> {
+    Ok(WindowBuilder::new()
+        .with_title(title)
+        .with_inner_size(WindowSize::new(width, height))
+        .build(window_target)?)
 }
 
 pub fn instance() -> wgpu::Instance {
     wgpu::Instance::new(&wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::PRIMARY,
+        backends: BACKENDS,
         ..Default::default()
     })
 }
 
+/// # Safety
+/// The window has to outlive the surface
 pub unsafe fn surface(
     instance: &wgpu::Instance,
     window: &Window,
@@ -30,14 +47,15 @@ pub unsafe fn surface(
     Ok(instance.create_surface_unsafe(target)?)
 }
 
+/// Requests an adapter, compatible with `surface` if there is one
 pub async fn adapter_async(
     instance: &wgpu::Instance,
-    surface: &wgpu::Surface<'static>,
+    surface: Option<&wgpu::Surface<'static>>,
 ) -> Option<wgpu::Adapter> {
     instance
         .request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(surface),
+            compatible_surface: surface,
             force_fallback_adapter: false,
         })
         .await
@@ -45,32 +63,134 @@ pub async fn adapter_async(
 
 pub fn adapter(
     instance: &wgpu::Instance,
-    surface: &wgpu::Surface<'static>,
-) -> Option<wgpu::Adapter> {
-    pollster::block_on(adapter_async(instance, surface))
+    surface: Option<&wgpu::Surface<'static>>,
+) -> Result<wgpu::Adapter> {
+    pollster::block_on(adapter_async(instance, surface)).with_context(|| {
+        format!("no compatible GPU adapter found for the PRIMARY backends (tried {BACKENDS:?})")
+    })
 }
 
 pub async fn device_queue_async(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
-    Ok(adapter
+    let name = adapter.get_info().name;
+    let missing = REQUIRED_FEATURES - adapter.features();
+    if !missing.is_empty() {
+        anyhow::bail!("GPU adapter {name:?} doesn't support the required features {missing:?}");
+    }
+    if !adapter
+        .features()
+        .contains(wgpu::Features::POLYGON_MODE_LINE)
+    {
+        log::warn!(
+            "GPU adapter {name:?} doesn't support POLYGON_MODE_LINE, wireframes are drawn filled"
+        );
+    }
+
+    let device_queue = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
-                required_features: FEATURES,
+                required_features: REQUIRED_FEATURES | (OPTIONAL_FEATURES & adapter.features()),
                 required_limits: wgpu::Limits::default(),
                 label: None,
                 memory_hints: Default::default(),
             },
             None,
         )
-        .await?)
+        .await
+        .context("failed to create the GPU device")?;
+    Ok(device_queue)
 }
 
 pub fn device_queue(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
     pollster::block_on(device_queue_async(adapter))
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PresentPreference {
+    /// vsync, letting wgpu pick between relaxed and strict fifo
+    #[default]
+    AutoVsync,
+    /// no vsync, frames are presented immediately and may tear
+    Immediate,
+    /// no vsync and no tearing, the newest frame replaces queued ones
+    Mailbox,
+}
+
+impl std::str::FromStr for PresentPreference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "vsync" | "autovsync" => Ok(PresentPreference::AutoVsync),
+            "immediate" => Ok(PresentPreference::Immediate),
+            "mailbox" => Ok(PresentPreference::Mailbox),
+            _ => anyhow::bail!("unknown present mode {s:?}, expected vsync, immediate or mailbox"),
+        }
+    }
+}
+
+impl PresentPreference {
+    /// Reads the preference from the `PRESENT_MODE` environment variable, if set
+    pub fn from_env() -> Self {
+        match std::env::var("PRESENT_MODE") {
+            Ok(value) => value.parse().unwrap_or_else(|err| {
+                log::warn!("{err}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// The preferred mode if the surface supports it, otherwise fifo which is always available
+    fn present_mode(self, available: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let mode = match self {
+            PresentPreference::AutoVsync => return wgpu::PresentMode::AutoVsync,
+            PresentPreference::Immediate => wgpu::PresentMode::Immediate,
+            PresentPreference::Mailbox => wgpu::PresentMode::Mailbox,
+        };
+        if available.contains(&mode) {
+            mode
+        } else {
+            log::warn!("Present mode {mode:?} is not supported, falling back to Fifo");
+            wgpu::PresentMode::Fifo
+        }
+    }
+}
+
+/// The polygon mode to use for a pipeline, falling back to fill when `preferred` needs a
+/// feature the device doesn't have
+pub fn polygon_mode(device: &wgpu::Device, preferred: wgpu::PolygonMode) -> wgpu::PolygonMode {
+    let feature = match preferred {
+        wgpu::PolygonMode::Fill => return preferred,
+        wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+        wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+    };
+    if device.features().contains(feature) {
+        preferred
+    } else {
+        wgpu::PolygonMode::Fill
+    }
+}
+
+/// Checks if vertex shaders can read storage buffers, which downlevel backends like GLES may
+/// not support
+pub fn vertex_storage(adapter: &wgpu::Adapter) -> bool {
+    let supported = adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+        && adapter.limits().max_storage_buffers_per_shader_stage > 0;
+    if !supported {
+        log::warn!(
+            "Vertex shader storage buffers are not supported, falling back to vertex colors"
+        );
+    }
+    supported
+}
+
 pub fn surface_config(
     surface: &wgpu::Surface<'static>,
     adapter: &wgpu::Adapter,
+    present: PresentPreference,
 ) -> wgpu::SurfaceConfiguration {
     let caps = surface.get_capabilities(adapter);
     let surface_format = caps
@@ -80,18 +200,63 @@ pub fn surface_config(
         .copied()
         .unwrap_or(caps.formats[0]);
 
+    // copying from the surface is needed for screenshots
+    let usage =
+        wgpu::TextureUsages::RENDER_ATTACHMENT | (caps.usages & wgpu::TextureUsages::COPY_SRC);
+
     wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        usage,
         format: surface_format,
         width: 0,
         height: 0,
-        present_mode: caps.present_modes[0],
+        present_mode: present.present_mode(&caps.present_modes),
         alpha_mode: caps.alpha_modes[0],
         view_formats: vec![],
         desired_maximum_frame_latency: 2,
     }
 }
 
+/// Configuration for rendering into an offscreen texture that can be copied from, in place of
+/// a surface
+pub fn offscreen_config(width: u32, height: u32) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width,
+        height,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        view_formats: vec![],
+        desired_maximum_frame_latency: 2,
+    }
+}
+
+/// Whether a target of `format` takes linear colors, because it encodes them to sRGB itself
+/// or because it is a float HDR format. Other targets get colors that are already sRGB.
+pub fn linear_target(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::*;
+    format.is_srgb() || matches!(format, Rgba16Float | Rgba32Float)
+}
+
+/// The MSAA sample count, or 1 if the adapter can't multisample the surface or depth format
+pub fn sample_count(adapter: &wgpu::Adapter, config: &wgpu::SurfaceConfiguration) -> u32 {
+    let supported = |format| {
+        adapter
+            .get_texture_format_features(format)
+            .flags
+            .sample_count_supported(MSAA_SAMPLES)
+    };
+    if supported(config.format) && supported(camera::DEPTH_FORMAT) {
+        MSAA_SAMPLES
+    } else {
+        log::warn!(
+            "{MSAA_SAMPLES}x MSAA is not supported for {:?}",
+            config.format
+        );
+        1
+    }
+}
+
 pub fn configure_surface(
     surface: &wgpu::Surface<'static>,
     device: &wgpu::Device,
@@ -118,3 +283,49 @@ pub fn shader(device: &wgpu::Device, file: impl AsRef<Path>) -> Result<wgpu::Sha
         source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_contents)),
     }))
 }
+
+/// Runs `f`, turning any wgpu validation error it causes into an error instead of a panic
+pub fn validated<T>(device: &wgpu::Device, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = f();
+    if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+        anyhow::bail!("{err}");
+    }
+    result
+}
+
+/// Backends `offscreen_adapter` falls back to without an adapter for `BACKENDS`, so the tests
+/// also run on software rasterizers like llvmpipe
+const FALLBACK_BACKENDS: wgpu::Backends = wgpu::Backends::GL;
+
+/// Adapter for rendering without a window. With `fallback` it is looked for on
+/// `FALLBACK_BACKENDS` as well, if there is none for `BACKENDS`.
+pub fn offscreen_adapter(fallback: bool) -> Result<wgpu::Adapter> {
+    let primary = adapter(&instance(), None);
+    if !fallback {
+        return primary;
+    }
+    primary.or_else(|err| {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: FALLBACK_BACKENDS,
+            ..Default::default()
+        });
+        pollster::block_on(adapter_async(&instance, None))
+            .with_context(|| format!("{err}, nor for {FALLBACK_BACKENDS:?}"))
+    })
+}
+
+/// Tells that a test passes without running, on stderr directly because the test harness
+/// captures `eprintln!` of passing tests
+pub fn skip_test(reason: impl std::fmt::Display) {
+    use std::io::Write;
+    writeln!(std::io::stderr(), "skipping a GPU test: {reason}").ok();
+}
+
+/// Device for the tests that need a GPU, `None` without any adapter so the tests can pass
+/// without one
+#[cfg(test)]
+pub fn test_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let device_queue = offscreen_adapter(true).and_then(|adapter| device_queue(&adapter));
+    device_queue.inspect_err(|err| skip_test(err)).ok()
+}
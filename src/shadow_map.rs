@@ -0,0 +1,214 @@
+use crate::{utils::*, RADIUS};
+
+/// Texels along each side of the shadow map
+const SIZE: u32 = 2048;
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Radius of the sphere the shadow map covers across the light, the planet with its highest
+/// mountains fits into it
+const BOUNDS: f64 = 1.05 * RADIUS;
+/// Receivers are moved this many texels along their normal before they are looked up, so the
+/// curved surface doesn't shadow itself where it turns away from the light
+const NORMAL_OFFSET_TEXELS: f32 = 1.5;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    /// from world space into the clip space of the shadow map
+    view_projection: Mat4,
+    /// in world units
+    normal_offset: f32,
+    /// size of a texel in uv coordinates, the step of the PCF kernel
+    texel_size: f32,
+    _padding: [f32; 2],
+}
+
+/// Depth of the scene seen from the sun, a single orthographic view along the sun direction
+/// covering the planet. Casters are drawn into it in a pass before the scene, receivers sample
+/// it with a comparison sampler.
+pub struct ShadowMap {
+    view: wgpu::TextureView,
+    uniform_buffer: Buffer<ShadowUniform>,
+    /// only the light matrix, for the pipelines drawing into the shadow map
+    light_bind_group_layout: wgpu::BindGroupLayout,
+    light_bind_group: wgpu::BindGroup,
+    /// the light matrix with the shadow map and its sampler, for the scene pipelines
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("shadow_map"),
+                size: wgpu::Extent3d {
+                    width: SIZE,
+                    height: SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow_map_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("shadow_uniform_buffer"),
+            len: 1,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let uniform_entry = |visibility| wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let light_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[uniform_entry(wgpu::ShaderStages::VERTEX)],
+                label: Some("light_bind_group_layout"),
+            });
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &light_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("light_bind_group"),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                uniform_entry(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("shadow_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        });
+
+        ShadowMap {
+            view,
+            uniform_buffer,
+            light_bind_group_layout,
+            light_bind_group,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Points the shadow map along `sun_dir`, towards the sun. `reach` is the distance from the
+    /// center of the planet up to which casters are drawn, e.g. to include a moon.
+    pub fn update(&self, queue: &wgpu::Queue, sun_dir: Vec3, reach: f64) {
+        let reach = reach.max(BOUNDS) as f32;
+        let sun_dir = sun_dir.normalize();
+        let up = match sun_dir.z.abs() < 0.99 {
+            true => Vec3::Z,
+            false => Vec3::X,
+        };
+        let view = Mat4::look_at_rh(sun_dir * reach, Vec3::ZERO, up);
+        let bounds = BOUNDS as f32;
+        // casters and receivers on either side of the planet, so it shadows the moon as well
+        let projection = Mat4::orthographic_rh(-bounds, bounds, -bounds, bounds, 0., 2. * reach);
+        let texel_size = 1. / SIZE as f32;
+        let uniform = ShadowUniform {
+            view_projection: projection * view,
+            normal_offset: NORMAL_OFFSET_TEXELS * 2. * bounds * texel_size,
+            texel_size,
+            _padding: [0.; 2],
+        };
+        queue.write_typed_buffer(&self.uniform_buffer, 0, &[uniform]);
+    }
+
+    pub fn depth_stencil_attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.),
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }
+    }
+
+    pub fn light_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.light_bind_group_layout
+    }
+
+    pub fn light_bind_group(&self) -> &wgpu::BindGroup {
+        &self.light_bind_group
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+/// Depth state of the pipelines drawing into the shadow map. The slope scaled bias keeps the
+/// surfaces at grazing angles to the sun from shadowing themselves.
+pub fn depth_stencil_state() -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState {
+            constant: 2,
+            slope_scale: 2.,
+            clamp: 0.,
+        },
+    }
+}
@@ -0,0 +1,153 @@
+use crate::post_process::HDR_FORMAT;
+use crate::utils::*;
+use camera::{DEPTH_FORMAT, FAR_DEPTH};
+
+fn target_size(config: &wgpu::SurfaceConfiguration) -> wgpu::Extent3d {
+    // a minimized window has a zero sized surface, but textures need at least one texel
+    wgpu::Extent3d {
+        width: config.width.max(1),
+        height: config.height.max(1),
+        depth_or_array_layers: 1,
+    }
+}
+
+fn texture(
+    device: &wgpu::Device,
+    label: &str,
+    size: wgpu::Extent3d,
+    sample_count: u32,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// The offscreen textures the scene passes render into, all at the size of the surface. The
+/// HDR target is read by the post process afterwards, the depth target by the depth debug view.
+pub struct Targets {
+    sample_count: u32,
+    size: wgpu::Extent3d,
+    /// multisampled color target that gets resolved into the HDR target, only used with MSAA
+    msaa: Option<wgpu::TextureView>,
+    hdr_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+}
+
+impl Targets {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Self {
+        let size = target_size(config);
+        let msaa = (sample_count > 1).then(|| {
+            texture(
+                device,
+                "msaa_texture",
+                size,
+                sample_count,
+                HDR_FORMAT,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+        });
+        let hdr_view = texture(
+            device,
+            "hdr_texture",
+            size,
+            1,
+            HDR_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let depth_view = texture(
+            device,
+            "depth_texture",
+            size,
+            sample_count,
+            DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+
+        Targets {
+            sample_count,
+            size,
+            msaa,
+            hdr_view,
+            depth_view,
+        }
+    }
+
+    /// Recreates all targets together if their size doesn't match the surface anymore. Returns
+    /// whether they were recreated, bind groups reading them have to be rebuilt then. The old
+    /// textures are dropped with the views replaced here, so repeated resizes don't pile them
+    /// up.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> bool {
+        if self.size == target_size(config) {
+            return false;
+        }
+        *self = Targets::new(device, config, self.sample_count);
+        true
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Color attachment rendering into the HDR target, through the multisampled target when
+    /// MSAA is used
+    pub fn color_attachment(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        let (view, resolve_target) = match &self.msaa {
+            Some(msaa_view) => (msaa_view, Some(&self.hdr_view)),
+            None => (&self.hdr_view, None),
+        };
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+
+    /// Depth attachment that is cleared, or loaded to keep the depth of an earlier pass
+    pub fn depth_stencil_attachment(
+        &self,
+        load: bool,
+    ) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.depth_view,
+            depth_ops: Some(wgpu::Operations {
+                load: match load {
+                    true => wgpu::LoadOp::Load,
+                    false => wgpu::LoadOp::Clear(FAR_DEPTH),
+                },
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }
+    }
+
+    /// The resolved scene, it can be bound as a texture in passes after the scene
+    pub fn hdr_view(&self) -> &wgpu::TextureView {
+        &self.hdr_view
+    }
+
+    /// The depth target, it can be bound as a texture in passes that don't render into it
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{setup, utils::*};
+use anyhow::Result;
+use wgpu::util::DeviceExt;
+
+pub const SHADER: &str = "shaders/text_overlay.wgsl";
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Screen pixels per glyph texel
+const SCALE: f32 = 2.;
+/// Distance of the text from the top left corner of the screen, in pixels
+const MARGIN: f32 = 8.;
+/// Glyph quads that fit into the vertex buffer, every character takes two with its shadow.
+/// Longer text is cut off.
+const MAX_QUADS: usize = 256;
+const TEXT_COLOR: Vec3 = vec3(1., 1., 1.);
+/// Drawn one glyph texel down and to the right of the text so it stays readable on the ice caps
+const SHADOW_COLOR: Vec3 = vec3(0., 0., 0.);
+
+/// Characters of the atlas with their rows from top to bottom, the lowest 5 bits of a row are
+/// its texels from left to right. Characters not in the atlas are drawn as the space.
+#[rustfmt::skip]
+const GLYPHS: &[(char, [u8; GLYPH_HEIGHT as usize])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextVertex {
+    /// in pixels from the top left corner of the screen
+    position: Vec2,
+    /// in texels of the glyph atlas
+    texel: Vec2,
+    color: Vec3,
+}
+
+impl TextVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x3];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OverlayUniform {
+    screen_size: Vec2,
+    _padding: [f32; 2],
+}
+
+/// The glyphs side by side in a single row, one byte per texel
+fn atlas_texels() -> Vec<u8> {
+    let width = GLYPHS.len() * GLYPH_WIDTH as usize;
+    let mut texels = vec![0; width * GLYPH_HEIGHT as usize];
+    for (glyph, (_, rows)) in GLYPHS.iter().enumerate() {
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH as usize {
+                if row & (1 << (GLYPH_WIDTH as usize - 1 - x)) != 0 {
+                    texels[y * width + glyph * GLYPH_WIDTH as usize + x] = 255;
+                }
+            }
+        }
+    }
+    texels
+}
+
+/// Quads of the glyphs of `text`, with its shadow first so the text is drawn over it
+fn text_vertices(text: &str) -> Vec<TextVertex> {
+    let advance = (GLYPH_WIDTH + 1) as f32 * SCALE;
+    let line_height = (GLYPH_HEIGHT + 2) as f32 * SCALE;
+    let size = vec2(GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32);
+
+    let mut glyphs = Vec::new();
+    for (line, text) in text.lines().enumerate() {
+        for (column, c) in text.chars().enumerate() {
+            let glyph = GLYPHS
+                .iter()
+                .position(|&(glyph, _)| glyph == c.to_ascii_uppercase())
+                .unwrap_or(0);
+            if glyph != 0 {
+                let position = vec2(column as f32 * advance, line as f32 * line_height);
+                glyphs.push((position + MARGIN, glyph));
+            }
+        }
+    }
+    glyphs.truncate(MAX_QUADS / 2);
+
+    let mut vertices = Vec::with_capacity(glyphs.len() * 8);
+    for (offset, color) in [(SCALE, SHADOW_COLOR), (0., TEXT_COLOR)] {
+        for &(position, glyph) in &glyphs {
+            let texel = vec2((glyph as u32 * GLYPH_WIDTH) as f32, 0.);
+            for corner in [vec2(0., 0.), vec2(0., 1.), vec2(1., 0.), vec2(1., 1.)] {
+                vertices.push(TextVertex {
+                    position: position + offset + corner * size * SCALE,
+                    texel: texel + corner * size,
+                    color,
+                });
+            }
+        }
+    }
+    vertices
+}
+
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Text Overlay Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[TextVertex::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        // drawn in its own pass straight into the resolved frame, on top of everything
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// A few lines of text in the top left corner of the screen, drawn from a bitmap font
+pub struct TextOverlay {
+    pub visible: bool,
+    vertex_buffer: Buffer<TextVertex>,
+    index_buffer: Buffer<u16>,
+    /// number of glyph quads in the vertex buffer, including the shadow
+    quads: usize,
+    uniform_buffer: Buffer<OverlayUniform>,
+    bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl TextOverlay {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> Result<Self> {
+        let vertex_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("text_overlay_vertex_buffer"),
+            len: 4 * MAX_QUADS,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let indices = (0..MAX_QUADS as u16)
+            .flat_map(|quad| [0, 1, 2, 2, 1, 3].map(|corner| 4 * quad + corner))
+            .collect::<Vec<_>>();
+        let index_buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("text_overlay_index_buffer"),
+            contents: &indices,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let uniform_buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("text_overlay_uniform_buffer"),
+            len: 1,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let atlas = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("text_overlay_atlas"),
+                size: wgpu::Extent3d {
+                    width: GLYPHS.len() as u32 * GLYPH_WIDTH,
+                    height: GLYPH_HEIGHT,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &atlas_texels(),
+        );
+        let atlas_view = atlas.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let shader = setup::shader(device, SHADER)?;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("text_overlay_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&atlas_view),
+                },
+            ],
+            label: Some("text_overlay_bind_group"),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Text Overlay Render Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let format = config.format;
+        let render_pipeline = render_pipeline(device, &render_pipeline_layout, &shader, format);
+
+        Ok(TextOverlay {
+            visible: false,
+            vertex_buffer,
+            index_buffer,
+            quads: 0,
+            uniform_buffer,
+            bind_group,
+            render_pipeline_layout,
+            format,
+            render_pipeline,
+        })
+    }
+
+    /// Recompiles the shader and rebuilds the pipeline, keeping the current pipeline on errors
+    pub fn reload_shader(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.render_pipeline = setup::validated(device, || {
+            let shader = setup::shader(device, SHADER)?;
+            Ok(render_pipeline(
+                device,
+                &self.render_pipeline_layout,
+                &shader,
+                self.format,
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// Replaces the text, lines are separated by newlines
+    pub fn update(&mut self, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration, text: &str) {
+        let uniform = OverlayUniform {
+            screen_size: vec2(config.width as f32, config.height as f32),
+            _padding: [0.; 2],
+        };
+        queue.write_typed_buffer(&self.uniform_buffer, 0, &[uniform]);
+
+        let vertices = text_vertices(text);
+        queue.write_typed_buffer(&self.vertex_buffer, 0, &vertices);
+        self.quads = vertices.len() / 4;
+    }
+}
+
+pub fn render(render_pass: &mut wgpu::RenderPass, overlay: &TextOverlay) {
+    render_pass.set_pipeline(&overlay.render_pipeline);
+    render_pass.set_bind_group(0, &overlay.bind_group, &[]);
+    render_pass.set_typed_vertex_buffer(0, &overlay.vertex_buffer);
+    render_pass.set_typed_index_buffer(&overlay.index_buffer);
+    render_pass.draw_indexed(0..6 * overlay.quads as u32, 0, 0..1);
+}
+
+/// Frames per second and frame time, averaged over half a second so the numbers are readable
+#[derive(Default)]
+pub struct FrameStats {
+    pub fps: f64,
+    /// in milliseconds
+    pub frame_time: f64,
+    elapsed: f64,
+    frames: u32,
+}
+
+impl FrameStats {
+    const WINDOW: f64 = 0.5;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a frame that took `dt` seconds, returns whether the averages were updated
+    pub fn tick(&mut self, dt: f64) -> bool {
+        self.elapsed += dt;
+        self.frames += 1;
+        if self.elapsed < Self::WINDOW {
+            return false;
+        }
+        self.fps = self.frames as f64 / self.elapsed;
+        self.frame_time = 1000. * self.elapsed / self.frames as f64;
+        self.elapsed = 0.;
+        self.frames = 0;
+        true
+    }
+}
@@ -28,7 +28,10 @@ pub struct TypedBufferInitDescriptor<'a, T: bytemuck::Pod + bytemuck::Zeroable>
 /// A GPU buffer with associated type and length (like a dynamically allocated array)
 pub struct Buffer<T> {
     buffer: wgpu::Buffer,
+    label: Option<String>,
     pub len: usize,
+    /// number of elements the GPU buffer has room for, at least `len`
+    capacity: usize,
     _type: PhantomData<T>,
 }
 
@@ -39,6 +42,75 @@ impl<T> std::ops::Deref for Buffer<T> {
     }
 }
 
+impl<T: bytemuck::Pod + bytemuck::Zeroable> Buffer<T> {
+    /// Sets the length to `len`, reallocating the GPU buffer with `usage` only if it is too
+    /// small. The contents are lost when the buffer is reallocated.
+    pub fn ensure_capacity(
+        &mut self,
+        device: &wgpu::Device,
+        len: usize,
+        usage: wgpu::BufferUsages,
+    ) {
+        if len > self.capacity {
+            *self = device.create_typed_buffer(&TypedBufferDescriptor {
+                label: self.label.as_deref(),
+                len,
+                usage,
+                mapped_at_creation: false,
+            });
+        }
+        self.len = len;
+    }
+
+    /// Shortens the length without touching the GPU buffer, does nothing if `len` is larger
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Copies the contents back to the CPU, blocking until the GPU is done. The buffer needs
+    /// `COPY_SRC` usage.
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<T>, wgpu::BufferAsyncError> {
+        if self.len == 0 {
+            return Ok(Vec::new());
+        }
+        let staging: Buffer<T> = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("Staging Buffer"),
+            len: self.len,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        let size = self.len * std::mem::size_of::<T>();
+        // both buffers have room for the rounded up size, see `create_typed_buffer`
+        let copy_size = (size as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging.buffer, 0, copy_size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let data = bytemuck::pod_collect_to_vec(&slice.get_mapped_range()[..size]);
+        staging.unmap();
+        Ok(data)
+    }
+}
+
 pub trait BufferDeviceExt<'a, T: bytemuck::Pod + bytemuck::Zeroable> {
     fn create_typed_buffer(&self, desc: &TypedBufferDescriptor) -> Buffer<T>;
     fn create_typed_buffer_init(&self, desc: &TypedBufferInitDescriptor<'a, T>) -> Buffer<T>;
@@ -46,6 +118,8 @@ pub trait BufferDeviceExt<'a, T: bytemuck::Pod + bytemuck::Zeroable> {
 
 pub trait BufferQueueExt<T: bytemuck::Pod + bytemuck::Zeroable> {
     fn write_typed_buffer(&self, buffer: &Buffer<T>, offset: u64, data: &[T]);
+    /// Writes `data` starting at the element `index` instead of a byte offset
+    fn write_typed_buffer_at(&self, buffer: &Buffer<T>, index: usize, data: &[T]);
 }
 
 pub trait BufferVertexRenderPassExt<T> {
@@ -59,23 +133,29 @@ pub trait BufferIndexRenderPassExt<T> {
 impl<'a, T: Sized + bytemuck::Pod + bytemuck::Zeroable> BufferDeviceExt<'a, T> for wgpu::Device {
     fn create_typed_buffer(&self, desc: &TypedBufferDescriptor) -> Buffer<T> {
         let len = desc.len;
+        let label = desc.label.map(str::to_owned);
         let desc = wgpu::BufferDescriptor {
             label: desc.label,
-            size: desc.len as u64 * std::mem::size_of::<T>() as u64,
+            // room for copies, which have to be a multiple of 4 bytes
+            size: (desc.len as u64 * std::mem::size_of::<T>() as u64)
+                .next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
             usage: desc.usage,
             mapped_at_creation: desc.mapped_at_creation,
         };
         let buffer = self.create_buffer(&desc);
         Buffer {
             buffer,
+            label,
             len,
+            capacity: len,
             _type: Default::default(),
         }
     }
 
     fn create_typed_buffer_init(&self, desc: &TypedBufferInitDescriptor<'a, T>) -> Buffer<T> {
         let len = desc.contents.len();
-        let contents = bytemuck::cast_slice(&desc.contents);
+        let label = desc.label.map(str::to_owned);
+        let contents = bytemuck::cast_slice(desc.contents);
         let desc = wgpu::util::BufferInitDescriptor {
             label: desc.label,
             contents,
@@ -84,7 +164,9 @@ impl<'a, T: Sized + bytemuck::Pod + bytemuck::Zeroable> BufferDeviceExt<'a, T> f
         let buffer = <wgpu::Device as DeviceExt>::create_buffer_init(self, &desc);
         Buffer {
             buffer,
+            label,
             len,
+            capacity: len,
             _type: Default::default(),
         }
     }
@@ -92,8 +174,20 @@ impl<'a, T: Sized + bytemuck::Pod + bytemuck::Zeroable> BufferDeviceExt<'a, T> f
 
 impl<T: bytemuck::Pod + bytemuck::Zeroable> BufferQueueExt<T> for wgpu::Queue {
     fn write_typed_buffer(&self, buffer: &Buffer<T>, offset: u64, data: &[T]) {
+        debug_assert!(
+            offset as usize + std::mem::size_of_val(data) <= buffer.len * std::mem::size_of::<T>(),
+            "writing {} elements at byte offset {offset} overflows buffer {:?} of length {}",
+            data.len(),
+            buffer.label.as_deref().unwrap_or("<unlabeled>"),
+            buffer.len,
+        );
         self.write_buffer(&buffer.buffer, offset, bytemuck::cast_slice(data));
     }
+
+    fn write_typed_buffer_at(&self, buffer: &Buffer<T>, index: usize, data: &[T]) {
+        let offset = (index * std::mem::size_of::<T>()) as u64;
+        self.write_typed_buffer(buffer, offset, data);
+    }
 }
 
 impl<'a, T: bytemuck::Pod + bytemuck::Zeroable> BufferVertexRenderPassExt<T>
@@ -115,3 +209,56 @@ impl<'a> BufferIndexRenderPassExt<u32> for wgpu::RenderPass<'a> {
         self.set_index_buffer(buffer.buffer.slice(..), wgpu::IndexFormat::Uint32);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // the bounds are only checked by a debug assertion
+    #[cfg_attr(not(debug_assertions), ignore)]
+    fn writing_past_the_end_panics() {
+        let Some((device, queue)) = crate::setup::test_device() else {
+            return;
+        };
+        let buffer = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("Test Buffer"),
+            len: 4,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // like `Background::update_screen_quad`, which fills its buffer exactly
+        queue.write_typed_buffer(&buffer, 0, &[0f32; 4]);
+
+        let overflow = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            queue.write_typed_buffer(&buffer, 0, &[0f32; 5])
+        }));
+        let message = overflow.expect_err("writing 5 elements into 4 didn't panic");
+        let message = message.downcast_ref::<String>().unwrap();
+        assert!(message.contains("\"Test Buffer\""), "{message}");
+    }
+
+    #[test]
+    fn read_returns_what_was_written() {
+        let Some((device, queue)) = crate::setup::test_device() else {
+            return;
+        };
+        let usage = wgpu::BufferUsages::COPY_SRC;
+        let empty: Buffer<u32> = device.create_typed_buffer(&TypedBufferDescriptor {
+            label: Some("Empty Test Buffer"),
+            len: 0,
+            usage,
+            mapped_at_creation: false,
+        });
+        assert!(empty.read(&device, &queue).unwrap().is_empty());
+
+        // 6 bytes, not a multiple of the copy alignment
+        let contents = [1u16, 2, 3];
+        let buffer = device.create_typed_buffer_init(&TypedBufferInitDescriptor {
+            label: Some("Test Buffer"),
+            contents: &contents,
+            usage,
+        });
+        assert_eq!(buffer.read(&device, &queue).unwrap(), contents);
+    }
+}
@@ -1,15 +1,57 @@
 use crate::utils::*;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+use std::path::Path;
+use winit::event::ElementState;
+use winit::keyboard::KeyCode;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Projection {
+    /// vertical field of view in radians
+    Perspective { fov_y: f32 },
+    /// height of the view volume in world units
+    Orthographic { height: f32 },
+}
 
 pub struct Camera {
     pub position: DVec3,
     pub look_dir: Vec3,
+    /// up before the roll, kept by the orbit and set by the other modes
     pub up: Vec3,
-    pub fov_y: f32,
+    /// rotation around the view direction in radians, positive rolls to the right. It is
+    /// applied on top of `up`, so it survives the camera modes moving the camera.
+    pub roll: f32,
+    pub projection: Projection,
     pub z_near: f32,
     pub z_far: f32,
     aspect: f32,
-    depth_texture: wgpu::Texture,
-    depth_view: wgpu::TextureView,
+    /// flight started with `focus_on`, moved along by `update_flight`
+    flight: Option<Flight>,
+}
+
+/// Flight around the origin from one direction and distance to another
+#[derive(Clone, Copy, Debug)]
+struct Flight {
+    rotation: DQuat,
+    from: DVec3,
+    from_distance: f64,
+    to_distance: f64,
+    start: f64,
+    duration: f64,
+}
+
+/// The pose and projection of a camera, as written by `Camera::save`
+#[derive(Serialize, Deserialize)]
+struct CameraFile {
+    position: [f64; 3],
+    look_dir: [f32; 3],
+    up: [f32; 3],
+    #[serde(default)]
+    roll: f32,
+    projection: Projection,
+    z_near: f32,
+    z_far: f32,
 }
 
 #[repr(C)]
@@ -20,68 +62,555 @@ pub struct CameraUniform {
     position: PackedVec3,
     z_near: f32,
     z_far: f32,
-    _padding: u64,
+    /// 1 if the depth goes from 1 at the near plane to 0 at the far plane
+    reverse_z: u32,
+    _padding: u32,
+    /// normalized direction pointing towards the sun
+    light_dir: Vec3,
+    specular_exponent: f32,
+    specular_intensity: f32,
+    _specular_padding: [f32; 3],
+}
+
+/// Blinn-Phong highlight of the sun on water
+#[derive(Clone, Copy, Debug)]
+pub struct Specular {
+    /// higher is a smaller, sharper highlight
+    pub exponent: f32,
+    pub intensity: f32,
+}
+
+impl Default for Specular {
+    fn default() -> Self {
+        Specular {
+            exponent: 64.,
+            intensity: 0.5,
+        }
+    }
 }
 
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Store depth reversed, 1 at the near plane and 0 at the far plane. Floats are much denser
+/// near 0, which makes up for the precision lost to the perspective far away from the camera.
+pub const REVERSE_Z: bool = true;
+/// Depth of the near and far plane
+const NEAR_DEPTH: f32 = if REVERSE_Z { 1. } else { 0. };
+pub const FAR_DEPTH: f32 = if REVERSE_Z { 0. } else { 1. };
+/// Narrowest and widest vertical field of view, the frustum degenerates outside of it
+pub const MIN_FOV_Y: f32 = 20f32.to_radians();
+pub const MAX_FOV_Y: f32 = 100f32.to_radians();
+
 impl Camera {
-    pub fn new(
-        device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        position: DVec3,
-        look_dir: Vec3,
-    ) -> Self {
+    pub fn new(config: &wgpu::SurfaceConfiguration, position: DVec3, look_dir: Vec3) -> Self {
         let aspect = config.width as f32 / config.height as f32;
-        let size = wgpu::Extent3d {
-            width: config.width.max(1),
-            height: config.height.max(1),
-            depth_or_array_layers: 1,
-        };
-        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("camera_depth_texture"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         Camera {
             position,
             look_dir,
             up: glam::Vec3::Z,
+            roll: 0.,
             aspect,
-            fov_y: 45f32.to_radians(),
+            projection: Projection::Perspective {
+                fov_y: 45f32.to_radians(),
+            },
             z_near: 0.001,
             z_far: 1_000_000.0,
-            depth_texture,
-            depth_view,
+            flight: None,
         }
     }
 
-    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
-        let new_camera = Camera::new(device, config, self.position, self.look_dir);
-        self.aspect = new_camera.aspect;
-        self.depth_texture = new_camera.depth_texture;
-        self.depth_view = new_camera.depth_view;
+    /// Writes the pose and projection as JSON, so the same view can be restored with `load`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = CameraFile {
+            position: self.position.to_array(),
+            look_dir: self.look_dir.to_array(),
+            up: self.up.to_array(),
+            roll: self.roll,
+            projection: self.projection,
+            z_near: self.z_near,
+            z_far: self.z_far,
+        };
+        let json = serde_json::to_string_pretty(&file)?;
+        std::fs::write(path, json).with_context(|| format!("failed to write {}", path.display()))
     }
 
-    pub fn depth_stencil_attachment(&self) -> wgpu::RenderPassDepthStencilAttachment {
-        wgpu::RenderPassDepthStencilAttachment {
-            view: &self.depth_view,
-            depth_ops: Some(wgpu::Operations {
-                load: wgpu::LoadOp::Clear(1.0),
-                store: wgpu::StoreOp::Store,
-            }),
-            stencil_ops: None,
+    /// Reads a camera written by `save`, with the aspect ratio of `config`. The directions are
+    /// normalized again and the field of view is clamped, so edited files stay usable.
+    pub fn load(path: &Path, config: &wgpu::SurfaceConfiguration) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let file: CameraFile = serde_json::from_str(&json)
+            .with_context(|| format!("invalid camera {}", path.display()))?;
+
+        let look_dir = Vec3::from(file.look_dir)
+            .try_normalize()
+            .context("the camera has no view direction")?;
+        let up = Vec3::from(file.up)
+            .try_normalize()
+            .context("the camera has no up direction")?;
+        anyhow::ensure!(
+            look_dir.cross(up).length() > 1e-3,
+            "the camera looks along its up direction"
+        );
+        anyhow::ensure!(
+            0. < file.z_near && file.z_near < file.z_far,
+            "the near plane {} is not between 0 and the far plane {}",
+            file.z_near,
+            file.z_far,
+        );
+
+        let mut camera = Camera::new(config, DVec3::from(file.position), look_dir);
+        camera.up = up;
+        camera.roll = file.roll;
+        camera.projection = file.projection;
+        if let Some(fov_y) = camera.fov_y() {
+            camera.set_fov_y(fov_y);
         }
+        camera.z_near = file.z_near;
+        camera.z_far = file.z_far;
+        Ok(camera)
+    }
+
+    /// Updates the aspect ratio for the new surface size, all other settings are kept
+    pub fn resize(&mut self, config: &wgpu::SurfaceConfiguration) {
+        self.aspect = config.width as f32 / config.height as f32;
     }
 
     pub fn aspect_ratio(&self) -> f32 {
         self.aspect
     }
+
+    /// Starts flying around the origin until the camera is `distance` away in `direction`,
+    /// looking back at the origin. The flight starts at time `start` and takes `duration`
+    /// seconds of the times passed to `update_flight`.
+    pub fn focus_on(&mut self, direction: DVec3, distance: f64, start: f64, duration: f64) {
+        let from = self.position.normalize_or(DVec3::Z);
+        self.flight = Some(Flight {
+            rotation: DQuat::from_rotation_arc(from, direction.normalize()),
+            from,
+            from_distance: self.position.length(),
+            to_distance: distance,
+            start,
+            duration,
+        });
+    }
+
+    pub fn cancel_flight(&mut self) {
+        self.flight = None;
+    }
+
+    /// Moves the camera to where the flight is at time `t`, eased in and out. The flight ends
+    /// once it arrived.
+    pub fn update_flight(&mut self, t: f64) {
+        let Some(flight) = self.flight else {
+            return;
+        };
+        let progress = ((t - flight.start) / flight.duration).clamp(0., 1.);
+        let eased = progress * progress * (3. - 2. * progress);
+
+        let direction = DQuat::IDENTITY.slerp(flight.rotation, eased) * flight.from;
+        let distance = flight.from_distance + (flight.to_distance - flight.from_distance) * eased;
+        self.position = direction * distance;
+        self.look_dir = -direction.as_vec3().normalize();
+        // z stays up, except right above the poles where it is the view direction and the
+        // last up is kept
+        let up = Vec3::Z.reject_from(self.look_dir);
+        let up = match up.length() > 1e-3 {
+            true => up,
+            false => self.up.reject_from(self.look_dir),
+        };
+        self.up = up.normalize_or(self.look_dir.any_orthonormal_vector());
+        if progress >= 1. {
+            self.flight = None;
+        }
+    }
+
+    /// Vertical field of view in radians, `None` for the orthographic projection
+    pub fn fov_y(&self) -> Option<f32> {
+        match self.projection {
+            Projection::Perspective { fov_y } => Some(fov_y),
+            Projection::Orthographic { .. } => None,
+        }
+    }
+
+    /// Sets the vertical field of view of the perspective projection, clamped between
+    /// `MIN_FOV_Y` and `MAX_FOV_Y`. The orthographic projection has none and is kept.
+    pub fn set_fov_y(&mut self, fov_y: f32) {
+        if let Projection::Perspective { fov_y: current } = &mut self.projection {
+            *current = fov_y.clamp(MIN_FOV_Y, MAX_FOV_Y);
+        }
+    }
+
+    /// Rolls the camera by `angle` radians around the view direction, positive to the right
+    pub fn roll_by(&mut self, angle: f32) {
+        self.roll = (self.roll + angle + PI).rem_euclid(TAU) - PI;
+    }
+
+    /// Snaps the roll to the nearest quarter turn, leveling the horizon of a slightly rolled
+    /// view
+    pub fn level(&mut self) {
+        self.roll = (self.roll / FRAC_PI_2).round() * FRAC_PI_2;
+    }
+
+    /// Up direction of the view, `up` rolled around the view direction and perpendicular to it
+    pub fn view_up(&self) -> Vec3 {
+        let up = self
+            .up
+            .reject_from(self.look_dir)
+            .normalize_or(self.look_dir.any_orthonormal_vector());
+        Quat::from_axis_angle(self.look_dir, self.roll) * up
+    }
+
+    /// View matrix with the camera at the origin. Everything is drawn relative to the camera,
+    /// positions are offset by the camera position in the shader, so the transform only holds
+    /// small values and keeps its f32 precision far away from the world origin.
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_to_rh(Vec3::ZERO, self.look_dir, self.view_up())
+    }
+
+    /// Perspective or orthographic projection. The planet shader replaces the resulting depth
+    /// with a logarithmic one.
+    pub fn projection_matrix(&self) -> Mat4 {
+        // swapping the planes maps the near plane to depth 1 and the far plane to 0
+        let (z_near, z_far) = match REVERSE_Z {
+            true => (self.z_far, self.z_near),
+            false => (self.z_near, self.z_far),
+        };
+        match self.projection {
+            Projection::Perspective { fov_y } => {
+                Mat4::perspective_rh(fov_y, self.aspect, z_near, z_far)
+            }
+            Projection::Orthographic { height } => {
+                let (half_width, half_height) = (0.5 * height * self.aspect, 0.5 * height);
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    z_near,
+                    z_far,
+                )
+            }
+        }
+    }
+
+    /// Switches between perspective and orthographic projection, keeping the size of the view
+    /// at the distance of the origin the same
+    pub fn toggle_projection(&mut self) {
+        let distance = self.position.length() as f32;
+        self.projection = match self.projection {
+            Projection::Perspective { fov_y } => Projection::Orthographic {
+                height: 2. * distance * (0.5 * fov_y).tan(),
+            },
+            Projection::Orthographic { height } => Projection::Perspective {
+                fov_y: 2. * (0.5 * height / distance).atan(),
+            },
+        };
+    }
+
+    /// Projection of camera relative positions into clip space
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// The six planes bounding the view volume as (normal, distance) with unit normals
+    /// pointing inwards, in camera relative coordinates like the view matrix. A point `p` is
+    /// inside when `plane.dot(p.extend(1.)) >= 0.` for all of them.
+    pub fn frustum_planes(&self) -> [Vec4; 6] {
+        let m = self.view_projection().transpose();
+        let (x, y, z, w) = (m.x_axis, m.y_axis, m.z_axis, m.w_axis);
+        // depth goes from 0 to 1, so the near and far planes are z >= 0 and z <= w
+        [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.truncate().length())
+    }
+
+    /// World space ray through the cursor, with the cursor and window size in pixels. The
+    /// unprojected points are relative to the camera, so the origin is offset by its position.
+    pub fn screen_ray(&self, cursor: Vec2, size: Vec2) -> (DVec3, Vec3) {
+        let ndc = vec2(2. * cursor.x / size.x - 1., 1. - 2. * cursor.y / size.y);
+        let inverse = self.view_projection().inverse();
+        let near = inverse.project_point3(ndc.extend(NEAR_DEPTH));
+        let far = inverse.project_point3(ndc.extend(FAR_DEPTH));
+        (self.position + near.as_dvec3(), (far - near).normalize())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CameraMode {
+    /// automatic orbit around the planet
+    #[default]
+    Orbit,
+    /// free flight, WASD + Space/Shift to move and the mouse to look around
+    Fly,
+    /// dragging with the right mouse button rotates the camera around the planet
+    Arcball,
+    /// the camera stays where it was put, after restoring a saved camera
+    Fixed,
+}
+
+/// Circle of the camera in orbit mode, around an axis through the center of the planet
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitParams {
+    /// unit axis the camera circles around
+    pub axis: DVec3,
+    /// distance of the camera from the axis
+    pub radius: f64,
+    /// angular speed in radians per second, positive turns clockwise seen from the tip of the
+    /// axis
+    pub speed: f64,
+    /// angle in radians the camera looks down onto the plane of the orbit from, towards the
+    /// axis
+    pub inclination: f64,
+}
+
+impl Default for OrbitParams {
+    /// 4 radii from the z axis and 1 radius above the equator
+    fn default() -> Self {
+        OrbitParams {
+            axis: DVec3::Z,
+            radius: 4. * crate::RADIUS,
+            speed: 0.1,
+            inclination: 0.25f64.atan(),
+        }
+    }
+}
+
+impl OrbitParams {
+    /// Position of the camera after `t` seconds. It starts as close to the y axis as the
+    /// plane of the orbit allows.
+    pub fn position(&self, t: f64) -> DVec3 {
+        let axis = self.axis.normalize();
+        let start = (DVec3::Y - axis * axis.y)
+            .try_normalize()
+            .unwrap_or_else(|| axis.any_orthonormal_vector());
+        let base = self.radius * (start + axis * self.inclination.tan());
+        DQuat::from_axis_angle(axis, -self.speed * t) * base
+    }
+}
+
+/// Drives the camera in fly mode. Owns the pitch/yaw of the view (yaw around the z axis,
+/// pitch above the xy plane) and the currently held movement keys.
+pub struct CameraController {
+    pub mode: CameraMode,
+    /// movement speed in world units per second
+    pub speed: f64,
+    /// rotation in radians per pixel of mouse motion
+    pub sensitivity: f32,
+    pitch: f32,
+    yaw: f32,
+    forward: f32,
+    backward: f32,
+    left: f32,
+    right: f32,
+    upward: f32,
+    downward: f32,
+    rotation: Vec2,
+    /// rotation of the camera around the origin in arcball mode, the camera looks along -z
+    /// and its up is y in the rotated frame
+    orientation: DQuat,
+    distance: f64,
+    /// arcball point and orientation at the start of the current drag
+    drag_start: Option<(DVec3, DQuat)>,
+    /// arcball point under the cursor
+    cursor: DVec3,
+}
+
+impl CameraController {
+    const MAX_PITCH: f32 = 89f32.to_radians();
+
+    pub fn new(speed: f64, sensitivity: f32) -> Self {
+        CameraController {
+            mode: CameraMode::default(),
+            speed,
+            sensitivity,
+            pitch: 0.,
+            yaw: 0.,
+            forward: 0.,
+            backward: 0.,
+            left: 0.,
+            right: 0.,
+            upward: 0.,
+            downward: 0.,
+            rotation: Vec2::ZERO,
+            orientation: DQuat::IDENTITY,
+            distance: 0.,
+            drag_start: None,
+            cursor: DVec3::Z,
+        }
+    }
+
+    /// Cycles between orbit, fly and arcball mode, a fixed camera continues in fly mode.
+    /// Entering fly mode picks up the current view direction so the view doesn't jump, arcball
+    /// mode keeps the camera position and turns it towards the planet.
+    pub fn toggle_mode(&mut self, camera: &mut Camera) {
+        self.mode = match self.mode {
+            CameraMode::Orbit | CameraMode::Fixed => {
+                // fly mode expects z to be up, a restored camera may be rolled
+                camera.up = Vec3::Z;
+                let look_dir = camera.look_dir.normalize();
+                self.pitch = look_dir.dot(camera.up).clamp(-1., 1.).asin();
+                self.yaw = look_dir.y.atan2(look_dir.x);
+                CameraMode::Fly
+            }
+            CameraMode::Fly => {
+                // orbit the origin at the current distance, looking at it from where we are
+                let back = camera.position.normalize();
+                let right = camera
+                    .up
+                    .as_dvec3()
+                    .cross(back)
+                    .try_normalize()
+                    .unwrap_or(back.any_orthonormal_vector());
+                let up = back.cross(right);
+                self.orientation = DQuat::from_mat3(&DMat3::from_cols(right, up, back));
+                self.distance = camera.position.length();
+                CameraMode::Arcball
+            }
+            CameraMode::Arcball => {
+                // the other modes expect z to be up
+                camera.up = Vec3::Z;
+                CameraMode::Orbit
+            }
+        };
+        self.rotation = Vec2::ZERO;
+        self.drag_start = None;
+    }
+
+    /// Returns true if the key is handled by the controller
+    pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
+        let amount = match state {
+            ElementState::Pressed => 1.,
+            ElementState::Released => 0.,
+        };
+        match key {
+            KeyCode::KeyW => self.forward = amount,
+            KeyCode::KeyS => self.backward = amount,
+            KeyCode::KeyA => self.left = amount,
+            KeyCode::KeyD => self.right = amount,
+            KeyCode::Space => self.upward = amount,
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => self.downward = amount,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn process_mouse_motion(&mut self, (dx, dy): (f64, f64)) {
+        if self.mode == CameraMode::Fly {
+            self.rotation += vec2(dx as f32, dy as f32);
+        }
+    }
+
+    /// Maps the cursor onto the arcball, a unit sphere facing the viewer that fills the
+    /// smaller dimension of the window. Outside of it the point lies on its silhouette.
+    pub fn process_cursor(&mut self, cursor: Vec2, size: Vec2) {
+        let scale = size.min_element() as f64;
+        let x = (2. * cursor.x as f64 - size.x as f64) / scale;
+        let y = (size.y as f64 - 2. * cursor.y as f64) / scale;
+        let r2 = x * x + y * y;
+        self.cursor = if r2 <= 1. {
+            dvec3(x, y, (1. - r2).sqrt())
+        } else {
+            dvec3(x, y, 0.).normalize()
+        };
+    }
+
+    /// Starts or ends an arcball drag
+    pub fn process_drag(&mut self, state: ElementState) {
+        self.drag_start = match state {
+            ElementState::Pressed => Some((self.cursor, self.orientation)),
+            ElementState::Released => None,
+        };
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: f64) {
+        match self.mode {
+            CameraMode::Fly => self.update_fly(camera, dt),
+            CameraMode::Arcball => self.update_arcball(camera),
+            CameraMode::Orbit | CameraMode::Fixed => {}
+        }
+    }
+
+    fn update_arcball(&mut self, camera: &mut Camera) {
+        if let Some((start, orientation)) = self.drag_start {
+            // dragging rotates the planet with the cursor, so the camera rotates the other way
+            let rotation = DQuat::from_rotation_arc(self.cursor, start);
+            self.orientation = (orientation * rotation).normalize();
+        }
+
+        camera.position = self.orientation * DVec3::Z * self.distance;
+        camera.look_dir = (self.orientation * -DVec3::Z).as_vec3().normalize();
+        camera.up = (self.orientation * DVec3::Y).as_vec3().normalize();
+    }
+
+    fn update_fly(&mut self, camera: &mut Camera, dt: f64) {
+        self.yaw -= self.rotation.x * self.sensitivity;
+        self.pitch -= self.rotation.y * self.sensitivity;
+        self.pitch = self.pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.rotation = Vec2::ZERO;
+
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        camera.look_dir = vec3(cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch).normalize();
+
+        // moves relative to the rolled view
+        let up = camera.view_up();
+        let right = camera.look_dir.cross(up).normalize();
+        let velocity = camera.look_dir * (self.forward - self.backward)
+            + right * (self.right - self.left)
+            + up * (self.upward - self.downward);
+        camera.position += velocity.as_dvec3() * self.speed * dt;
+    }
+}
+
+/// Size of `CameraUniform` for the bind group layouts. A WGSL struct that is larger or a
+/// buffer that is smaller then fails validation instead of reading garbage.
+pub const UNIFORM_SIZE: Option<wgpu::BufferSize> =
+    wgpu::BufferSize::new(std::mem::size_of::<CameraUniform>() as u64);
+
+/// Layout entry of the camera uniform, binding 0 of the first bind group of every scene
+/// pipeline. Both stages see it, so fragment shaders can light the scene with `light_dir` and
+/// the camera position. Only bind groups that hold more bindings, like the planet's, need the
+/// entry, the others use `bind_group_layout`.
+pub fn bind_group_layout_entry() -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: UNIFORM_SIZE,
+        },
+        count: None,
+    }
+}
+
+/// Binds the camera uniform to the entry of `bind_group_layout_entry`
+pub fn bind_group_entry(camera_uniform: &wgpu::Buffer) -> wgpu::BindGroupEntry<'_> {
+    wgpu::BindGroupEntry {
+        binding: 0,
+        resource: camera_uniform.as_entire_binding(),
+    }
+}
+
+/// Layout of a bind group holding only the camera uniform, the first bind group of the scene
+/// pipelines whose other bindings come in a bind group of their own
+pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[bind_group_layout_entry()],
+        label: Some("camera_bind_group_layout"),
+    })
+}
+
+/// Bind group of `bind_group_layout` with the camera uniform
+pub fn bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    camera_uniform: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[bind_group_entry(camera_uniform)],
+        label: Some("camera_bind_group"),
+    })
 }
 
 pub fn uniform_buffer(device: &wgpu::Device) -> Buffer<CameraUniform> {
@@ -96,11 +625,12 @@ pub fn uniform_buffer(device: &wgpu::Device) -> Buffer<CameraUniform> {
 pub fn write_view_projection(
     queue: &wgpu::Queue,
     camera: &Camera,
+    light_dir: Vec3,
+    specular: Specular,
     uniform_buffer: &Buffer<CameraUniform>,
 ) {
-    let view = glam::Mat4::look_to_rh(Vec3::ZERO, camera.look_dir, camera.up);
-    let projection =
-        glam::Mat4::perspective_rh(camera.fov_y, camera.aspect, camera.z_near, camera.z_far);
+    let view = camera.view_matrix();
+    let projection = camera.projection_matrix();
     let position = camera.position.into();
     queue.write_typed_buffer(
         uniform_buffer,
@@ -111,17 +641,115 @@ pub fn write_view_projection(
             position,
             z_near: camera.z_near,
             z_far: camera.z_far,
+            reverse_z: REVERSE_Z as u32,
             _padding: 0,
+            light_dir: light_dir.normalize(),
+            specular_exponent: specular.exponent,
+            specular_intensity: specular.intensity,
+            _specular_padding: [0.; 3],
         }],
     );
 }
 
 pub fn depth_stencil_state() -> wgpu::DepthStencilState {
     wgpu::DepthStencilState {
-        format: wgpu::TextureFormat::Depth32Float,
+        format: DEPTH_FORMAT,
         depth_write_enabled: true,
-        depth_compare: wgpu::CompareFunction::Less,
+        depth_compare: match REVERSE_Z {
+            true => wgpu::CompareFunction::Greater,
+            false => wgpu::CompareFunction::Less,
+        },
         stencil: wgpu::StencilState::default(),
         bias: wgpu::DepthBiasState::default(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup;
+
+    #[test]
+    fn resize_keeps_the_settings() {
+        let mut camera = Camera::new(&setup::offscreen_config(800, 600), DVec3::X, -Vec3::X);
+        camera.set_fov_y(60f32.to_radians());
+        camera.z_near = 0.5;
+        camera.z_far = 500.;
+
+        camera.resize(&setup::offscreen_config(1920, 1080));
+        assert_eq!(camera.aspect_ratio(), 1920. / 1080.);
+        assert_eq!(camera.fov_y(), Some(60f32.to_radians()));
+        assert_eq!((camera.z_near, camera.z_far), (0.5, 500.));
+        assert_eq!((camera.position, camera.look_dir), (DVec3::X, -Vec3::X));
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{name}-{}.json", std::process::id()))
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let config = setup::offscreen_config(800, 600);
+        let mut camera = Camera::new(
+            &config,
+            DVec3::new(1.5, -2., 0.25),
+            Vec3::new(-0.6, 0.8, 0.),
+        );
+        camera.up = Vec3::new(0., 0.6, 0.8);
+        camera.roll = 0.3;
+        camera.set_fov_y(70f32.to_radians());
+        camera.z_near = 0.01;
+        camera.z_far = 1000.;
+
+        let path = temp_path("camera-round-trip");
+        camera.save(&path).unwrap();
+        let loaded = Camera::load(&path, &config);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert!(loaded.position.distance(camera.position) < 1e-12);
+        assert!(loaded.look_dir.distance(camera.look_dir) < 1e-6);
+        assert!(loaded.up.distance(camera.up) < 1e-6);
+        assert!((loaded.roll - camera.roll).abs() < 1e-6);
+        assert!((loaded.fov_y().unwrap() - camera.fov_y().unwrap()).abs() < 1e-6);
+        assert_eq!((loaded.z_near, loaded.z_far), (camera.z_near, camera.z_far));
+        assert_eq!(loaded.aspect_ratio(), camera.aspect_ratio());
+    }
+
+    #[test]
+    fn load_normalizes_the_directions() {
+        let config = setup::offscreen_config(800, 600);
+        let mut camera = Camera::new(&config, DVec3::X, Vec3::new(-3., 0., 4.));
+        camera.up = Vec3::Z * 10.;
+
+        let path = temp_path("camera-normalize");
+        camera.save(&path).unwrap();
+        let loaded = Camera::load(&path, &config);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert!(loaded.look_dir.distance(Vec3::new(-0.6, 0., 0.8)) < 1e-6);
+        assert!(loaded.up.distance(Vec3::Z) < 1e-6);
+    }
+
+    #[test]
+    fn binding_a_too_small_uniform_fails_validation() {
+        let Some((device, _queue)) = setup::test_device() else {
+            return;
+        };
+        let layout = bind_group_layout(&device);
+        let bind = |size: u64| {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Test Camera Uniform Buffer"),
+                size,
+                usage: wgpu::BufferUsages::UNIFORM,
+                mapped_at_creation: false,
+            });
+            setup::validated(&device, || Ok(bind_group(&device, &layout, &buffer)))
+        };
+
+        let size = std::mem::size_of::<CameraUniform>() as u64;
+        assert!(bind(size).is_ok());
+        assert!(bind(size - 16).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+/// Playback controls of the simulation, consulted by the clock every frame
+pub struct SimState {
+    pub paused: bool,
+    /// simulated seconds per real second
+    pub speed: f64,
+    /// advances exactly one step on the next frame while paused
+    pub step_once: bool,
+}
+
+impl Default for SimState {
+    fn default() -> Self {
+        SimState {
+            paused: false,
+            speed: 1.,
+            step_once: false,
+        }
+    }
+}
+
+/// Fixed timestep clock for the simulation, independent of the frame rate. Every frame adds
+/// its duration and the simulation advances in whole steps of `1 / rate` seconds, the rest is
+/// carried over to the next frame. Rendering happens between the last two steps.
+pub struct SimulationClock {
+    /// duration of a step in seconds
+    step: f64,
+    /// simulation time after the last step
+    time: f64,
+    /// frame time not yet simulated, less than a step after `advance`
+    accumulator: f64,
+}
+
+impl SimulationClock {
+    /// Steps simulated per frame at normal speed at most. After a stall, e.g. while the window
+    /// is dragged, the simulation slows down instead of running many steps at once.
+    const MAX_STEPS: u32 = 8;
+
+    /// Clock taking `rate` steps per second
+    pub fn new(rate: f64) -> Self {
+        SimulationClock {
+            step: 1. / rate,
+            time: 0.,
+            accumulator: 0.,
+        }
+    }
+
+    /// Adds a frame of `dt` seconds scaled by the speed and returns the number of steps to
+    /// simulate for it. While paused the time stands still, except for a requested single step.
+    pub fn advance(&mut self, dt: f64, state: &mut SimState) -> u32 {
+        if state.paused {
+            let steps = std::mem::take(&mut state.step_once) as u32;
+            self.time += steps as f64 * self.step;
+            return steps;
+        }
+        state.step_once = false;
+
+        self.accumulator += dt * state.speed;
+        let max_steps = (Self::MAX_STEPS as f64 * state.speed.max(1.)).ceil() as u32;
+        let steps = ((self.accumulator / self.step) as u32).min(max_steps);
+        self.time += steps as f64 * self.step;
+        self.accumulator = match steps == max_steps {
+            true => self.accumulator.min(self.step),
+            false => self.accumulator - steps as f64 * self.step,
+        };
+        steps
+    }
+
+    /// Duration of a step in seconds
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Fraction of the next step that already passed, from 0 to 1
+    fn alpha(&self) -> f64 {
+        (self.accumulator / self.step).min(1.)
+    }
+
+    /// Time to render at, between the last two steps. Lags a step behind `advance`, so it only
+    /// interpolates between simulated states and never extrapolates.
+    pub fn interpolated_time(&self) -> f64 {
+        (self.time - self.step * (1. - self.alpha())).max(0.)
+    }
+}
@@ -5,5 +5,7 @@ pub use buffer::*;
 
 pub mod camera;
 
+pub mod clock;
+
 pub mod packed_vec3;
 pub use packed_vec3::*;
@@ -1,7 +1,7 @@
 use crate::utils::*;
 
 #[repr(C)]
-#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct PackedVec3 {
     data: u128,
 }
@@ -32,6 +32,23 @@ impl From<DVec3> for PackedVec3 {
     }
 }
 
+impl From<PackedVec3> for DVec3 {
+    fn from(value: PackedVec3) -> Self {
+        const SCALE: f64 = 16384.0;
+
+        // sign extends the lowest `bits` bits of `packed`
+        let unpack = |packed: u128, bits: u32| {
+            let shift = 64 - bits;
+            (((packed as u64) << shift) as i64 >> shift) as f64 / SCALE
+        };
+
+        let x = unpack(value.data >> (43 + 42), 43);
+        let y = unpack(value.data >> 42, 43);
+        let z = unpack(value.data, 42);
+        dvec3(x, y, z)
+    }
+}
+
 impl From<PackedVec3> for UVec4 {
     fn from(value: PackedVec3) -> Self {
         let bytes = value.data.to_ne_bytes();
@@ -0,0 +1,93 @@
+//! Renders fixed scenes headlessly and compares them with the images in `tests/reference`.
+//! They are rendered without MSAA, which differs the most between GPUs, and on GL if there is
+//! no PRIMARY adapter. Without any adapter the tests pass without rendering. Set
+//! `UPDATE_REFERENCES` to write the renders as the new references instead.
+
+use planet_placer::{config::AppConfig, headless::HeadlessOptions, planet, setup, utils::*};
+use std::path::{Path, PathBuf};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 180;
+/// Largest difference of a color channel from the reference, for the rounding of different GPUs
+const TOLERANCE: u8 = 8;
+
+fn app_config() -> AppConfig {
+    AppConfig {
+        seed: 7,
+        subdivisions: 4,
+        plates: 12,
+        width: WIDTH,
+        height: HEIGHT,
+        ..Default::default()
+    }
+}
+
+fn have_adapter() -> bool {
+    setup::offscreen_adapter(true)
+        .inspect_err(|err| setup::skip_test(err))
+        .is_ok()
+}
+
+fn temp_path(name: &str, extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{name}-{}.{extension}", std::process::id()))
+}
+
+/// Renders the planet of `app_config` from the camera at `camera_position`, or from the camera
+/// pose if there is one, and compares it with the reference `name`
+fn render_and_compare(name: &str, camera_position: DVec3, camera_pose: Option<PathBuf>) {
+    let app_config = app_config();
+    let planet_data = planet::PlanetData::generate(
+        app_config.seed,
+        &app_config.generation_params(),
+        &app_config.elevation_params(),
+    );
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/reference")
+        .join(format!("{name}.png"));
+    let update = std::env::var_os("UPDATE_REFERENCES").is_some();
+    let options = HeadlessOptions {
+        width: WIDTH,
+        height: HEIGHT,
+        camera_position,
+        camera_pose,
+        texture: None,
+        output: if update {
+            reference.clone()
+        } else {
+            temp_path(name, "png")
+        },
+        reference: (!update).then_some(reference),
+        tolerance: TOLERANCE,
+        sample_count: Some(1),
+        fallback_adapter: true,
+    };
+
+    let result = options.render(&app_config, planet_data);
+    if !update {
+        std::fs::remove_file(&options.output).ok();
+    }
+    result.unwrap();
+}
+
+#[test]
+fn background() {
+    if !have_adapter() {
+        return;
+    }
+    // away from the planet and the moon's orbit, only the stars are in view
+    let config = setup::offscreen_config(WIDTH, HEIGHT);
+    let mut camera = camera::Camera::new(&config, dvec3(0., 0., 3.), Vec3::Z);
+    camera.up = Vec3::Y;
+    let pose = temp_path("background-camera", "json");
+    camera.save(&pose).unwrap();
+    render_and_compare("background", camera.position, Some(pose.clone()));
+    std::fs::remove_file(&pose).unwrap();
+}
+
+#[test]
+fn planet() {
+    if !have_adapter() {
+        return;
+    }
+    render_and_compare("planet", dvec3(2.5, -1.5, 1.), None);
+}